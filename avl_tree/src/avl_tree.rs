@@ -1,19 +1,57 @@
-use std::cmp::{Ord, max};
+use std::cmp::{Ord, Ordering, max};
+use std::iter::Peekable;
 use std::mem;
-use std::ops::Not;
+use std::ops::{Bound, Not, RangeBounds};
 
-struct AVLNode<T: Ord> {
-    value: T,
-    left: Option<Box<AVLNode<T>>>,
-    right: Option<Box<AVLNode<T>>>,
+mod interval_tree;
+
+pub use interval_tree::*;
+
+/// 增强: 每个节点多存一份子树摘要, 比如子树里最大的右端点, value之和等
+///  摘要只由节点自己和两个孩子的摘要算出来, 所以和height/size一样在update_height里自底向上更新
+///  rotate/rebalance/join/split都会走update_height, 摘要自然就同步了
+///
+/// 摘要可以依赖value, 所以带增强的树不提供`get_mut`/`entry`这类能原地改value的接口
+pub trait Augment<K, V> {
+    type Summary;
+
+    fn summarize(key: &K, value: &V, left: Option<&Self::Summary>, right: Option<&Self::Summary>) -> Self::Summary;
+}
+
+/// 不增强
+impl<K, V> Augment<K, V> for () {
+    type Summary = ();
+
+    fn summarize(_: &K, _: &V, _: Option<&()>, _: Option<&()>) {}
+}
+
+struct AVLNode<K: Ord, V, A: Augment<K, V> = ()> {
+    key: K,
+    value: V,
+    left: Option<Box<AVLNode<K, V, A>>>,
+    right: Option<Box<AVLNode<K, V, A>>>,
     height: usize,
+    // 子树节点数, 和height一起在update_height中更新
+    size: usize,
+    // 子树摘要, 同上
+    summary: A::Summary,
+}
+
+/// 有序kv: 按key排序, 节点里多存一个value
+///  每个节点记录子树大小, 所以`len`, `nth`, `rank`都是O(log n)
+///  A是增强, 默认不增强, 见`Augment`
+pub struct AVLMap<K: Ord, V, A: Augment<K, V> = ()> {
+    root: Option<Box<AVLNode<K, V, A>>>,
 }
 
-struct AVLTree<T: Ord> {
-    root: Option<Box<AVLNode<T>>>,
-    length: usize,
+/// 有序集合: value为()的AVLMap
+pub struct AVLSet<T: Ord> {
+    map: AVLMap<T, ()>,
 }
 
+/// 原来的名字, 它一直是集合
+pub type AVLTree<T> = AVLSet<T>;
+
 #[derive(Clone, Copy)]
 enum Side {
     Left,
@@ -31,10 +69,22 @@ impl Not for Side {
     }
 }
 
-impl<T: Ord> AVLNode<T> {
+impl<K: Ord, V, A: Augment<K, V>> AVLNode<K, V, A> {
+    fn new(key: K, value: V) -> Box<Self> {
+        let summary = A::summarize(&key, &value, None, None);
+        Box::new(Self {
+            key,
+            value,
+            left: None,
+            right: None,
+            height: 1,
+            size: 1,
+            summary,
+        })
+    }
 
     // 平衡操作都是被dfs调用的, 所以我们可以只关系一层
-    pub fn rebalance(&mut self) {
+    fn rebalance(self: &mut Box<Self>) {
         // 重新计算高度
         self.update_height();
         let factor = self.balance_factor();
@@ -51,12 +101,13 @@ impl<T: Ord> AVLNode<T> {
         };
 
         let sub_factor = subtree.balance_factor();
+        // 子树和自己是反向的, 子树要先反着旋一次
         if factor < 0 && sub_factor > 0 {
-            // RL的情况
-            subtree.rotate(Side::Left);
-        } else if factor > 0 && sub_factor < 0 {
-            // LR的情况
+            // RL的情况: 右子树右旋
             subtree.rotate(Side::Right);
+        } else if factor > 0 && sub_factor < 0 {
+            // LR的情况: 左子树左旋
+            subtree.rotate(Side::Left);
         }
         self.rotate(side);
     }
@@ -66,35 +117,45 @@ impl<T: Ord> AVLNode<T> {
     ///  1. 孩子继承: 旋转方向的"反向子树"的"同向孩子"需要继承
     ///  2. 新根节点: 旋转方向的"反向子树"称为新根
     ///  3. 旧根成子树: 旧根成为新根的"同向孩子"
-    fn rotate(&mut self, side: Side) {
+    ///
+    /// 交换的是Box指针而不是节点内容, 所以节点插入后在堆上的位置不会变, entry可以放心借出value
+    fn rotate(self: &mut Box<Self>, side: Side) {
         let mut subtree = self.child_mut(!side).take().unwrap();
         let new_child = subtree.child_mut(side).take();
         *self.child_mut(!side) = new_child;
         self.update_height();
 
         // 根节点交换
-        mem::swap(self, subtree.as_mut());
+        mem::swap(self, &mut subtree);
 
         // 旧跟成子树
         *self.child_mut(side) = Some(subtree);
         self.update_height();
     }
 
-    fn child(&self, side: Side) -> &Option<Box<AVLNode<T>>>{
+    fn child(&self, side: Side) -> &Option<Box<AVLNode<K, V, A>>>{
         match side {
             Side::Left => &self.left,
             Side::Right => &self.right,
         }
     }
-    fn child_mut(&mut self, side: Side) -> &mut Option<Box<AVLNode<T>>>{
+    fn child_mut(&mut self, side: Side) -> &mut Option<Box<AVLNode<K, V, A>>>{
         match side {
             Side::Left => &mut self.left,
             Side::Right => &mut self.right,
         }
     }
 
+    /// 顺便更新子树大小和摘要: 树的形状变了都会走到这里
     fn update_height(&mut self) {
         self.height = 1 + max(self.height(Side::Left), self.height(Side::Right));
+        self.size = 1 + self.size(Side::Left) + self.size(Side::Right);
+        self.summary = A::summarize(
+            &self.key,
+            &self.value,
+            self.left.as_ref().map(|n| &n.summary),
+            self.right.as_ref().map(|n| &n.summary),
+        );
     }
 
     /// 计算l - r高度差
@@ -112,74 +173,88 @@ impl<T: Ord> AVLNode<T> {
     fn height(&self, side: Side) -> usize {
         self.child(side).as_ref().map_or(0, |n| n.height)
     }
+
+    /// 左/右子树的节点数
+    fn size(&self, side: Side) -> usize {
+        self.child(side).as_ref().map_or(0, |n| n.size)
+    }
 }
 
-impl<T: Ord> AVLTree<T> {
+impl<K: Ord, V, A: Augment<K, V>> AVLMap<K, V, A> {
     pub fn new() -> Self {
-        Self { root: None, length: 0 }
+        Self { root: None }
     }
-    pub fn insert(&mut self, value: T) -> bool {
-        let inserted = Self::tree_insert(&mut self.root, value);
-        if inserted {
-            self.length += 1;
-        }
-        inserted
+
+    /// key已存在时覆盖value, 返回旧value
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        Self::tree_insert(&mut self.root, AVLNode::new(key, value))
     }
 
-    fn tree_insert(tree: &mut Option<Box<AVLNode<T>>>, value: T) -> bool {
+    /// 返回None说明插入了新节点, 需要dfs平衡
+    ///  覆盖value时树的形状不变, 但摘要可能变了, 路径上也要更新
+    fn tree_insert(tree: &mut Option<Box<AVLNode<K, V, A>>>, new: Box<AVLNode<K, V, A>>) -> Option<V> {
         if let Some(node) = tree {
-            let inserted = match value.cmp(&node.value) {
-                std::cmp::Ordering::Less => Self::tree_insert(&mut node.left, value),
-                std::cmp::Ordering::Equal => false,
-                std::cmp::Ordering::Greater => Self::tree_insert(&mut node.right, value),
+            let old = match new.key.cmp(&node.key) {
+                std::cmp::Ordering::Less => Self::tree_insert(&mut node.left, new),
+                std::cmp::Ordering::Equal => Some(mem::replace(&mut node.value, new.value)),
+                std::cmp::Ordering::Greater => Self::tree_insert(&mut node.right, new),
             };
-            if inserted {
+            if old.is_none() {
                 node.rebalance();
+            } else {
+                node.update_height();
             }
-            inserted
+            old
         } else {
-            *tree = Some(Box::new(AVLNode {
-                value,
-                left: None,
-                right: None,
-                height: 1,
-            }));
-            true
+            *tree = Some(new);
+            None
         }
     }
 
     // 二叉搜索, 找相等
-    pub fn contains(&self, value: &T) -> bool {
+    fn find(&self, key: &K) -> Option<&AVLNode<K, V, A>> {
         let mut tree = &self.root;
         while let Some(node) = tree {
-            tree = match value.cmp(&node.value) {
+            tree = match key.cmp(&node.key) {
                 std::cmp::Ordering::Less => &node.left,
                 std::cmp::Ordering::Greater => &node.right,
                 // 找到
-                std::cmp::Ordering::Equal => return true,
+                std::cmp::Ordering::Equal => return Some(node),
             }
         }
-        false
+        None
     }
 
-    pub fn remove(&mut self, value: &T) -> bool {
-        let removed = Self::tree_remove(&mut self.root, value);
-        if removed {
-            self.length -= 1;
-        }
-        removed
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find(key).map(|n| &n.value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// 整棵树的摘要, 空树为None
+    pub fn summary(&self) -> Option<&A::Summary> {
+        self.root.as_ref().map(|n| &n.summary)
+    }
+
+    /// 返回被删除的value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        Self::tree_remove(&mut self.root, key)
     }
 
-    // 删除value对应节点
+    // 删除key对应节点
     //  1. 如果是非叶子节点则需要考虑孩子的领养问题: merge
     //  2. 如果节点存在且产生了删除, 需要考虑dfs做平衡
-    fn tree_remove(tree: &mut Option<Box<AVLNode<T>>>, value: &T) -> bool {
+    fn tree_remove(tree: &mut Option<Box<AVLNode<K, V, A>>>, key: &K) -> Option<V> {
         if let Some(node) = tree {
-            let removed = match value.cmp(&node.value) {
+            let removed = match key.cmp(&node.key) {
                 // 如果待删节点大, 则递归查找右子树
-                std::cmp::Ordering::Less => Self::tree_remove(&mut node.left, value),
-                std::cmp::Ordering::Greater => Self::tree_remove(&mut node.right, value),
+                std::cmp::Ordering::Less => Self::tree_remove(&mut node.left, key),
+                std::cmp::Ordering::Greater => Self::tree_remove(&mut node.right, key),
                 std::cmp::Ordering::Equal => {
+                    // 拿到节点所有权, 才能把value带出去
+                    let mut node = tree.take().unwrap();
                     // 判断是否存在左右孩子需要领养
                     *tree = match (node.left.take(), node.right.take()) {
                         // 不需要领养: 直接删除当前节点
@@ -188,25 +263,186 @@ impl<T: Ord> AVLTree<T> {
                         (None, Some(n)) | (Some(n), None) => Some(n),
                         (Some(l), Some(r)) => Some(Self::merge(l, r)),
                     };
-                    return true;
+                    return Some(node.value);
                 }
             };
-            if removed {
+            if removed.is_some() {
                 node.rebalance();
             }
             removed
         } else {
-            false
+            None
         }
     }
 
-    fn merge(left: Box<AVLNode<T>>, right: Box<AVLNode<T>>) -> Box<AVLNode<T>> {
+    /// 合并两棵树, left中所有key都比right小
+    ///  取右子树的最小节点做新根, 再按高度join
+    ///  删除时两边高度最多差1, 直接接上就行; split/append时高度可能差很多, 所以要join
+    fn merge(left: Box<AVLNode<K, V, A>>, right: Box<AVLNode<K, V, A>>) -> Box<AVLNode<K, V, A>> {
         let mut new_right = Some(right);
-        let mut new_root = Self::take_min(&mut new_right).unwrap();
-        new_root.left = Some(left);
-        new_root.right = new_right;
-        new_root.rebalance();
-        new_root
+        let new_root = Self::take_min(&mut new_right).unwrap();
+        Self::join_node(Some(left), new_root, new_right)
+    }
+
+    /// 同merge, 但两边都可能是空树
+    fn merge_opt(left: Option<Box<AVLNode<K, V, A>>>, right: Option<Box<AVLNode<K, V, A>>>) -> Option<Box<AVLNode<K, V, A>>> {
+        match (left, right) {
+            (None, tree) | (tree, None) => tree,
+            (Some(l), Some(r)) => Some(Self::merge(l, r)),
+        }
+    }
+
+    /// ⭐按高度join: left < mid < right, mid是单独的节点
+    ///  两边高度差不超过1: mid直接做根
+    ///  否则沿高的那棵树的内侧往下走, 直到高度和矮的差不多, 在那里用mid接上, 一路rebalance回来
+    ///  复杂度O(|h(left) - h(right)| + 1)
+    fn join_node(left: Option<Box<AVLNode<K, V, A>>>, mut mid: Box<AVLNode<K, V, A>>, right: Option<Box<AVLNode<K, V, A>>>) -> Box<AVLNode<K, V, A>> {
+        let lh = left.as_ref().map_or(0, |n| n.height);
+        let rh = right.as_ref().map_or(0, |n| n.height);
+        if lh > rh + 1 {
+            let mut left = left.unwrap();
+            let inner = left.right.take();
+            left.right = Some(Self::join_node(inner, mid, right));
+            left.rebalance();
+            left
+        } else if rh > lh + 1 {
+            let mut right = right.unwrap();
+            let inner = right.left.take();
+            right.left = Some(Self::join_node(left, mid, inner));
+            right.rebalance();
+            right
+        } else {
+            mid.left = left;
+            mid.right = right;
+            mid.rebalance();
+            mid
+        }
+    }
+
+    /// 按key把树切成两半: (< key, == key的节点, > key)
+    ///  沿查找路径往下, 回来时把路径上的节点和另一侧的子树join到对应的一半
+    ///  每一层join的代价是高度差, 加起来是O(log n)
+    #[allow(clippy::type_complexity)]
+    fn split(tree: Option<Box<AVLNode<K, V, A>>>, key: &K)
+        -> (Option<Box<AVLNode<K, V, A>>>, Option<Box<AVLNode<K, V, A>>>, Option<Box<AVLNode<K, V, A>>>)
+    {
+        let Some(mut node) = tree else {
+            return (None, None, None);
+        };
+        let (left, right) = (node.left.take(), node.right.take());
+        match key.cmp(&node.key) {
+            std::cmp::Ordering::Less => {
+                let (l, found, r) = Self::split(left, key);
+                (l, found, Some(Self::join_node(r, node, right)))
+            },
+            std::cmp::Ordering::Equal => {
+                node.update_height();
+                (left, Some(node), right)
+            },
+            std::cmp::Ordering::Greater => {
+                let (l, found, r) = Self::split(right, key);
+                (Some(Self::join_node(left, node, l)), found, r)
+            },
+        }
+    }
+
+    /// ⭐以下集合运算都是同一个套路: 拆下t1的根k, 用k把t2切开, 两边递归, 最后按k在不在结果中join或merge
+    ///  每层的split/join都是O(log n), 总共O(m log(n/m + 1)), m是小的那棵
+    ///  key相同时保留t1的节点
+    fn union_nodes(t1: Option<Box<AVLNode<K, V, A>>>, t2: Option<Box<AVLNode<K, V, A>>>) -> Option<Box<AVLNode<K, V, A>>> {
+        let Some(mut root) = t1 else { return t2 };
+        if t2.is_none() {
+            return Some(root);
+        }
+        let (l1, r1) = (root.left.take(), root.right.take());
+        let (l2, _, r2) = Self::split(t2, &root.key);
+        let l = Self::union_nodes(l1, l2);
+        let r = Self::union_nodes(r1, r2);
+        Some(Self::join_node(l, root, r))
+    }
+
+    fn intersection_nodes(t1: Option<Box<AVLNode<K, V, A>>>, t2: Option<Box<AVLNode<K, V, A>>>) -> Option<Box<AVLNode<K, V, A>>> {
+        let (Some(mut root), Some(t2)) = (t1, t2) else { return None };
+        let (l1, r1) = (root.left.take(), root.right.take());
+        let (l2, found, r2) = Self::split(Some(t2), &root.key);
+        let l = Self::intersection_nodes(l1, l2);
+        let r = Self::intersection_nodes(r1, r2);
+        match found {
+            Some(_) => Some(Self::join_node(l, root, r)),
+            None => Self::merge_opt(l, r),
+        }
+    }
+
+    /// t1 - t2: 反过来拆t2的根, 切开t1
+    fn difference_nodes(t1: Option<Box<AVLNode<K, V, A>>>, t2: Option<Box<AVLNode<K, V, A>>>) -> Option<Box<AVLNode<K, V, A>>> {
+        let Some(mut root) = t2 else { return t1 };
+        t1.as_ref()?;
+        let (l2, r2) = (root.left.take(), root.right.take());
+        let (l1, _, r1) = Self::split(t1, &root.key);
+        let l = Self::difference_nodes(l1, l2);
+        let r = Self::difference_nodes(r1, r2);
+        Self::merge_opt(l, r)
+    }
+
+    fn symmetric_difference_nodes(t1: Option<Box<AVLNode<K, V, A>>>, t2: Option<Box<AVLNode<K, V, A>>>) -> Option<Box<AVLNode<K, V, A>>> {
+        let Some(mut root) = t1 else { return t2 };
+        if t2.is_none() {
+            return Some(root);
+        }
+        let (l1, r1) = (root.left.take(), root.right.take());
+        let (l2, found, r2) = Self::split(t2, &root.key);
+        let l = Self::symmetric_difference_nodes(l1, l2);
+        let r = Self::symmetric_difference_nodes(r1, r2);
+        match found {
+            Some(_) => Self::merge_opt(l, r),
+            None => Some(Self::join_node(l, root, r)),
+        }
+    }
+
+    /// 同`BTreeMap::split_off`: 返回所有>= key的kv, 自己只留下< key的
+    pub fn split_off(&mut self, key: &K) -> Self {
+        let (left, found, right) = Self::split(self.root.take(), key);
+        self.root = left;
+        let right = match found {
+            Some(node) => Self::join_node(None, node, right),
+            None => match right {
+                Some(right) => right,
+                None => return Self::new(),
+            },
+        };
+        Self { root: Some(right) }
+    }
+
+    /// 拼接: 要求self中所有key都比other小, O(log n)
+    ///
+    /// # Panics
+    ///
+    /// key的范围有重叠时panic, 见`append`
+    pub fn join(mut self, mut other: Self) -> Self {
+        if let (Some((a, _)), Some((b, _))) = (self.last(), other.first()) {
+            assert!(a < b, "AVLMap::join: key ranges overlap");
+        }
+        self.root = Self::merge_opt(self.root.take(), other.root.take());
+        self
+    }
+
+    /// 同`BTreeMap::append`: 把other的所有kv移进来, other变为空, key相同时用other的value
+    ///  key的范围不重叠时(整个other在self的一边)直接join, O(log n)
+    ///  重叠时只能一个个插入, O(m log(n + m))
+    pub fn append(&mut self, other: &mut Self) {
+        let (left, right) = match (self.last(), other.first(), self.first(), other.last()) {
+            (_, None, _, _) => return,
+            (Some((a, _)), Some((b, _)), _, _) if a < b => (self.root.take(), other.root.take()),
+            (_, _, Some((a, _)), Some((b, _))) if b < a => (other.root.take(), self.root.take()),
+            (None, _, _, _) => (None, other.root.take()),
+            _ => {
+                while let Some((k, v)) = other.pop_first() {
+                    self.insert(k, v);
+                }
+                return;
+            },
+        };
+        self.root = Self::merge_opt(left, right);
     }
 
 
@@ -214,7 +450,7 @@ impl<T: Ord> AVLTree<T> {
     ///  找左子树的最左节点
     ///  如果左子树不存在, 则当前节点就行最小, 树根变为右节点
     ///  同理remove, take掉后相当于删除, 需要dfs平衡
-    pub fn take_min(tree: &mut Option<Box<AVLNode<T>>>) -> Option<Box<AVLNode<T>>>{
+    fn take_min(tree: &mut Option<Box<AVLNode<K, V, A>>>) -> Option<Box<AVLNode<K, V, A>>>{
         if let Some(mut node) = tree.take() {
             if let Some(small) = Self::take_min(&mut node.left) {
                 // 尝试从左子树找
@@ -233,52 +469,553 @@ impl<T: Ord> AVLTree<T> {
         }
     }
 
-    /// 先序遍历预处理: dfs到底, 左节点压栈
+    /// 同take_min, 最右(大)节点
+    fn take_max(tree: &mut Option<Box<AVLNode<K, V, A>>>) -> Option<Box<AVLNode<K, V, A>>>{
+        if let Some(mut node) = tree.take() {
+            if let Some(big) = Self::take_max(&mut node.right) {
+                node.rebalance();
+                *tree = Some(node);
+                Some(big)
+            } else {
+                *tree = node.left.take();
+                Some(node)
+            }
+        } else {
+            None
+        }
+    }
+
+    /// 中序遍历预处理: 两头各一个栈, 前面dfs到最左, 后面dfs到最右
     /// Returns an iterator that visits the nodes in the tree in order.
-    fn node_iter(&self) -> NodeIter<T> {
+    fn node_iter(&self) -> NodeIter<'_, K, V, A> {
+        self.node_range(..)
+    }
+
+    /// 区间的两个端点各自二叉搜索一次, 路径上在区间内的节点压栈
+    ///  剩余个数用count_range算出来, 两头走到一起时正好为0, 不用比较两个栈
+    fn node_range<R: RangeBounds<K>>(&self, range: R) -> NodeIter<'_, K, V, A> {
         let cap = self.root.as_ref().map_or(0, |n| n.height);
         let mut node_iter = NodeIter {
-            stack: Vec::with_capacity(cap),
+            front: Vec::with_capacity(cap),
+            back: Vec::with_capacity(cap),
+            len: self.count_range((range.start_bound(), range.end_bound())),
         };
 
-        // Initialize stack with path to leftmost child
+        // 前栈: 不小于起点的往左走并压栈, 否则往右走
         let mut child = &self.root;
         while let Some(node) = child {
-            node_iter.stack.push(node.as_ref());
-            child = &node.left;
+            let after_start = match range.start_bound() {
+                Bound::Included(start) => &node.key >= start,
+                Bound::Excluded(start) => &node.key > start,
+                Bound::Unbounded => true,
+            };
+            if after_start {
+                node_iter.front.push(node.as_ref());
+                child = &node.left;
+            } else {
+                child = &node.right;
+            }
+        }
+
+        // 后栈: 对称
+        let mut child = &self.root;
+        while let Some(node) = child {
+            let before_end = match range.end_bound() {
+                Bound::Included(end) => &node.key <= end,
+                Bound::Excluded(end) => &node.key < end,
+                Bound::Unbounded => true,
+            };
+            if before_end {
+                node_iter.back.push(node.as_ref());
+                child = &node.right;
+            } else {
+                child = &node.left;
+            }
         }
         node_iter
     }
 
     /// 迭代器转换
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, K, V, A> {
         Iter {
             node_iter: self.node_iter(),
         }
     }
 
+    /// 区间迭代, 两头都能走
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V, A> {
+        Iter {
+            node_iter: self.node_range(range),
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V, A> {
+        Keys { iter: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V, A> {
+        Values { iter: self.iter() }
+    }
+
+    /// Returns the number of values in the tree.
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |n| n.size)
+    }
+
+    /// Returns `true` if the tree contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// 第k小(从0开始)的kv
+    ///  左子树有l个: k < l往左找, k == l就是当前节点, 否则往右找第k - l - 1个
+    pub fn nth(&self, mut k: usize) -> Option<(&K, &V)> {
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            let l = node.size(Side::Left);
+            tree = match k.cmp(&l) {
+                std::cmp::Ordering::Less => &node.left,
+                std::cmp::Ordering::Equal => return Some((&node.key, &node.value)),
+                std::cmp::Ordering::Greater => {
+                    k -= l + 1;
+                    &node.right
+                },
+            }
+        }
+        None
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.iter().next_back()
+    }
+
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        Self::take_min(&mut self.root).map(|n| (n.key, n.value))
+    }
+
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        Self::take_max(&mut self.root).map(|n| (n.key, n.value))
+    }
+
+    /// 小于等于key的最大kv
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        self.lower(key, true)
+    }
+
+    /// 大于等于key的最小kv
+    pub fn ceil(&self, key: &K) -> Option<(&K, &V)> {
+        self.upper(key, true)
+    }
+
+    /// 小于key的最大kv, key不必存在
+    pub fn predecessor(&self, key: &K) -> Option<(&K, &V)> {
+        self.lower(key, false)
+    }
+
+    /// 大于key的最小kv, key不必存在
+    pub fn successor(&self, key: &K) -> Option<(&K, &V)> {
+        self.upper(key, false)
+    }
+
+    /// 满足条件就记下来再往右找更大的, 否则往左
+    fn lower(&self, key: &K, inclusive: bool) -> Option<(&K, &V)> {
+        let mut found = None;
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            if node.key < *key || (inclusive && node.key == *key) {
+                found = Some((&node.key, &node.value));
+                tree = &node.right;
+            } else {
+                tree = &node.left;
+            }
+        }
+        found
+    }
+
+    /// 同lower, 方向反过来
+    fn upper(&self, key: &K, inclusive: bool) -> Option<(&K, &V)> {
+        let mut found = None;
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            if node.key > *key || (inclusive && node.key == *key) {
+                found = Some((&node.key, &node.value));
+                tree = &node.left;
+            } else {
+                tree = &node.right;
+            }
+        }
+        found
+    }
+
+    /// 小于key的个数, key存在时就是它的下标
+    pub fn rank(&self, key: &K) -> usize {
+        self.count_less(key, false)
+    }
+
+    /// 区间内的个数, 两端各算一次rank
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.count_less(start, false),
+            Bound::Excluded(start) => self.count_less(start, true),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.count_less(end, true),
+            Bound::Excluded(end) => self.count_less(end, false),
+            Bound::Unbounded => self.len(),
+        };
+        end.saturating_sub(start)
+    }
+
+    /// 小于(inclusive时小于等于)key的个数
+    ///  往右走时, 左子树和当前节点都比key小
+    fn count_less(&self, key: &K, inclusive: bool) -> usize {
+        let mut count = 0;
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            let go_right = match key.cmp(&node.key) {
+                std::cmp::Ordering::Less => false,
+                std::cmp::Ordering::Equal => inclusive,
+                std::cmp::Ordering::Greater => true,
+            };
+            tree = if go_right {
+                count += node.size(Side::Left) + 1;
+                &node.right
+            } else {
+                &node.left
+            };
+        }
+        count
+    }
+
+    /// 检查结构, 出错时返回描述, 摘要没法比较就不查了
+    ///  1. 每个节点的height和size等于重新算出来的
+    ///  2. 左右子树高度差不超过1
+    ///  3. 中序有序
+    pub fn check_invariants(&self) -> Result<(), String> {
+        Self::check_node(&self.root)?;
+        if self.keys().zip(self.keys().skip(1)).any(|(a, b)| a >= b) {
+            return Err("keys out of order".to_string());
+        }
+        Ok(())
+    }
+
+    /// 返回子树的(高度, 节点数)
+    fn check_node(tree: &Option<Box<AVLNode<K, V, A>>>) -> Result<(usize, usize), String> {
+        let Some(node) = tree else { return Ok((0, 0)) };
+        let (left_height, left_size) = Self::check_node(&node.left)?;
+        let (right_height, right_size) = Self::check_node(&node.right)?;
+        if left_height.abs_diff(right_height) > 1 {
+            return Err(format!("unbalanced node: left height {}, right height {}", left_height, right_height));
+        }
+        if node.height != 1 + max(left_height, right_height) {
+            return Err(format!("height {} != {}", node.height, 1 + max(left_height, right_height)));
+        }
+        if node.size != 1 + left_size + right_size {
+            return Err(format!("size {} != {}", node.size, 1 + left_size + right_size));
+        }
+        Ok((node.height, node.size))
+    }
+}
+
+/// 能原地改value的接口: 只给不带增强的树
+impl<K: Ord, V> AVLMap<K, V> {
+    fn find_mut(&mut self, key: &K) -> Option<&mut AVLNode<K, V>> {
+        let mut tree = &mut self.root;
+        while let Some(node) = tree {
+            tree = match key.cmp(&node.key) {
+                std::cmp::Ordering::Less => &mut node.left,
+                std::cmp::Ordering::Greater => &mut node.right,
+                std::cmp::Ordering::Equal => return Some(node),
+            }
+        }
+        None
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.find_mut(key).map(|n| &mut n.value)
+    }
+
+    /// 同`HashMap::entry`
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        if self.contains_key(&key) {
+            Entry::Occupied(OccupiedEntry { map: self, key })
+        } else {
+            Entry::Vacant(VacantEntry { map: self, key })
+        }
+    }
+}
+
+impl<T: Ord> AVLSet<T> {
+    pub fn new() -> Self {
+        Self { map: AVLMap::new() }
+    }
+
+    /// 已存在时返回false, 不会替换原来的元素
+    pub fn insert(&mut self, value: T) -> bool {
+        if self.map.contains_key(&value) {
+            return false;
+        }
+        self.map.insert(value, ());
+        true
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.map.contains_key(value)
+    }
+
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.map.remove(value).is_some()
+    }
+
+    pub fn iter(&self) -> Keys<'_, T, ()> {
+        self.map.keys()
+    }
+
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Keys<'_, T, ()> {
+        Keys { iter: self.map.range(range) }
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.map.first().map(|(k, _)| k)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.map.last().map(|(k, _)| k)
+    }
+
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.map.pop_first().map(|(k, _)| k)
+    }
+
+    pub fn pop_last(&mut self) -> Option<T> {
+        self.map.pop_last().map(|(k, _)| k)
+    }
+
+    pub fn floor(&self, value: &T) -> Option<&T> {
+        self.map.floor(value).map(|(k, _)| k)
+    }
+
+    pub fn ceil(&self, value: &T) -> Option<&T> {
+        self.map.ceil(value).map(|(k, _)| k)
+    }
+
+    pub fn predecessor(&self, value: &T) -> Option<&T> {
+        self.map.predecessor(value).map(|(k, _)| k)
+    }
+
+    pub fn successor(&self, value: &T) -> Option<&T> {
+        self.map.successor(value).map(|(k, _)| k)
+    }
+
     /// Returns the number of values in the tree.
     pub fn len(&self) -> usize {
-        self.length
+        self.map.len()
     }
 
     /// Returns `true` if the tree contains no values.
     pub fn is_empty(&self) -> bool {
-        self.length == 0
+        self.map.is_empty()
+    }
+
+    /// 第k小(从0开始)的元素
+    pub fn nth(&self, k: usize) -> Option<&T> {
+        self.map.nth(k).map(|(k, _)| k)
+    }
+
+    pub fn check_invariants(&self) -> Result<(), String> {
+        self.map.check_invariants()
+    }
+
+    /// 小于value的个数
+    pub fn rank(&self, value: &T) -> usize {
+        self.map.rank(value)
+    }
+
+    pub fn count_range<R: RangeBounds<T>>(&self, range: R) -> usize {
+        self.map.count_range(range)
+    }
+
+    /// 返回所有>= value的元素
+    pub fn split_off(&mut self, value: &T) -> Self {
+        Self { map: self.map.split_off(value) }
+    }
+
+    /// 要求self中所有元素都比other小
+    pub fn join(self, other: Self) -> Self {
+        Self { map: self.map.join(other.map) }
+    }
+
+    pub fn append(&mut self, other: &mut Self) {
+        self.map.append(&mut other.map);
+    }
+
+    /// 以下四个是惰性的, 同`BTreeSet`: 两个有序迭代器归并
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, T> {
+        Union { iter: MergeIter::new(self, other) }
+    }
+
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, T> {
+        Intersection { iter: MergeIter::new(self, other) }
+    }
+
+    pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, T> {
+        Difference { iter: MergeIter::new(self, other) }
+    }
+
+    pub fn symmetric_difference<'a>(&'a self, other: &'a Self) -> SymmetricDifference<'a, T> {
+        SymmetricDifference { iter: MergeIter::new(self, other) }
+    }
+
+    /// 以下四个拿走两棵树, 用split/join直接拼出平衡的结果, O(m log(n/m + 1))
+    pub fn into_union(self, other: Self) -> Self {
+        Self { map: AVLMap { root: AVLMap::union_nodes(self.map.root, other.map.root) } }
+    }
+
+    pub fn into_intersection(self, other: Self) -> Self {
+        Self { map: AVLMap { root: AVLMap::intersection_nodes(self.map.root, other.map.root) } }
+    }
+
+    pub fn into_difference(self, other: Self) -> Self {
+        Self { map: AVLMap { root: AVLMap::difference_nodes(self.map.root, other.map.root) } }
+    }
+
+    pub fn into_symmetric_difference(self, other: Self) -> Self {
+        Self { map: AVLMap { root: AVLMap::symmetric_difference_nodes(self.map.root, other.map.root) } }
+    }
+}
+
+/// 同`HashMap::Entry`
+pub enum Entry<'a, K: Ord, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+/// 节点是Box, 借着节点就不能再旋转, 所以entry只记住key, 用到时再找一次
+pub struct OccupiedEntry<'a, K: Ord, V> {
+    map: &'a mut AVLMap<K, V>,
+    key: K,
+}
+
+pub struct VacantEntry<'a, K: Ord, V> {
+    map: &'a mut AVLMap<K, V>,
+    key: K,
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V
+        where K: Clone
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V
+        where K: Clone
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+        where K: Clone, V: Default
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(e) = &mut self {
+            f(e.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Ord, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        self.map.get(&self.key).unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.map.get_mut(&self.key).unwrap()
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.map.get_mut(&self.key).unwrap()
+    }
+
+    /// 覆盖value, 返回旧value
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// 删除节点, 返回value
+    pub fn remove(self) -> V {
+        self.map.remove(&self.key).unwrap()
     }
 }
 
+impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// 插入后dfs平衡会旋转, 路径变了, 只能拿key再查一遍
+    ///  key被移进了节点, 所以要先clone一份
+    pub fn insert(self, value: V) -> &'a mut V
+        where K: Clone
+    {
+        AVLMap::tree_insert(&mut self.map.root, AVLNode::new(self.key.clone(), value));
+        self.map.get_mut(&self.key).unwrap()
+    }
+}
+
+
+impl<K: Ord, V, A: Augment<K, V>> Default for AVLMap<K, V, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-impl<T: Ord> Default for AVLTree<T> {
+impl<T: Ord> Default for AVLSet<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
 /// 迭代器转AVL树
-impl<T: Ord> FromIterator<T> for AVLTree<T> {
+impl<K: Ord, V, A: Augment<K, V>> FromIterator<(K, V)> for AVLMap<K, V, A> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = AVLMap::new();
+        for (key, value) in iter {
+            tree.insert(key, value);
+        }
+        tree
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AVLSet<T> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let mut tree = AVLTree::new();
+        let mut tree = AVLSet::new();
         for value in iter {
             tree.insert(value);
         }
@@ -286,59 +1023,253 @@ impl<T: Ord> FromIterator<T> for AVLTree<T> {
     }
 }
 
-/// An iterator over the nodes of an `AVLTree`.
+impl<'a, K: Ord, V, A: Augment<K, V>> IntoIterator for &'a AVLMap<K, V, A> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T: Ord> IntoIterator for &'a AVLSet<T> {
+    type Item = &'a T;
+    type IntoIter = Keys<'a, T, ()>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the nodes of an `AVLMap`.
 ///
-/// This struct is created by the `node_iter` method of `AVLTree`.
-struct NodeIter<'a, T: Ord> {
-    stack: Vec<&'a AVLNode<T>>,
+/// This struct is created by the `node_iter` method of `AVLMap`.
+struct NodeIter<'a, K: Ord, V, A: Augment<K, V> = ()> {
+    front: Vec<&'a AVLNode<K, V, A>>,
+    back: Vec<&'a AVLNode<K, V, A>>,
+    // 还剩几个, 为0时两个栈里剩下的都是另一头已经走过的
+    len: usize,
 }
 
-impl<'a, T: Ord> Iterator for NodeIter<'a, T> {
-    type Item = &'a AVLNode<T>;
+impl<'a, K: Ord, V, A: Augment<K, V>> Iterator for NodeIter<'a, K, V, A> {
+    type Item = &'a AVLNode<K, V, A>;
 
-    /// 先序遍历
+    /// 中序遍历
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(node) = self.stack.pop() {
-            // Push left path of right subtree to stack
-            let mut child = &node.right;
-            while let Some(subtree) = child {
-                self.stack.push(subtree.as_ref());
-                child = &subtree.left;
-            }
-            Some(node)
-        } else {
-            None
+        if self.len == 0 {
+            return None;
         }
+        self.len -= 1;
+        let node = self.front.pop().unwrap();
+        // Push left path of right subtree to stack
+        let mut child = &node.right;
+        while let Some(subtree) = child {
+            self.front.push(subtree.as_ref());
+            child = &subtree.left;
+        }
+        Some(node)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
     }
 }
 
-/// An iterator over the items of an `AVLTree`.
+impl<K: Ord, V, A: Augment<K, V>> DoubleEndedIterator for NodeIter<'_, K, V, A> {
+    /// 反向中序遍历: 左右对调
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        let node = self.back.pop().unwrap();
+        let mut child = &node.left;
+        while let Some(subtree) = child {
+            self.back.push(subtree.as_ref());
+            child = &subtree.right;
+        }
+        Some(node)
+    }
+}
+
+/// An iterator over the entries of an `AVLMap`.
 ///
-/// This struct is created by the `iter` method of `AVLTree`.
-pub struct Iter<'a, T: Ord> {
-    node_iter: NodeIter<'a, T>,
+/// This struct is created by the `iter` and `range` methods of `AVLMap`.
+pub struct Iter<'a, K: Ord, V, A: Augment<K, V> = ()> {
+    node_iter: NodeIter<'a, K, V, A>,
+}
+
+impl<'a, K: Ord, V, A: Augment<K, V>> Iterator for Iter<'a, K, V, A> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.node_iter.next().map(|node| (&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_iter.size_hint()
+    }
+}
+
+impl<K: Ord, V, A: Augment<K, V>> DoubleEndedIterator for Iter<'_, K, V, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.node_iter.next_back().map(|node| (&node.key, &node.value))
+    }
+}
+
+impl<K: Ord, V, A: Augment<K, V>> ExactSizeIterator for Iter<'_, K, V, A> {}
+
+/// `AVLSet::iter`也是它
+pub struct Keys<'a, K: Ord, V, A: Augment<K, V> = ()> {
+    iter: Iter<'a, K, V, A>,
+}
+
+impl<'a, K: Ord, V, A: Augment<K, V>> Iterator for Keys<'a, K, V, A> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.iter.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K: Ord, V, A: Augment<K, V>> DoubleEndedIterator for Keys<'_, K, V, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<K: Ord, V, A: Augment<K, V>> ExactSizeIterator for Keys<'_, K, V, A> {}
+
+pub struct Values<'a, K: Ord, V, A: Augment<K, V> = ()> {
+    iter: Iter<'a, K, V, A>,
+}
+
+impl<'a, K: Ord, V, A: Augment<K, V>> Iterator for Values<'a, K, V, A> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.iter.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K: Ord, V, A: Augment<K, V>> DoubleEndedIterator for Values<'_, K, V, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(_, v)| v)
+    }
+}
+
+impl<K: Ord, V, A: Augment<K, V>> ExactSizeIterator for Values<'_, K, V, A> {}
+
+/// 归并两个有序集合, 每次吐出(a中的, b中的), 相等时两个都有
+struct MergeIter<'a, T: Ord> {
+    a: Peekable<Keys<'a, T, ()>>,
+    b: Peekable<Keys<'a, T, ()>>,
+}
+
+impl<'a, T: Ord> MergeIter<'a, T> {
+    fn new(a: &'a AVLSet<T>, b: &'a AVLSet<T>) -> Self {
+        Self { a: a.iter().peekable(), b: b.iter().peekable() }
+    }
+}
+
+impl<'a, T: Ord> Iterator for MergeIter<'a, T> {
+    type Item = (Option<&'a T>, Option<&'a T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let order = match (self.a.peek(), self.b.peek()) {
+            (None, None) => return None,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(a), Some(b)) => a.cmp(b),
+        };
+        Some(match order {
+            Ordering::Less => (self.a.next(), None),
+            Ordering::Equal => (self.a.next(), self.b.next()),
+            Ordering::Greater => (None, self.b.next()),
+        })
+    }
 }
 
-impl<'a, T: Ord> Iterator for Iter<'a, T> {
+pub struct Union<'a, T: Ord> {
+    iter: MergeIter<'a, T>,
+}
+
+impl<'a, T: Ord> Iterator for Union<'a, T> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<&'a T> {
-        match self.node_iter.next() {
-            Some(node) => Some(&node.value),
-            None => None,
-        }
+        self.iter.next().and_then(|(a, b)| a.or(b))
+    }
+}
+
+pub struct Intersection<'a, T: Ord> {
+    iter: MergeIter<'a, T>,
+}
+
+impl<'a, T: Ord> Iterator for Intersection<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.iter.find_map(|pair| match pair {
+            (Some(a), Some(_)) => Some(a),
+            _ => None,
+        })
+    }
+}
+
+pub struct Difference<'a, T: Ord> {
+    iter: MergeIter<'a, T>,
+}
+
+impl<'a, T: Ord> Iterator for Difference<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.iter.find_map(|pair| match pair {
+            (Some(a), None) => Some(a),
+            _ => None,
+        })
+    }
+}
+
+pub struct SymmetricDifference<'a, T: Ord> {
+    iter: MergeIter<'a, T>,
+}
+
+impl<'a, T: Ord> Iterator for SymmetricDifference<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.iter.find_map(|pair| match pair {
+            (Some(x), None) | (None, Some(x)) => Some(x),
+            _ => None,
+        })
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use super::AVLTree;
+    use super::{AVLMap, AVLTree, Augment, Entry, Side};
+    use std::collections::{BTreeMap, BTreeSet};
 
     /// Returns `true` if all nodes in the tree are balanced.
     fn is_balanced<T: Ord>(tree: &AVLTree<T>) -> bool {
-        tree.node_iter()
-            .all(|n| (-1..=1).contains(&n.balance_factor()))
+        map_is_balanced(&tree.map)
+    }
+
+    /// 顺便检查子树大小
+    fn map_is_balanced<K: Ord, V, A: Augment<K, V>>(map: &AVLMap<K, V, A>) -> bool {
+        map.check_invariants().is_ok() && map.node_iter()
+            .all(|n| (-1..=1).contains(&n.balance_factor()) && n.size == 1 + n.size(Side::Left) + n.size(Side::Right))
     }
 
     #[test]
@@ -375,7 +1306,7 @@ mod tests {
     #[test]
     fn sorted() {
         let tree: AVLTree<_> = (1..8).rev().collect();
-        assert!((1..8).eq(tree.iter().map(|&x| x)));
+        assert!((1..8).eq(tree.iter().copied()));
     }
 
     #[test]
@@ -387,4 +1318,328 @@ mod tests {
             assert!(is_balanced(&tree));
         }
     }
+
+    #[test]
+    fn map_insert_get() {
+        let mut map = AVLMap::new();
+        assert_eq!(map.insert("b", 2), None);
+        assert_eq!(map.insert("a", 1), None);
+        assert_eq!(map.insert("b", 3), Some(2));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&"b"), Some(&3));
+        assert_eq!(map.get(&"c"), None);
+        *map.get_mut(&"a").unwrap() += 10;
+        assert!(map.iter().eq([(&"a", &11), (&"b", &3)]));
+        assert!(map.keys().eq(&["a", "b"]));
+        assert!(map.values().eq(&[11, 3]));
+    }
+
+    #[test]
+    fn map_remove() {
+        let mut map: AVLMap<_, _> = (0..8).map(|i| (i, i.to_string())).collect();
+        assert_eq!(map.remove(&4), Some("4".to_string()));
+        assert_eq!(map.remove(&4), None);
+        assert!(!map.contains_key(&4));
+        assert_eq!(map.len(), 7);
+        assert!(map_is_balanced(&map));
+    }
+
+    #[test]
+    fn map_entry() {
+        let mut map = AVLMap::new();
+        // 计数, 插入会触发旋转, 借出的value要还是对的
+        for word in "c b a c a c d e f".split(' ') {
+            *map.entry(word).or_insert(0) += 1;
+        }
+        assert!(map.iter().eq([(&"a", &2), (&"b", &1), (&"c", &3), (&"d", &1), (&"e", &1), (&"f", &1)]));
+        assert!(map_is_balanced(&map));
+
+        map.entry("a").and_modify(|v| *v = 100).or_default();
+        assert_eq!(map.get(&"a"), Some(&100));
+        *map.entry("g").or_default() += 1;
+        assert_eq!(map.get(&"g"), Some(&1));
+        assert_eq!(map.entry("z").key(), &"z");
+
+        match map.entry("c") {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.insert(30), 3);
+                assert_eq!(e.remove(), 30);
+            },
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert!(!map.contains_key(&"c"));
+        assert_eq!(map.len(), 6);
+    }
+
+    #[test]
+    fn map_random() {
+        let mut map = AVLMap::new();
+        let mut expect = BTreeMap::new();
+        for _ in 0..4096 {
+            let k = rand::random::<u64>() % 256;
+            match rand::random::<u64>() % 3 {
+                0 => assert_eq!(map.insert(k, k), expect.insert(k, k)),
+                1 => assert_eq!(map.remove(&k), expect.remove(&k)),
+                _ => assert_eq!(*map.entry(k).or_insert(k), *expect.entry(k).or_insert(k)),
+            }
+            assert_eq!(map.len(), expect.len());
+            assert!(map_is_balanced(&map));
+        }
+        assert!(map.iter().eq(expect.iter()));
+    }
+
+    #[test]
+    fn order_statistics() {
+        let mut tree = AVLTree::new();
+        let mut expect = vec![];
+        for _ in 0..1024 {
+            // 只放偶数, 奇数用来测不存在的rank
+            let x = rand::random::<u64>() % 512 * 2;
+            if rand::random::<u64>().is_multiple_of(4) {
+                tree.remove(&x);
+                expect.retain(|&y| y != x);
+            } else if tree.insert(x) {
+                expect.push(x);
+            }
+        }
+        expect.sort();
+        assert!(is_balanced(&tree));
+        assert_eq!(tree.len(), expect.len());
+
+        for (i, x) in expect.iter().enumerate() {
+            assert_eq!(tree.nth(i), Some(x));
+            assert_eq!(tree.rank(x), i);
+        }
+        assert_eq!(tree.nth(expect.len()), None);
+        for _ in 0..256 {
+            let a = rand::random::<u64>() % 1026;
+            let b = rand::random::<u64>() % 1026;
+            assert_eq!(tree.rank(&a), expect.partition_point(|&x| x < a));
+            let count = |lo: usize, hi: usize| hi.saturating_sub(lo);
+            let lt = |v| expect.partition_point(|&x| x < v);
+            let le = |v| expect.partition_point(|&x| x <= v);
+            assert_eq!(tree.count_range(a..b), count(lt(a), lt(b)));
+            assert_eq!(tree.count_range(a..=b), count(lt(a), le(b)));
+            assert_eq!(tree.count_range(..b), lt(b));
+            assert_eq!(tree.count_range(a..), expect.len() - lt(a));
+        }
+        assert_eq!(tree.count_range(..), expect.len());
+    }
+
+    #[test]
+    fn range_and_bounds() {
+        let mut tree = AVLTree::new();
+        let mut expect = std::collections::BTreeSet::new();
+        assert_eq!((tree.first(), tree.last()), (None, None));
+        assert_eq!(tree.range(..).next(), None);
+        // 只放偶数, 奇数用来测不存在的key
+        for _ in 0..512 {
+            let x = rand::random::<u64>() % 512 * 2;
+            tree.insert(x);
+            expect.insert(x);
+        }
+
+        assert!(tree.iter().rev().eq(expect.iter().rev()));
+        assert_eq!(tree.iter().len(), expect.len());
+        assert_eq!(tree.first(), expect.first());
+        assert_eq!(tree.last(), expect.last());
+        for _ in 0..256 {
+            let a = rand::random::<u64>() % 1026;
+            let b = rand::random::<u64>() % 1026;
+            let (a, b) = (a.min(b), a.max(b));
+            assert!(tree.range(a..b).eq(expect.range(a..b)));
+            assert!(tree.range(a..=b).rev().eq(expect.range(a..=b).rev()));
+            assert!(tree.range(a..).eq(expect.range(a..)));
+            assert!(tree.range(..=b).rev().eq(expect.range(..=b).rev()));
+            assert_eq!(tree.range(a..b).len(), expect.range(a..b).count());
+
+            // 两头交替走, 在中间相遇
+            let mut it = tree.range(a..=b);
+            let mut ex = expect.range(a..=b);
+            loop {
+                let (x, y) = (it.next(), ex.next());
+                assert_eq!(x, y);
+                let (x2, y2) = (it.next_back(), ex.next_back());
+                assert_eq!(x2, y2);
+                if y.is_none() && y2.is_none() {
+                    break;
+                }
+            }
+
+            assert_eq!(tree.floor(&a), expect.range(..=a).next_back());
+            assert_eq!(tree.ceil(&a), expect.range(a..).next());
+            assert_eq!(tree.predecessor(&a), expect.range(..a).next_back());
+            assert_eq!(tree.successor(&a), expect.range(a + 1..).next());
+        }
+
+        while !expect.is_empty() {
+            if rand::random::<u64>().is_multiple_of(2) {
+                assert_eq!(tree.pop_first(), expect.pop_first());
+            } else {
+                assert_eq!(tree.pop_last(), expect.pop_last());
+            }
+            assert!(is_balanced(&tree));
+            assert_eq!(tree.len(), expect.len());
+        }
+        assert_eq!(tree.pop_first(), None);
+        assert_eq!(tree.pop_last(), None);
+    }
+
+    fn random_tree(n: usize, max: u64) -> (AVLTree<u64>, BTreeSet<u64>) {
+        let mut tree = AVLTree::new();
+        let mut expect = BTreeSet::new();
+        for _ in 0..n {
+            let x = rand::random::<u64>() % max;
+            tree.insert(x);
+            expect.insert(x);
+        }
+        (tree, expect)
+    }
+
+    #[test]
+    fn split_off() {
+        for _ in 0..64 {
+            let n = (rand::random::<u64>() % 512) as usize;
+            let (mut tree, mut expect) = random_tree(n, 1024);
+            let key = rand::random::<u64>() % 1100;
+            let right = tree.split_off(&key);
+            let expect_right = expect.split_off(&key);
+            assert!(is_balanced(&tree) && is_balanced(&right));
+            assert!(tree.iter().eq(expect.iter()));
+            assert!(right.iter().eq(expect_right.iter()));
+            assert_eq!((tree.len(), right.len()), (expect.len(), expect_right.len()));
+
+            // 再拼回去
+            let tree = tree.join(right);
+            expect.extend(expect_right);
+            assert!(is_balanced(&tree));
+            assert!(tree.iter().eq(expect.iter()));
+        }
+    }
+
+    #[test]
+    fn join_different_heights() {
+        // 一边很大一边很小, join要沿着大树往下走
+        let small: AVLTree<_> = (0..3).collect();
+        let big: AVLTree<_> = (10..1000).collect();
+        let tree = small.join(big);
+        assert!(is_balanced(&tree));
+        assert!(tree.iter().copied().eq((0..3).chain(10..1000)));
+
+        let big: AVLTree<_> = (0..1000).collect();
+        let small: AVLTree<_> = (2000..2001).collect();
+        let tree = big.join(small).join(AVLTree::new());
+        assert!(is_balanced(&tree));
+        assert_eq!(tree.len(), 1001);
+        assert_eq!(tree.last(), Some(&2000));
+    }
+
+    #[test]
+    #[should_panic]
+    fn join_overlap() {
+        let a: AVLTree<_> = (0..10).collect();
+        let b: AVLTree<_> = (5..15).collect();
+        a.join(b);
+    }
+
+    #[test]
+    fn append() {
+        for _ in 0..64 {
+            let (mut a, mut ea) = random_tree((rand::random::<u64>() % 256) as usize, 512);
+            // 一半概率不重叠
+            let offset = if rand::random::<u64>().is_multiple_of(2) { 0 } else { 512 };
+            let (mut b, mut eb) = random_tree((rand::random::<u64>() % 256) as usize, 512);
+            let mut b2 = AVLTree::new();
+            while let Some(x) = b.pop_first() {
+                b2.insert(x + offset);
+            }
+            eb = eb.into_iter().map(|x| x + offset).collect();
+            if rand::random::<u64>().is_multiple_of(2) {
+                a.append(&mut b2);
+                ea.append(&mut eb);
+                assert!(is_balanced(&a));
+                assert!(a.iter().eq(ea.iter()));
+                assert!(b2.is_empty());
+            } else {
+                b2.append(&mut a);
+                eb.append(&mut ea);
+                assert!(is_balanced(&b2));
+                assert!(b2.iter().eq(eb.iter()));
+                assert!(a.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn set_algebra() {
+        for _ in 0..64 {
+            // 大小悬殊和差不多的都要有
+            let (a, ea) = random_tree((rand::random::<u64>() % 512) as usize, 1024);
+            let (b, eb) = random_tree((rand::random::<u64>() % 32) as usize, 1024);
+            for (a, b, ea, eb) in [(&a, &b, &ea, &eb), (&b, &a, &eb, &ea)] {
+                assert!(a.union(b).eq(ea.union(eb)));
+                assert!(a.intersection(b).eq(ea.intersection(eb)));
+                assert!(a.difference(b).eq(ea.difference(eb)));
+                assert!(a.symmetric_difference(b).eq(ea.symmetric_difference(eb)));
+            }
+
+            let copy = |t: &AVLTree<u64>| t.iter().copied().collect::<AVLTree<_>>();
+            let bulk = [
+                copy(&a).into_union(copy(&b)),
+                copy(&a).into_intersection(copy(&b)),
+                copy(&a).into_difference(copy(&b)),
+                copy(&b).into_difference(copy(&a)),
+                copy(&a).into_symmetric_difference(copy(&b)),
+            ];
+            let expect: [BTreeSet<u64>; 5] = [&ea | &eb, &ea & &eb, &ea - &eb, &eb - &ea, &ea ^ &eb];
+            for (tree, expect) in bulk.iter().zip(&expect) {
+                assert!(is_balanced(tree));
+                assert_eq!(tree.len(), expect.len());
+                assert!(tree.iter().eq(expect.iter()));
+            }
+        }
+    }
+
+    /// 测试用的增强: 子树value之和
+    struct Sum;
+
+    impl Augment<u64, u64> for Sum {
+        type Summary = u64;
+
+        fn summarize(_: &u64, value: &u64, left: Option<&u64>, right: Option<&u64>) -> u64 {
+            value + left.unwrap_or(&0) + right.unwrap_or(&0)
+        }
+    }
+
+    fn sum_ok(map: &AVLMap<u64, u64, Sum>) -> bool {
+        map.node_iter().all(|n| {
+            n.summary == n.value + n.left.as_ref().map_or(0, |l| l.summary) + n.right.as_ref().map_or(0, |r| r.summary)
+        }) && map_is_balanced(map) && map.summary().copied().unwrap_or(0) == map.values().sum::<u64>()
+    }
+
+    #[test]
+    fn augment() {
+        let mut map: AVLMap<u64, u64, Sum> = AVLMap::new();
+        for _ in 0..2048 {
+            let k = rand::random::<u64>() % 1024;
+            if rand::random::<u64>().is_multiple_of(3) {
+                map.remove(&k);
+            } else {
+                // 覆盖value也要更新路径上的摘要
+                map.insert(k, rand::random::<u64>() % 100);
+            }
+        }
+        assert!(sum_ok(&map));
+
+        // 切开再拼回去
+        let mut right = map.split_off(&512);
+        assert!(sum_ok(&map) && sum_ok(&right));
+        map.pop_first();
+        right.pop_last();
+        let mut map = map.join(right);
+        assert!(sum_ok(&map));
+        let mut other: AVLMap<u64, u64, Sum> = (0..64).map(|i| (i * 37, i)).collect();
+        map.append(&mut other);
+        assert!(sum_ok(&map));
+    }
 }
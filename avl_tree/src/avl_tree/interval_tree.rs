@@ -0,0 +1,427 @@
+use std::mem;
+
+type Item<T, V> = ((T, T), V);
+
+/// 优先搜索树(priority search tree)的节点, 每个节点正好放一个区间
+///  - 堆: 节点的右端点 >= 子树里所有区间的右端点
+///  - 路由: 左子树的区间(lo, hi) < split <= 右子树的区间, 节点自己的区间可以在子树范围内的任意位置
+struct Node<T, V> {
+    item: Item<T, V>,
+    split: (T, T),
+    left: Option<Box<Node<T, V>>>,
+    right: Option<Box<Node<T, V>>>,
+    // 子树区间数, 也就是节点数
+    size: usize,
+}
+
+/// 区间树: 闭区间[lo, hi], 用优先搜索树实现, 查询O(log n + k)
+///  ⭐相交 <=> lo <= b且hi >= a: 按(lo, hi)路由时lo <= b是一个前缀, hi >= a交给堆剪枝
+///  平衡用替罪羊树: 插入太深时重建子树, 删除太多时重建整棵树, 深度O(log n)
+///  同一个区间只存一个value, 要存多个就让V是Vec
+pub struct IntervalTree<T, V> {
+    root: Option<Box<Node<T, V>>>,
+    len: usize,
+    // 上次整棵重建以来最大的len, 删除后len < 3/4 * max_len就整棵重建
+    max_len: usize,
+}
+
+fn size<T, V>(tree: &Option<Box<Node<T, V>>>) -> usize {
+    tree.as_ref().map_or(0, |n| n.size)
+}
+
+/// 替罪羊树的深度上限: log_{4/3}(n)
+fn depth_limit(n: usize) -> usize {
+    ((n as f64).ln() / (4.0f64 / 3.0).ln()).floor() as usize
+}
+
+impl<T: Ord + Clone, V> Node<T, V> {
+    fn leaf(item: Item<T, V>) -> Self {
+        let split = item.0.clone();
+        Self { item, split, left: None, right: None, size: 1 }
+    }
+
+    fn child_mut(&mut self, key: &(T, T)) -> &mut Option<Box<Node<T, V>>> {
+        if key < &self.split { &mut self.left } else { &mut self.right }
+    }
+}
+
+impl<T: Ord + Clone, V> IntervalTree<T, V> {
+    pub fn new() -> Self {
+        Self { root: None, len: 0, max_len: 0 }
+    }
+
+    /// 区间已存在时覆盖value, 返回旧value
+    ///  不存在时从根往下走, 右端点比节点的大就换下节点的区间继续往下放, 最后挂成新叶子
+    ///  新叶子太深时找替罪羊重建, 均摊O(log² n)
+    pub fn insert(&mut self, lo: T, hi: T, value: V) -> Option<V> {
+        assert!(lo <= hi, "IntervalTree::insert: lo > hi");
+        let key = (lo, hi);
+        if let Some(v) = self.get_mut(&key) {
+            return Some(mem::replace(v, value));
+        }
+        self.len += 1;
+        self.max_len = self.max_len.max(self.len);
+        Self::insert_at(&mut self.root, (key, value), 0, depth_limit(self.len));
+        None
+    }
+
+    /// 返回true表示新叶子太深, 还没找到替罪羊
+    fn insert_at(tree: &mut Option<Box<Node<T, V>>>, mut item: Item<T, V>, depth: usize, limit: usize) -> bool {
+        let Some(node) = tree else {
+            *tree = Some(Box::new(Node::leaf(item)));
+            return depth > limit;
+        };
+        node.size += 1;
+        if item.0 .1 > node.item.0 .1 {
+            mem::swap(&mut item, &mut node.item);
+        }
+        if !Self::insert_at(node.child_mut(&item.0), item, depth + 1, limit) {
+            return false;
+        }
+        // 替罪羊: 有一个孩子占了3/4以上
+        if size(&node.left).max(size(&node.right)) * 4 > node.size * 3 {
+            Self::rebuild(tree);
+            return false;
+        }
+        true
+    }
+
+    /// 堆剪枝: 区间的右端点比节点的还大, 子树里就不会有
+    pub fn remove(&mut self, lo: T, hi: T) -> Option<V> {
+        let value = Self::remove_at(&mut self.root, &(lo, hi))?;
+        self.len -= 1;
+        if self.len * 4 < self.max_len * 3 {
+            Self::rebuild(&mut self.root);
+            self.max_len = self.len;
+        }
+        Some(value)
+    }
+
+    fn remove_at(tree: &mut Option<Box<Node<T, V>>>, key: &(T, T)) -> Option<V> {
+        let node = tree.as_mut()?;
+        if &node.item.0 == key {
+            return Some(Self::take_root(tree).1);
+        }
+        if key.1 > node.item.0 .1 {
+            return None;
+        }
+        let value = Self::remove_at(node.child_mut(key), key)?;
+        node.size -= 1;
+        Some(value)
+    }
+
+    /// 拿走根的区间, 右端点大的那个孩子的区间提上来, 一路提到叶子, 叶子删掉
+    ///  深度只会变浅, 路由也不变: 提上来的区间本来就在这棵子树的范围里
+    fn take_root(tree: &mut Option<Box<Node<T, V>>>) -> Item<T, V> {
+        let node = tree.as_mut().unwrap();
+        node.size -= 1;
+        let child = match (&node.left, &node.right) {
+            (None, None) => return tree.take().unwrap().item,
+            (Some(_), None) => &mut node.left,
+            (None, Some(_)) => &mut node.right,
+            (Some(l), Some(r)) => if l.item.0 .1 >= r.item.0 .1 { &mut node.left } else { &mut node.right },
+        };
+        let up = Self::take_root(child);
+        mem::replace(&mut node.item, up)
+    }
+
+    /// 把子树拍平, 按(lo, hi)排序后重新建一棵满的
+    fn rebuild(tree: &mut Option<Box<Node<T, V>>>) {
+        let mut items = Vec::with_capacity(size(tree));
+        Self::drain(tree.take(), &mut items);
+        items.sort_unstable_by(|x, y| x.0.cmp(&y.0));
+        *tree = Self::build(items);
+    }
+
+    fn drain(tree: Option<Box<Node<T, V>>>, out: &mut Vec<Item<T, V>>) {
+        if let Some(node) = tree {
+            let Node { item, left, right, .. } = *node;
+            out.push(item);
+            Self::drain(left, out);
+            Self::drain(right, out);
+        }
+    }
+
+    /// items已排序: 右端点最大的放在根, 剩下的从中间分开, split是右半的第一个
+    ///  每层找最大值和删除都是O(m), 一共O(m log m), 高度不超过log2(m)
+    fn build(mut items: Vec<Item<T, V>>) -> Option<Box<Node<T, V>>> {
+        let top = (0..items.len()).max_by(|&i, &j| items[i].0 .1.cmp(&items[j].0 .1))?;
+        let item = items.remove(top);
+        let size = items.len() + 1;
+        let right = items.split_off(items.len() / 2);
+        let split = right.first().map_or_else(|| item.0.clone(), |r| r.0.clone());
+        Some(Box::new(Node { item, split, left: Self::build(items), right: Self::build(right), size }))
+    }
+
+    pub fn get(&self, lo: T, hi: T) -> Option<&V> {
+        let key = (lo, hi);
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            if node.item.0 == key {
+                return Some(&node.item.1);
+            }
+            if key.1 > node.item.0 .1 {
+                return None;
+            }
+            tree = if key < node.split { &node.left } else { &node.right };
+        }
+        None
+    }
+
+    /// 只在insert里用: 覆盖value不改右端点, 堆不受影响
+    fn get_mut(&mut self, key: &(T, T)) -> Option<&mut V> {
+        let mut tree = &mut self.root;
+        while let Some(node) = tree {
+            if &node.item.0 == key {
+                return Some(&mut node.item.1);
+            }
+            if key.1 > node.item.0 .1 {
+                return None;
+            }
+            tree = node.child_mut(key);
+        }
+        None
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 所有区间, ⭐不保证顺序
+    pub fn iter(&self) -> Intervals<'_, T, V> {
+        Intervals { stack: self.root.as_deref().into_iter().collect(), len: self.len }
+    }
+
+    /// 所有和[a, b]相交的区间, O(log n + k), ⭐不保证顺序
+    ///  右端点 < a的节点整棵子树跳过(堆); split的lo > b时右子树跳过, 右子树的lo都 >= split的lo
+    ///  走到的节点要么是结果, 要么它的范围跨过了b, 后者每层最多一个, 所以多走的不超过树高
+    pub fn overlapping(&self, a: &T, b: &T) -> Overlapping<'_, T, V> {
+        let mut iter = Overlapping { stack: Vec::new(), a: a.clone(), b: b.clone() };
+        iter.push(&self.root);
+        iter
+    }
+
+    /// 任意一个和[a, b]相交的区间, O(log n)
+    ///  第一个结果之前走到的都是跨过b的节点, 每层最多一个
+    pub fn any_overlapping(&self, a: &T, b: &T) -> Option<(&(T, T), &V)> {
+        self.overlapping(a, b).next()
+    }
+
+    /// 所有包含p的区间
+    pub fn containing(&self, p: &T) -> Overlapping<'_, T, V> {
+        self.overlapping(p, p)
+    }
+
+    /// 任意一个包含p的区间, O(log n)
+    pub fn any_containing(&self, p: &T) -> Option<(&(T, T), &V)> {
+        self.any_overlapping(p, p)
+    }
+}
+
+impl<T: Ord + Clone, V> Default for IntervalTree<T, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Clone, V> FromIterator<((T, T), V)> for IntervalTree<T, V> {
+    fn from_iter<I: IntoIterator<Item = ((T, T), V)>>(iter: I) -> Self {
+        let mut tree = IntervalTree::new();
+        for ((lo, hi), value) in iter {
+            tree.insert(lo, hi, value);
+        }
+        tree
+    }
+}
+
+/// 由`iter`创建, 前序遍历
+pub struct Intervals<'a, T, V> {
+    stack: Vec<&'a Node<T, V>>,
+    len: usize,
+}
+
+impl<'a, T, V> Iterator for Intervals<'a, T, V> {
+    type Item = (&'a (T, T), &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.right.as_deref());
+        self.stack.extend(node.left.as_deref());
+        self.len -= 1;
+        Some((&node.item.0, &node.item.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T, V> ExactSizeIterator for Intervals<'_, T, V> {}
+
+/// 带剪枝的前序遍历, 由`overlapping`和`containing`创建
+pub struct Overlapping<'a, T, V> {
+    stack: Vec<&'a Node<T, V>>,
+    a: T,
+    b: T,
+}
+
+impl<'a, T: Ord, V> Overlapping<'a, T, V> {
+    /// 右端点 < a的子树不进栈
+    fn push(&mut self, tree: &'a Option<Box<Node<T, V>>>) {
+        if let Some(node) = tree {
+            if node.item.0 .1 >= self.a {
+                self.stack.push(node);
+            }
+        }
+    }
+
+    /// 出栈一个节点, 它是结果的话返回它
+    fn step(&mut self) -> Option<Option<(&'a (T, T), &'a V)>> {
+        let node = self.stack.pop()?;
+        if node.split.0 <= self.b {
+            self.push(&node.right);
+        }
+        self.push(&node.left);
+        // 进栈时已经保证了hi >= a
+        Some((node.item.0 .0 <= self.b).then_some((&node.item.0, &node.item.1)))
+    }
+}
+
+impl<'a, T: Ord, V> Iterator for Overlapping<'a, T, V> {
+    type Item = (&'a (T, T), &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(found) = self.step() {
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    type Tree = Option<Box<Node<u64, u64>>>;
+
+    fn overlaps(key: &(u64, u64), a: &u64, b: &u64) -> bool {
+        &key.0 <= b && a <= &key.1
+    }
+
+    /// 检查堆, 路由范围[lo, hi), 子树大小, 返回高度
+    fn check(tree: &Tree, lo: Option<(u64, u64)>, hi: Option<(u64, u64)>) -> usize {
+        let Some(node) = tree else { return 0 };
+        let key = node.item.0;
+        assert!(lo.is_none_or(|lo| lo <= key) && hi.is_none_or(|hi| key < hi));
+        for child in [&node.left, &node.right].into_iter().flatten() {
+            assert!(child.item.0 .1 <= key.1);
+        }
+        assert_eq!(node.size, 1 + size(&node.left) + size(&node.right));
+        let split = Some(node.split);
+        1 + check(&node.left, lo, split).max(check(&node.right, split, hi))
+    }
+
+    fn check_tree(tree: &IntervalTree<u64, u64>) {
+        let height = check(&tree.root, None, None);
+        assert_eq!(size(&tree.root), tree.len());
+        // 替罪羊树: 高度不超过log_{4/3}(max_len) + 2
+        assert!(height <= depth_limit(tree.max_len) + 2, "height {} len {}", height, tree.len());
+    }
+
+    /// 查询走到(出栈)的节点数和结果数
+    fn visited(tree: &IntervalTree<u64, u64>, a: u64, b: u64) -> (usize, usize) {
+        let mut iter = tree.overlapping(&a, &b);
+        let (mut visited, mut found) = (0, 0);
+        while let Some(item) = iter.step() {
+            visited += 1;
+            found += item.is_some() as usize;
+        }
+        (visited, found)
+    }
+
+    #[test]
+    fn schedule() {
+        let tree: IntervalTree<u32, &str> = [((9, 10), "standup"), ((13, 15), "review"), ((14, 16), "1:1"), ((17, 18), "gym")]
+            .into_iter()
+            .collect();
+        let mut conflicts: Vec<_> = tree.overlapping(&14, &17).map(|(_, v)| *v).collect();
+        conflicts.sort();
+        assert_eq!(conflicts, ["1:1", "gym", "review"]);
+        assert_eq!(tree.any_containing(&9).map(|(_, v)| *v), Some("standup"));
+        assert!(tree.any_containing(&12).is_none());
+        assert_eq!(tree.containing(&16).count(), 1);
+    }
+
+    #[test]
+    fn random() {
+        let mut tree = IntervalTree::new();
+        let mut expect = BTreeMap::new();
+        for i in 0..4096u64 {
+            let lo = rand::random::<u64>() % 1000;
+            let hi = lo + rand::random::<u64>() % 50;
+            if i % 4 == 3 {
+                // 删一个已有的, 再删一个多半不存在的
+                let (lo, hi) = *expect.keys().nth((rand::random::<u64>() % expect.len() as u64) as usize).unwrap();
+                assert_eq!(tree.remove(lo, hi), expect.remove(&(lo, hi)));
+                assert_eq!(tree.remove(lo, hi + 1), expect.remove(&(lo, hi + 1)));
+            } else {
+                assert_eq!(tree.insert(lo, hi, i), expect.insert((lo, hi), i));
+            }
+            if i % 64 == 0 {
+                check_tree(&tree);
+            }
+        }
+        assert_eq!(tree.len(), expect.len());
+        check_tree(&tree);
+        for (k, v) in &expect {
+            assert_eq!(tree.get(k.0, k.1), Some(v));
+        }
+        let mut all: Vec<_> = tree.iter().map(|(k, v)| (*k, *v)).collect();
+        all.sort();
+        assert_eq!(all, expect.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>());
+
+        for _ in 0..256 {
+            let a = rand::random::<u64>() % 1100;
+            let b = a + rand::random::<u64>() % 20;
+            let mut got: Vec<_> = tree.overlapping(&a, &b).map(|(k, _)| *k).collect();
+            got.sort();
+            let want: Vec<_> = expect.keys().copied().filter(|k| overlaps(k, &a, &b)).collect();
+            assert_eq!(got, want);
+            match tree.any_overlapping(&a, &b) {
+                Some((k, _)) => assert!(want.contains(k)),
+                None => assert!(want.is_empty()),
+            }
+        }
+    }
+
+    #[test]
+    fn query_bound() {
+        // 按lo有序插入, 右端点交替变化: 结果会分散在树里
+        let mut tree = IntervalTree::new();
+        for i in 0..4096u64 {
+            tree.insert(i * 2, i * 2 + if i % 2 == 0 { 1 } else { 5000 }, i);
+        }
+        check_tree(&tree);
+        let height = check(&tree.root, None, None);
+        for (a, b) in [(0, 0), (3000, 3000), (6000, 8000), (0, 9000), (9000, 9000)] {
+            let (visited, found) = visited(&tree, a, b);
+            assert_eq!(found, tree.overlapping(&a, &b).count());
+            // ⭐多走的节点不超过树高
+            assert!(visited <= found + height, "visited {} found {} height {}", visited, found, height);
+        }
+
+        // 删掉大部分后整棵重建, 高度跟着变小
+        for i in 0..4000u64 {
+            tree.remove(i * 2, i * 2 + if i % 2 == 0 { 1 } else { 5000 });
+        }
+        check_tree(&tree);
+        assert_eq!(tree.len(), 96);
+    }
+}
@@ -1,2 +1,3 @@
-mod avl_tree;
-mod avl_tree_t;
+pub mod avl_tree;
+
+pub use crate::avl_tree::*;
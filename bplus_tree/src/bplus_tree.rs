@@ -0,0 +1,677 @@
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+
+/// 默认阶数
+pub const DEFAULT_ORDER: usize = 64;
+
+/// 节点都放在arena里, 用下标互相引用, 叶子之间的next指针就不用unsafe了
+enum Node<K, V> {
+    Internal(Internal<K>),
+    Leaf(Leaf<K, V>),
+}
+
+/// children[i]里的key都在[keys[i - 1], keys[i])之间
+///  删除后keys[i]不一定还在树里, 但这个范围始终成立
+struct Internal<K> {
+    keys: Vec<K>,
+    children: Vec<usize>,
+}
+
+struct Leaf<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    // 右边的叶子, 范围扫描时顺着走
+    next: Option<usize>,
+}
+
+impl<K, V> Node<K, V> {
+    fn empty_leaf() -> Self {
+        Node::Leaf(Leaf { keys: Vec::new(), values: Vec::new(), next: None })
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Node::Internal(n) => n.keys.len(),
+            Node::Leaf(n) => n.keys.len(),
+        }
+    }
+}
+
+/// B+树
+///  - 阶数order: 内部节点最多order个孩子, 每个节点最多order - 1个key
+///  - 除了根, 每个节点至少(order - 1) / 2个key, 即内部节点至少ceil(order / 2)个孩子
+///  - kv都在叶子里, 叶子从左到右串成链表
+pub struct BPlusTree<K: Ord + Clone, V> {
+    nodes: Vec<Node<K, V>>,
+    // 被合并掉的节点下标, 下次分裂时复用
+    free: Vec<usize>,
+    root: usize,
+    order: usize,
+    len: usize,
+}
+
+impl<K: Ord + Clone, V> BPlusTree<K, V> {
+    pub fn new(order: usize) -> Self {
+        assert!(order >= 3, "BPlusTree: order must be at least 3");
+        Self {
+            nodes: vec![Node::empty_leaf()],
+            free: Vec::new(),
+            root: 0,
+            order,
+            len: 0,
+        }
+    }
+
+    /// 从有序输入建树, key必须严格递增
+    ///  ⭐自底向上: 先把kv平均分到尽量少的叶子里, 再一层层往上建, 每层也是平均分
+    ///  平均分保证最后一个节点不会太空, O(n)
+    pub fn bulk_load<I: IntoIterator<Item = (K, V)>>(order: usize, iter: I) -> Self {
+        let mut tree = Self::new(order);
+        let (keys, values): (Vec<K>, Vec<V>) = iter.into_iter().unzip();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]), "BPlusTree::bulk_load: keys not strictly increasing");
+        if keys.is_empty() {
+            return tree;
+        }
+        tree.len = keys.len();
+        tree.nodes.clear();
+
+        // 每一层: (子树的最小key, 节点下标)
+        let mut level = Vec::new();
+        let mut keys = keys.into_iter();
+        let mut values = values.into_iter();
+        for size in Self::even_split(tree.len, order - 1) {
+            let leaf = Leaf {
+                keys: keys.by_ref().take(size).collect(),
+                values: values.by_ref().take(size).collect(),
+                next: Some(tree.nodes.len() + 1),
+            };
+            level.push((leaf.keys[0].clone(), tree.nodes.len()));
+            tree.nodes.push(Node::Leaf(leaf));
+        }
+        if let Some(Node::Leaf(last)) = tree.nodes.last_mut() {
+            last.next = None;
+        }
+
+        while level.len() > 1 {
+            let mut upper = Vec::new();
+            let mut children = level.into_iter();
+            for size in Self::even_split(children.len(), order) {
+                let mut group = children.by_ref().take(size);
+                let (min, first) = group.next().unwrap();
+                let mut node = Internal { keys: Vec::new(), children: vec![first] };
+                for (key, child) in group {
+                    node.keys.push(key);
+                    node.children.push(child);
+                }
+                upper.push((min, tree.nodes.len()));
+                tree.nodes.push(Node::Internal(node));
+            }
+            level = upper;
+        }
+        tree.root = level[0].1;
+        tree
+    }
+
+    /// n个分成最少的组, 每组不超过cap, 各组大小最多差1
+    fn even_split(n: usize, cap: usize) -> impl Iterator<Item = usize> {
+        let groups = n.div_ceil(cap);
+        (0..groups).map(move |i| n / groups + usize::from(i < n % groups))
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 只有根为1
+    pub fn height(&self) -> usize {
+        let mut height = 1;
+        let mut id = self.root;
+        while let Node::Internal(node) = &self.nodes[id] {
+            id = node.children[0];
+            height += 1;
+        }
+        height
+    }
+
+    /// 非根节点的最少key数
+    fn min_keys(&self) -> usize {
+        (self.order - 1) / 2
+    }
+
+    fn alloc(&mut self, node: Node<K, V>) -> usize {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn dealloc(&mut self, id: usize) {
+        self.nodes[id] = Node::empty_leaf();
+        self.free.push(id);
+    }
+
+    /// 拿出节点的所有权, 改完再放回去, 同时改好几个节点时用
+    fn take(&mut self, id: usize) -> Node<K, V> {
+        mem::replace(&mut self.nodes[id], Node::empty_leaf())
+    }
+
+    fn internal_mut(&mut self, id: usize) -> &mut Internal<K> {
+        match &mut self.nodes[id] {
+            Node::Internal(node) => node,
+            Node::Leaf(_) => unreachable!(),
+        }
+    }
+
+    /// 一路往下找到key所在的叶子
+    fn find_leaf(&self, key: &K) -> usize {
+        let mut id = self.root;
+        while let Node::Internal(node) = &self.nodes[id] {
+            id = node.children[node.keys.partition_point(|k| k <= key)];
+        }
+        id
+    }
+
+    fn leaf(&self, id: usize) -> &Leaf<K, V> {
+        match &self.nodes[id] {
+            Node::Leaf(leaf) => leaf,
+            Node::Internal(_) => unreachable!(),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let leaf = self.leaf(self.find_leaf(key));
+        leaf.keys.binary_search(key).ok().map(|i| &leaf.values[i])
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let id = self.find_leaf(key);
+        match &mut self.nodes[id] {
+            Node::Leaf(leaf) => leaf.keys.binary_search(key).ok().map(|i| &mut leaf.values[i]),
+            Node::Internal(_) => unreachable!(),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// key已存在时覆盖value, 返回旧value
+    ///  根分裂时长出新根, 树只会从上面长高
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let (old, split) = self.insert_rec(self.root, key, value);
+        if let Some((sep, right)) = split {
+            let root = Internal { keys: vec![sep], children: vec![self.root, right] };
+            self.root = self.alloc(Node::Internal(root));
+        }
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// 节点满了就从中间分裂, 返回(分隔key, 右半边)给父节点
+    #[allow(clippy::type_complexity)]
+    fn insert_rec(&mut self, id: usize, key: K, value: V) -> (Option<V>, Option<(K, usize)>) {
+        let max = self.order - 1;
+        match &mut self.nodes[id] {
+            Node::Leaf(leaf) => {
+                match leaf.keys.binary_search(&key) {
+                    Ok(i) => return (Some(mem::replace(&mut leaf.values[i], value)), None),
+                    Err(i) => {
+                        leaf.keys.insert(i, key);
+                        leaf.values.insert(i, value);
+                    }
+                }
+                if leaf.keys.len() <= max {
+                    return (None, None);
+                }
+                // 叶子分裂: 右半边的第一个key复制一份给父节点
+                let mid = leaf.keys.len() / 2;
+                let right = Leaf {
+                    keys: leaf.keys.split_off(mid),
+                    values: leaf.values.split_off(mid),
+                    next: leaf.next,
+                };
+                let sep = right.keys[0].clone();
+                let right = self.alloc(Node::Leaf(right));
+                if let Node::Leaf(leaf) = &mut self.nodes[id] {
+                    leaf.next = Some(right);
+                }
+                (None, Some((sep, right)))
+            }
+            Node::Internal(node) => {
+                let i = node.keys.partition_point(|k| k <= &key);
+                let child = node.children[i];
+                let (old, split) = self.insert_rec(child, key, value);
+                let Some((sep, new_child)) = split else { return (old, None) };
+
+                let node = self.internal_mut(id);
+                node.keys.insert(i, sep);
+                node.children.insert(i + 1, new_child);
+                if node.keys.len() <= max {
+                    return (old, None);
+                }
+                // 内部节点分裂: 中间的key移到父节点, 不留副本
+                let mid = node.keys.len() / 2;
+                let right = Internal {
+                    keys: node.keys.split_off(mid + 1),
+                    children: node.children.split_off(mid + 1),
+                };
+                let sep = node.keys.pop().unwrap();
+                let right = self.alloc(Node::Internal(right));
+                (old, Some((sep, right)))
+            }
+        }
+    }
+
+    /// 返回被删除的value
+    ///  根是内部节点且只剩一个孩子时, 孩子成为新根, 树从上面变矮
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.remove_rec(self.root, key)?;
+        self.len -= 1;
+        if let Node::Internal(root) = &self.nodes[self.root] {
+            if root.keys.is_empty() {
+                let old = self.root;
+                self.root = root.children[0];
+                self.dealloc(old);
+            }
+        }
+        Some(value)
+    }
+
+    fn remove_rec(&mut self, id: usize, key: &K) -> Option<V> {
+        match &mut self.nodes[id] {
+            Node::Leaf(leaf) => {
+                let i = leaf.keys.binary_search(key).ok()?;
+                leaf.keys.remove(i);
+                Some(leaf.values.remove(i))
+            }
+            Node::Internal(node) => {
+                let i = node.keys.partition_point(|k| k <= key);
+                let child = node.children[i];
+                let value = self.remove_rec(child, key)?;
+                if self.nodes[child].len() < self.min_keys() {
+                    self.fix_underflow(id, i);
+                }
+                Some(value)
+            }
+        }
+    }
+
+    /// ⭐孩子i的key不够了
+    ///  1. 相邻的兄弟有多的: 借一个, 经过父节点转一下
+    ///  2. 都没有多的: 和兄弟合并, 父节点少一个key, 可能继续往上不够
+    fn fix_underflow(&mut self, parent: usize, i: usize) {
+        let min = self.min_keys();
+        let node = self.internal_mut(parent);
+        let left = (i > 0).then(|| node.children[i - 1]);
+        let right = node.children.get(i + 1).copied();
+
+        if let Some(left) = left.filter(|&l| self.nodes[l].len() > min) {
+            self.borrow_from_left(parent, i, left);
+        } else if let Some(right) = right.filter(|&r| self.nodes[r].len() > min) {
+            self.borrow_from_right(parent, i, right);
+        } else if left.is_some() {
+            // 合并到左兄弟
+            self.merge(parent, i - 1);
+        } else {
+            self.merge(parent, i);
+        }
+    }
+
+    /// 左兄弟的最后一个移到孩子i的最前面
+    fn borrow_from_left(&mut self, parent: usize, i: usize, left: usize) {
+        let child = self.internal_mut(parent).children[i];
+        let (mut l, mut c) = (self.take(left), self.take(child));
+        let sep = &mut self.internal_mut(parent).keys[i - 1];
+        match (&mut l, &mut c) {
+            (Node::Leaf(l), Node::Leaf(c)) => {
+                c.keys.insert(0, l.keys.pop().unwrap());
+                c.values.insert(0, l.values.pop().unwrap());
+                *sep = c.keys[0].clone();
+            }
+            (Node::Internal(l), Node::Internal(c)) => {
+                // 父节点的分隔key下来, 左兄弟的最大key上去
+                let up = l.keys.pop().unwrap();
+                c.keys.insert(0, mem::replace(sep, up));
+                c.children.insert(0, l.children.pop().unwrap());
+            }
+            _ => unreachable!(),
+        }
+        self.nodes[left] = l;
+        self.nodes[child] = c;
+    }
+
+    /// 同borrow_from_left, 右兄弟的第一个移到孩子i的最后面
+    fn borrow_from_right(&mut self, parent: usize, i: usize, right: usize) {
+        let child = self.internal_mut(parent).children[i];
+        let (mut c, mut r) = (self.take(child), self.take(right));
+        let sep = &mut self.internal_mut(parent).keys[i];
+        match (&mut c, &mut r) {
+            (Node::Leaf(c), Node::Leaf(r)) => {
+                c.keys.push(r.keys.remove(0));
+                c.values.push(r.values.remove(0));
+                *sep = r.keys[0].clone();
+            }
+            (Node::Internal(c), Node::Internal(r)) => {
+                let up = r.keys.remove(0);
+                c.keys.push(mem::replace(sep, up));
+                c.children.push(r.children.remove(0));
+            }
+            _ => unreachable!(),
+        }
+        self.nodes[child] = c;
+        self.nodes[right] = r;
+    }
+
+    /// 孩子i + 1合并到孩子i, 父节点去掉它们之间的分隔key
+    ///  叶子直接拼接, 分隔key丢掉; 内部节点要把分隔key拉下来放在中间
+    fn merge(&mut self, parent: usize, i: usize) {
+        let node = self.internal_mut(parent);
+        let sep = node.keys.remove(i);
+        let right = node.children.remove(i + 1);
+        let left = node.children[i];
+        let r = self.take(right);
+        match (&mut self.nodes[left], r) {
+            (Node::Leaf(l), Node::Leaf(r)) => {
+                l.keys.extend(r.keys);
+                l.values.extend(r.values);
+                l.next = r.next;
+            }
+            (Node::Internal(l), Node::Internal(r)) => {
+                l.keys.push(sep);
+                l.keys.extend(r.keys);
+                l.children.extend(r.children);
+            }
+            _ => unreachable!(),
+        }
+        self.dealloc(right);
+    }
+
+    /// 最左的叶子
+    fn first_leaf(&self) -> usize {
+        let mut id = self.root;
+        while let Node::Internal(node) = &self.nodes[id] {
+            id = node.children[0];
+        }
+        id
+    }
+
+    /// 第一个满足条件的位置, 叶子走到头时挪到下一个叶子的开头, 全部走完为None
+    ///  Included找>= key的, Excluded找> key的
+    fn position(&self, bound: Bound<&K>) -> Option<(usize, usize)> {
+        let (leaf, idx) = match bound {
+            Bound::Unbounded => (self.first_leaf(), 0),
+            Bound::Included(key) => {
+                let id = self.find_leaf(key);
+                (id, self.leaf(id).keys.partition_point(|k| k < key))
+            }
+            Bound::Excluded(key) => {
+                let id = self.find_leaf(key);
+                (id, self.leaf(id).keys.partition_point(|k| k <= key))
+            }
+        };
+        let node = self.leaf(leaf);
+        if idx < node.keys.len() {
+            Some((leaf, idx))
+        } else {
+            // 下一个叶子的第一个key >= 分隔key > key, 一定满足
+            node.next.map(|next| (next, 0))
+        }
+    }
+
+    /// 顺着叶子链表扫描, 起点和终点各找一次叶子, O(log n + k)
+    ///  同`BTreeMap::range`, 起点大于终点时panic
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) if s > e => {
+                panic!("BPlusTree::range: start is greater than end")
+            }
+            // 起点和终点相同, 有一边不包含就是空的
+            (Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) | (Bound::Included(s), Bound::Excluded(e)) if s == e => {
+                return Range { tree: self, pos: None, end: None };
+            }
+            _ => {}
+        }
+        let end = match range.end_bound() {
+            Bound::Included(key) => self.position(Bound::Excluded(key)),
+            Bound::Excluded(key) => self.position(Bound::Included(key)),
+            Bound::Unbounded => None,
+        };
+        Range { tree: self, pos: self.position(range.start_bound()), end }
+    }
+
+    pub fn iter(&self) -> Range<'_, K, V> {
+        self.range(..)
+    }
+
+    /// 检查结构, 出错时返回描述
+    ///  1. 所有叶子在同一层
+    ///  2. 除了根, 每个节点的key数在[(order - 1) / 2, order - 1]之间; 内部的根至少一个key
+    ///  3. 节点内有序, 每个孩子的key都在父节点给的范围里
+    ///  4. 叶子链表按顺序串起了所有叶子, kv总数等于len
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let mut leaves = Vec::new();
+        self.check_node(self.root, None, None, 0, &mut leaves)?;
+        if leaves.windows(2).any(|w| w[0].1 != w[1].1) {
+            return Err("leaves at different depths".to_string());
+        }
+        let mut id = Some(self.first_leaf());
+        let mut len = 0;
+        for &(leaf, _) in &leaves {
+            if id != Some(leaf) {
+                return Err(format!("leaf chain expects {}, got {:?}", leaf, id));
+            }
+            len += self.leaf(leaf).keys.len();
+            id = self.leaf(leaf).next;
+        }
+        if id.is_some() {
+            return Err("leaf chain longer than the tree".to_string());
+        }
+        if len != self.len {
+            return Err(format!("len {} != {} kv in leaves", self.len, len));
+        }
+        Ok(())
+    }
+
+    /// 中序收集(叶子, 深度), key都要在[lo, hi)里
+    fn check_node(&self, id: usize, lo: Option<&K>, hi: Option<&K>, depth: usize, leaves: &mut Vec<(usize, usize)>) -> Result<(), String> {
+        let node = &self.nodes[id];
+        let len = node.len();
+        if len > self.order - 1 {
+            return Err(format!("node {} overflows: {} keys", id, len));
+        }
+        if id != self.root && len < self.min_keys() {
+            return Err(format!("node {} underflows: {} keys", id, len));
+        }
+        let keys = match node {
+            Node::Internal(n) => &n.keys,
+            Node::Leaf(n) => &n.keys,
+        };
+        if keys.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(format!("node {} keys out of order", id));
+        }
+        if keys.first().is_some_and(|k| lo.is_some_and(|lo| k < lo)) || keys.last().is_some_and(|k| hi.is_some_and(|hi| k >= hi)) {
+            return Err(format!("node {} keys out of parent's range", id));
+        }
+        match node {
+            Node::Leaf(leaf) => {
+                if leaf.values.len() != len {
+                    return Err(format!("leaf {} has {} keys but {} values", id, len, leaf.values.len()));
+                }
+                leaves.push((id, depth));
+            }
+            Node::Internal(n) => {
+                if n.keys.is_empty() || n.children.len() != len + 1 {
+                    return Err(format!("internal node {} has {} keys and {} children", id, len, n.children.len()));
+                }
+                for (i, &child) in n.children.iter().enumerate() {
+                    let lo = if i == 0 { lo } else { Some(&n.keys[i - 1]) };
+                    let hi = n.keys.get(i).or(hi);
+                    self.check_node(child, lo, hi, depth + 1, leaves)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K: Ord + Clone, V> Default for BPlusTree<K, V> {
+    fn default() -> Self {
+        Self::new(DEFAULT_ORDER)
+    }
+}
+
+impl<K: Ord + Clone, V> FromIterator<(K, V)> for BPlusTree<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = BPlusTree::default();
+        for (key, value) in iter {
+            tree.insert(key, value);
+        }
+        tree
+    }
+}
+
+impl<'a, K: Ord + Clone, V> IntoIterator for &'a BPlusTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Range<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// 顺着叶子链表走, 走到end为止
+pub struct Range<'a, K: Ord + Clone, V> {
+    tree: &'a BPlusTree<K, V>,
+    // (叶子, 下标), None为走完了
+    pos: Option<(usize, usize)>,
+    end: Option<(usize, usize)>,
+}
+
+impl<'a, K: Ord + Clone, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, i) = self.pos.filter(|&pos| Some(pos) != self.end)?;
+        let leaf = self.tree.leaf(id);
+        self.pos = if i + 1 < leaf.keys.len() {
+            Some((id, i + 1))
+        } else {
+            leaf.next.map(|next| (next, 0))
+        };
+        Some((&leaf.keys[i], &leaf.values[i]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BPlusTree;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn insert_get() {
+        let mut tree = BPlusTree::new(4);
+        for i in 0..100 {
+            assert_eq!(tree.insert(i, i * 10), None);
+            tree.check_invariants().unwrap();
+        }
+        assert_eq!(tree.insert(7, 0), Some(70));
+        *tree.get_mut(&8).unwrap() += 1;
+        assert_eq!(tree.get(&8), Some(&81));
+        assert_eq!(tree.get(&100), None);
+        assert_eq!(tree.len(), 100);
+        assert!(tree.height() > 1);
+        assert!(tree.iter().map(|(k, _)| *k).eq(0..100));
+    }
+
+    #[test]
+    fn remove_borrow_merge() {
+        // 阶数3最容易触发借和合并
+        for order in [3, 4, 5, 8] {
+            let mut tree: BPlusTree<u64, u64> = BPlusTree::new(order);
+            for i in 0..200 {
+                tree.insert(i, i);
+            }
+            // 交错删除, 两边的兄弟都会被借
+            for i in (0..200).step_by(2).chain((1..200).rev().step_by(2)) {
+                assert_eq!(tree.remove(&i), Some(i));
+                assert_eq!(tree.remove(&i), None);
+                tree.check_invariants().unwrap();
+            }
+            assert!(tree.is_empty());
+            assert_eq!(tree.height(), 1);
+        }
+    }
+
+    #[test]
+    fn random() {
+        for order in [3, 4, 7, 16] {
+            let mut tree = BPlusTree::new(order);
+            let mut expect = BTreeMap::new();
+            for _ in 0..4096 {
+                let k = rand::random::<u64>() % 512;
+                if rand::random::<u64>().is_multiple_of(3) {
+                    assert_eq!(tree.remove(&k), expect.remove(&k));
+                } else {
+                    assert_eq!(tree.insert(k, k + 1), expect.insert(k, k + 1));
+                }
+                tree.check_invariants().unwrap();
+            }
+            assert!(tree.iter().eq(expect.iter()));
+        }
+    }
+
+    #[test]
+    fn range() {
+        let tree: BPlusTree<u64, u64> = BPlusTree::bulk_load(4, (0..100).map(|i| (i * 2, i)));
+        let expect: BTreeMap<u64, u64> = (0..100).map(|i| (i * 2, i)).collect();
+        for _ in 0..256 {
+            let a = rand::random::<u64>() % 210;
+            let b = a + rand::random::<u64>() % 30;
+            assert!(tree.range(a..b).eq(expect.range(a..b)));
+            assert!(tree.range(a..=b).eq(expect.range(a..=b)));
+            assert!(tree.range(a..).eq(expect.range(a..)));
+            assert!(tree.range(..b).eq(expect.range(..b)));
+        }
+        assert_eq!(tree.range(4..4).count(), 0);
+        assert_eq!(tree.range(4..=4).count(), 1);
+        assert_eq!(tree.range(1000..).count(), 0);
+    }
+
+    #[test]
+    fn bulk_load() {
+        for order in [3, 4, 5, 64] {
+            for n in [0, 1, 2, 3, 10, 100, 1000] {
+                let mut tree = BPlusTree::bulk_load(order, (0..n).map(|i| (i, i)));
+                tree.check_invariants().unwrap();
+                assert_eq!(tree.len(), n as usize);
+                assert!(tree.iter().map(|(k, _)| *k).eq(0..n));
+                // 建好之后增删也没问题
+                tree.insert(n, n);
+                tree.remove(&0);
+                tree.check_invariants().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "not strictly increasing")]
+    fn bulk_load_unsorted() {
+        BPlusTree::bulk_load(4, [(2, ()), (1, ())]);
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use lru::LruCache;
+
+pub const PAGE_SIZE: usize = 4096;
+
+pub type PageId = u32;
+
+/// 帧里没有页, 不会出现在page_table里
+const NO_PAGE: PageId = PageId::MAX;
+
+/// 缓存一个磁盘页
+struct Frame {
+    page_id: PageId,
+    data: Box<[u8; PAGE_SIZE]>,
+    // 有几个人正在用, 不为0时不能换出
+    pin: usize,
+    // 改过, 换出前要写回
+    dirty: bool,
+}
+
+/// 缓冲池: 固定数量的帧缓存文件中的页
+///  - `fetch_page`/`new_page`钉住(pin)一页, 用完`unpin_page`; 钉住的页不会被换出
+///  - pin减到0的帧放进LRU, 没有空帧时换出最久没用的, 脏页先写回
+///  - LRU里只放没被钉住的帧, 直接复用`lru`的淘汰逻辑: 钉住时`pop`, 换出时`pop_lru`
+pub struct BufferPool {
+    file: File,
+    frames: Vec<Frame>,
+    // 页号 -> 帧下标
+    page_table: HashMap<PageId, usize>,
+    // 还没用过的帧
+    free: Vec<usize>,
+    replacer: LruCache<usize, ()>,
+    reads: usize,
+    writes: usize,
+}
+
+impl BufferPool {
+    pub fn new(file: File, pool_size: usize) -> Self {
+        assert!(pool_size > 0);
+        Self {
+            file,
+            frames: Vec::with_capacity(pool_size),
+            page_table: HashMap::new(),
+            free: (0..pool_size).rev().collect(),
+            replacer: LruCache::new(pool_size),
+            reads: 0,
+            writes: 0,
+        }
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.frames.len() + self.free.len()
+    }
+
+    /// 从磁盘读了几页
+    pub fn reads(&self) -> usize {
+        self.reads
+    }
+
+    /// 往磁盘写了几页
+    pub fn writes(&self) -> usize {
+        self.writes
+    }
+
+    /// 正被钉住的页数
+    pub fn pinned(&self) -> usize {
+        self.frames.iter().filter(|f| f.pin > 0).count()
+    }
+
+    /// 钉住一页, 不在池里时从磁盘读
+    pub fn fetch_page(&mut self, page_id: PageId) -> io::Result<()> {
+        if let Some(&i) = self.page_table.get(&page_id) {
+            self.pin(i);
+            return Ok(());
+        }
+        let i = self.victim()?;
+        let frame = &mut self.frames[i];
+        let read = self.file
+            .seek(SeekFrom::Start(page_id as u64 * PAGE_SIZE as u64))
+            .and_then(|_| self.file.read_exact(&mut frame.data[..]));
+        if let Err(e) = read {
+            // ⭐帧已经在frames里了, 不能再放回free; 当成没钉住的空帧放回LRU, 下次换出时直接复用
+            frame.page_id = NO_PAGE;
+            frame.dirty = false;
+            self.replacer.put(i, ());
+            return Err(e);
+        }
+        self.reads += 1;
+        frame.page_id = page_id;
+        frame.pin = 1;
+        frame.dirty = false;
+        self.page_table.insert(page_id, i);
+        Ok(())
+    }
+
+    /// 钉住一个刚分配的页, 内容清零, 不用读盘
+    ///  文件里可能还没有这一页, 换出时写回就有了
+    pub fn new_page(&mut self, page_id: PageId) -> io::Result<()> {
+        let i = match self.page_table.get(&page_id) {
+            Some(&i) => {
+                self.pin(i);
+                i
+            }
+            None => {
+                let i = self.victim()?;
+                self.frames[i].page_id = page_id;
+                self.frames[i].pin = 1;
+                self.page_table.insert(page_id, i);
+                i
+            }
+        };
+        let frame = &mut self.frames[i];
+        frame.data.fill(0);
+        frame.dirty = true;
+        Ok(())
+    }
+
+    /// 用完了, pin减到0时可以被换出
+    pub fn unpin_page(&mut self, page_id: PageId) {
+        let i = self.page_table[&page_id];
+        let frame = &mut self.frames[i];
+        assert!(frame.pin > 0, "BufferPool::unpin_page: page {} is not pinned", page_id);
+        frame.pin -= 1;
+        if frame.pin == 0 {
+            self.replacer.put(i, ());
+        }
+    }
+
+    /// 只能读钉住的页
+    pub fn page(&self, page_id: PageId) -> &[u8; PAGE_SIZE] {
+        let frame = &self.frames[self.pinned_frame(page_id)];
+        &frame.data
+    }
+
+    /// 同page, 顺便标脏
+    pub fn page_mut(&mut self, page_id: PageId) -> &mut [u8; PAGE_SIZE] {
+        let i = self.pinned_frame(page_id);
+        let frame = &mut self.frames[i];
+        frame.dirty = true;
+        &mut frame.data
+    }
+
+    /// 所有脏页写回
+    pub fn flush_all(&mut self) -> io::Result<()> {
+        for i in 0..self.frames.len() {
+            self.write_back(i)?;
+        }
+        self.file.sync_data()
+    }
+
+    fn pinned_frame(&self, page_id: PageId) -> usize {
+        match self.page_table.get(&page_id) {
+            Some(&i) if self.frames[i].pin > 0 => i,
+            _ => panic!("BufferPool: page {} is not pinned", page_id),
+        }
+    }
+
+    fn pin(&mut self, i: usize) {
+        let frame = &mut self.frames[i];
+        if frame.pin == 0 {
+            self.replacer.pop(&i);
+        }
+        frame.pin += 1;
+    }
+
+    /// ⭐找一个能用的帧: 先用空帧, 再换出LRU里最久没用的, 都没有说明全被钉住了
+    fn victim(&mut self) -> io::Result<usize> {
+        if let Some(i) = self.free.pop() {
+            self.frames.push(Frame { page_id: NO_PAGE, data: Box::new([0; PAGE_SIZE]), pin: 0, dirty: false });
+            debug_assert_eq!(i, self.frames.len() - 1);
+            return Ok(i);
+        }
+        let Some((i, ())) = self.replacer.pop_lru() else {
+            return Err(io::Error::other("buffer pool: all pages are pinned"));
+        };
+        if let Err(e) = self.write_back(i) {
+            // 没换出去, 放回去下次再试
+            self.replacer.put(i, ());
+            return Err(e);
+        }
+        self.page_table.remove(&self.frames[i].page_id);
+        Ok(i)
+    }
+
+    fn write_back(&mut self, i: usize) -> io::Result<()> {
+        let frame = &mut self.frames[i];
+        if frame.dirty {
+            self.file.seek(SeekFrom::Start(frame.page_id as u64 * PAGE_SIZE as u64))?;
+            self.file.write_all(&frame.data[..])?;
+            self.writes += 1;
+            frame.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+/// 尽量写回, 出错也没办法了, 要确认落盘就先调`flush_all`
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        let _ = self.flush_all();
+    }
+}
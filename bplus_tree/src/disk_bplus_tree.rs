@@ -0,0 +1,851 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+use crate::buffer_pool::{BufferPool, PageId, PAGE_SIZE};
+
+const MAGIC: &[u8; 8] = b"BPTREE01";
+/// 节点页开头的kind, count和next
+const NODE_HEADER_SIZE: usize = 12;
+const LEAF: u32 = 1;
+const INTERNAL: u32 = 2;
+/// 0是头页, 所以可以当空指针用
+const NIL: PageId = 0;
+
+/// 定长编码: 节点是定长的页, 所以kv也要定长才能算出一页放几个
+pub trait Codec: Sized {
+    const SIZE: usize;
+    fn encode(&self, buf: &mut [u8]);
+    fn decode(buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_codec {
+    ($($t:ty),*) => {
+        $(
+            impl Codec for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+                fn encode(&self, buf: &mut [u8]) {
+                    buf[..Self::SIZE].copy_from_slice(&self.to_le_bytes());
+                }
+                fn decode(buf: &[u8]) -> Self {
+                    <$t>::from_le_bytes(buf[..Self::SIZE].try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_codec!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl<const N: usize> Codec for [u8; N] {
+    const SIZE: usize = N;
+    fn encode(&self, buf: &mut [u8]) {
+        buf[..N].copy_from_slice(self);
+    }
+    fn decode(buf: &[u8]) -> Self {
+        buf[..N].try_into().unwrap()
+    }
+}
+
+/// 节点页解码到内存中的样子
+///  页布局: kind: u32 | count: u32 | next: u32 | 内容
+///   - 叶子: count个(key, value)
+///   - 内部节点: order个孩子页号的位置, 后面是key, 这样key的偏移是固定的
+enum Node<K, V> {
+    Internal { keys: Vec<K>, children: Vec<PageId> },
+    Leaf { keys: Vec<K>, values: Vec<V>, next: PageId },
+}
+
+impl<K: Codec, V: Codec> Node<K, V> {
+    fn len(&self) -> usize {
+        match self {
+            Node::Internal { keys, .. } | Node::Leaf { keys, .. } => keys.len(),
+        }
+    }
+
+    fn decode(page: &[u8], order: usize) -> Self {
+        let count = u32::decode(&page[4..]) as usize;
+        let body = &page[NODE_HEADER_SIZE..];
+        if u32::decode(page) == LEAF {
+            let (keys, values) = body
+                .chunks_exact(K::SIZE + V::SIZE)
+                .take(count)
+                .map(|slot| (K::decode(slot), V::decode(&slot[K::SIZE..])))
+                .unzip();
+            Node::Leaf { keys, values, next: u32::decode(&page[8..]) }
+        } else {
+            let children = body.chunks_exact(4).take(count + 1).map(u32::decode).collect();
+            let keys = body[order * 4..].chunks_exact(K::SIZE).take(count).map(K::decode).collect();
+            Node::Internal { keys, children }
+        }
+    }
+
+    fn encode(&self, page: &mut [u8], order: usize) {
+        (self.len() as u32).encode(&mut page[4..]);
+        let body = &mut page[NODE_HEADER_SIZE..];
+        match self {
+            Node::Leaf { keys, values, next } => {
+                let slots = body.chunks_exact_mut(K::SIZE + V::SIZE);
+                for ((k, v), slot) in keys.iter().zip(values).zip(slots) {
+                    k.encode(slot);
+                    v.encode(&mut slot[K::SIZE..]);
+                }
+                LEAF.encode(page);
+                next.encode(&mut page[8..]);
+            }
+            Node::Internal { keys, children } => {
+                for (child, slot) in children.iter().zip(body.chunks_exact_mut(4)) {
+                    child.encode(slot);
+                }
+                for (k, slot) in keys.iter().zip(body[order * 4..].chunks_exact_mut(K::SIZE)) {
+                    k.encode(slot);
+                }
+                INTERNAL.encode(page);
+            }
+        }
+    }
+}
+
+/// 磁盘版B+树
+///
+/// 文件按PAGE_SIZE分页:
+///  - 第0页是头页: magic, kv大小, 阶数, 根页号, 页数, 空闲页链表头, kv总数
+///  - 其他页是节点页或空闲页, 空闲页的前4字节是下一个空闲页号(0表示没有)
+///
+/// 所有页都经过缓冲池读写, 只有池大小那么多页在内存里, 所以能索引比内存大的数据
+/// 节点读出来解码成`Node`, 改完再编码写回; 头页在内存中缓存一份, `flush`时写回
+/// 算法和`BPlusTree`一样, 只是节点换成了页号
+pub struct DiskBPlusTree<K, V> {
+    pool: BufferPool,
+    order: usize,
+    root: PageId,
+    page_count: u32,
+    free_head: PageId,
+    len: usize,
+    marker: PhantomData<(K, V)>,
+}
+
+impl<K: Codec + Ord, V: Codec> DiskBPlusTree<K, V> {
+    /// 一页能放下的最大阶数: 叶子放order - 1个kv, 内部节点放order个孩子和order - 1个key
+    pub fn max_order() -> usize {
+        let leaf = (PAGE_SIZE - NODE_HEADER_SIZE) / (K::SIZE + V::SIZE) + 1;
+        let internal = (PAGE_SIZE - NODE_HEADER_SIZE + K::SIZE) / (4 + K::SIZE);
+        leaf.min(internal)
+    }
+
+    /// 新建索引文件, 已存在则清空
+    ///  pool_size: 缓冲池最多缓存几页, 范围扫描要钉住一页, 所以至少2
+    pub fn create<P: AsRef<Path>>(path: P, order: usize, pool_size: usize) -> io::Result<Self> {
+        assert!(order >= 3 && order <= Self::max_order(), "DiskBPlusTree: order must be in 3..={}", Self::max_order());
+        assert!(pool_size >= 2);
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        let mut tree = Self {
+            pool: BufferPool::new(file, pool_size),
+            order,
+            root: NIL,
+            page_count: 1,
+            free_head: NIL,
+            len: 0,
+            marker: PhantomData,
+        };
+        tree.pool.new_page(0)?;
+        tree.pool.unpin_page(0);
+        tree.root = tree.alloc_page()?;
+        tree.write_node(tree.root, &Node::Leaf { keys: Vec::new(), values: Vec::new(), next: NIL })?;
+        tree.flush()?;
+        Ok(tree)
+    }
+
+    /// 打开已有的索引文件, 从头页恢复
+    pub fn open<P: AsRef<Path>>(path: P, pool_size: usize) -> io::Result<Self> {
+        assert!(pool_size >= 2);
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut pool = BufferPool::new(file, pool_size);
+        pool.fetch_page(0)?;
+        let page = pool.page(0);
+        let header = (
+            &page[0..8] == MAGIC,
+            u32::decode(&page[8..]) as usize,
+            u32::decode(&page[12..]) as usize,
+            u32::decode(&page[16..]) as usize,
+            u32::decode(&page[20..]),
+            u32::decode(&page[24..]),
+            u32::decode(&page[28..]),
+            u64::decode(&page[32..]) as usize,
+        );
+        pool.unpin_page(0);
+
+        let (magic, k_size, v_size, order, root, page_count, free_head, len) = header;
+        if !magic {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a b+ tree file"));
+        }
+        if k_size != K::SIZE || v_size != V::SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "key/value size mismatch"));
+        }
+        if order < 3 || order > Self::max_order() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad order"));
+        }
+        Ok(Self { pool, order, root, page_count, free_head, len, marker: PhantomData })
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 文件里有几页, 包括头页和空闲页
+    pub fn page_count(&self) -> usize {
+        self.page_count as usize
+    }
+
+    pub fn pool(&self) -> &BufferPool {
+        &self.pool
+    }
+
+    /// 头页和所有脏页写回
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.pool.flush_all()
+    }
+
+    fn min_keys(&self) -> usize {
+        (self.order - 1) / 2
+    }
+
+    pub fn get(&mut self, key: &K) -> io::Result<Option<V>> {
+        let mut id = self.root;
+        loop {
+            match self.read_node(id)? {
+                Node::Internal { keys, children } => id = children[keys.partition_point(|k| k <= key)],
+                Node::Leaf { keys, mut values, .. } => {
+                    return Ok(keys.binary_search(key).ok().map(|i| values.swap_remove(i)));
+                }
+            }
+        }
+    }
+
+    pub fn contains_key(&mut self, key: &K) -> io::Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// key已存在时覆盖value, 返回旧value
+    pub fn insert(&mut self, key: K, value: V) -> io::Result<Option<V>> {
+        let (old, split) = self.insert_rec(self.root, key, value)?;
+        if let Some((sep, right)) = split {
+            let root = self.alloc_page()?;
+            self.write_node(root, &Node::Internal { keys: vec![sep], children: vec![self.root, right] })?;
+            self.root = root;
+        }
+        if old.is_none() {
+            self.len += 1;
+        }
+        Ok(old)
+    }
+
+    /// 同`BPlusTree::insert_rec`, 节点在递归前读出来, 递归不会改它
+    #[allow(clippy::type_complexity)]
+    fn insert_rec(&mut self, id: PageId, key: K, value: V) -> io::Result<(Option<V>, Option<(K, PageId)>)> {
+        let max = self.order - 1;
+        let mut node = self.read_node(id)?;
+        let split = match &mut node {
+            Node::Leaf { keys, values, next } => {
+                match keys.binary_search(&key) {
+                    Ok(i) => {
+                        let old = std::mem::replace(&mut values[i], value);
+                        self.write_node(id, &node)?;
+                        return Ok((Some(old), None));
+                    }
+                    Err(i) => {
+                        keys.insert(i, key);
+                        values.insert(i, value);
+                    }
+                }
+                if keys.len() <= max {
+                    None
+                } else {
+                    // 叶子分裂: 右半边的第一个key复制一份给父节点
+                    let mid = keys.len() / 2;
+                    let right_keys = keys.split_off(mid);
+                    let sep = K::decode(&encoded(&right_keys[0]));
+                    let right = Node::Leaf { keys: right_keys, values: values.split_off(mid), next: *next };
+                    let right_id = self.alloc_page()?;
+                    self.write_node(right_id, &right)?;
+                    *next = right_id;
+                    Some((sep, right_id))
+                }
+            }
+            Node::Internal { keys, children } => {
+                let i = keys.partition_point(|k| k <= &key);
+                let (old, split) = self.insert_rec(children[i], key, value)?;
+                let Some((sep, new_child)) = split else { return Ok((old, None)) };
+                keys.insert(i, sep);
+                children.insert(i + 1, new_child);
+                if keys.len() <= max {
+                    None
+                } else {
+                    // 内部节点分裂: 中间的key移到父节点, 不留副本
+                    let mid = keys.len() / 2;
+                    let right = Node::Internal { keys: keys.split_off(mid + 1), children: children.split_off(mid + 1) };
+                    let sep = keys.pop().unwrap();
+                    let right_id = self.alloc_page()?;
+                    self.write_node(right_id, &right)?;
+                    Some((sep, right_id))
+                }
+            }
+        };
+        self.write_node(id, &node)?;
+        Ok((None, split))
+    }
+
+    /// 返回被删除的value
+    pub fn remove(&mut self, key: &K) -> io::Result<Option<V>> {
+        let Some((value, _)) = self.remove_rec(self.root, key)? else { return Ok(None) };
+        self.len -= 1;
+        if let Node::Internal { keys, children } = self.read_node(self.root)? {
+            if keys.is_empty() {
+                self.free_page(self.root)?;
+                self.root = children[0];
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /// 返回(value, 删完后这个节点的key数), 父节点据此判断要不要调整
+    fn remove_rec(&mut self, id: PageId, key: &K) -> io::Result<Option<(V, usize)>> {
+        let mut node = self.read_node(id)?;
+        let value = match &mut node {
+            Node::Leaf { keys, values, .. } => {
+                let Ok(i) = keys.binary_search(key) else { return Ok(None) };
+                keys.remove(i);
+                values.remove(i)
+            }
+            Node::Internal { keys, children } => {
+                let i = keys.partition_point(|k| k <= key);
+                let Some((value, child_len)) = self.remove_rec(children[i], key)? else { return Ok(None) };
+                if child_len >= self.min_keys() {
+                    return Ok(Some((value, keys.len())));
+                }
+                self.fix_underflow(keys, children, i)?;
+                value
+            }
+        };
+        self.write_node(id, &node)?;
+        Ok(Some((value, node.len())))
+    }
+
+    /// 同`BPlusTree::fix_underflow`, 父节点在内存里, 由调用者写回
+    fn fix_underflow(&mut self, keys: &mut Vec<K>, children: &mut Vec<PageId>, i: usize) -> io::Result<()> {
+        let min = self.min_keys();
+        let mut child = self.read_node(children[i])?;
+        if i > 0 {
+            let mut left = self.read_node(children[i - 1])?;
+            if left.len() > min {
+                Self::borrow(&mut left, &mut child, &mut keys[i - 1], true);
+                self.write_node(children[i - 1], &left)?;
+                return self.write_node(children[i], &child);
+            }
+        }
+        if i + 1 < children.len() {
+            let mut right = self.read_node(children[i + 1])?;
+            if right.len() > min {
+                Self::borrow(&mut child, &mut right, &mut keys[i], false);
+                self.write_node(children[i], &child)?;
+                return self.write_node(children[i + 1], &right);
+            }
+        }
+        // 都没有多的: 合并到左边
+        let i = if i > 0 { i - 1 } else { i };
+        let sep = keys.remove(i);
+        let right_id = children.remove(i + 1);
+        let mut left = self.read_node(children[i])?;
+        match (&mut left, self.read_node(right_id)?) {
+            (Node::Leaf { keys, values, next }, Node::Leaf { keys: rk, values: rv, next: rn }) => {
+                keys.extend(rk);
+                values.extend(rv);
+                *next = rn;
+            }
+            (Node::Internal { keys, children }, Node::Internal { keys: rk, children: rc }) => {
+                keys.push(sep);
+                keys.extend(rk);
+                children.extend(rc);
+            }
+            _ => unreachable!(),
+        }
+        self.write_node(children[i], &left)?;
+        self.free_page(right_id)
+    }
+
+    /// 相邻两个兄弟之间挪一个, from_left: 从左往右挪, 否则从右往左
+    ///  sep是父节点里它们之间的分隔key
+    fn borrow(left: &mut Node<K, V>, right: &mut Node<K, V>, sep: &mut K, from_left: bool) {
+        match (left, right) {
+            (Node::Leaf { keys: lk, values: lv, .. }, Node::Leaf { keys: rk, values: rv, .. }) => {
+                if from_left {
+                    rk.insert(0, lk.pop().unwrap());
+                    rv.insert(0, lv.pop().unwrap());
+                } else {
+                    lk.push(rk.remove(0));
+                    lv.push(rv.remove(0));
+                }
+                *sep = K::decode(&encoded(&rk[0]));
+            }
+            (Node::Internal { keys: lk, children: lc }, Node::Internal { keys: rk, children: rc }) => {
+                if from_left {
+                    let up = lk.pop().unwrap();
+                    rk.insert(0, std::mem::replace(sep, up));
+                    rc.insert(0, lc.pop().unwrap());
+                } else {
+                    let up = rk.remove(0);
+                    lk.push(std::mem::replace(sep, up));
+                    lc.push(rc.remove(0));
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// 顺着叶子链表扫描, 扫描时钉住当前叶子
+    ///  迭代器借走了整棵树, 因为读页要改缓冲池
+    pub fn range<R: RangeBounds<K>>(&mut self, range: R) -> io::Result<RangeScan<'_, K, V>> {
+        let start = range.start_bound();
+        let mut id = self.root;
+        let idx = loop {
+            match self.read_node(id)? {
+                Node::Internal { keys, children } => {
+                    id = match start {
+                        Bound::Included(s) | Bound::Excluded(s) => children[keys.partition_point(|k| k <= s)],
+                        Bound::Unbounded => children[0],
+                    };
+                }
+                Node::Leaf { keys, .. } => {
+                    break match start {
+                        Bound::Included(s) => keys.partition_point(|k| k < s),
+                        Bound::Excluded(s) => keys.partition_point(|k| k <= s),
+                        Bound::Unbounded => 0,
+                    };
+                }
+            }
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => Bound::Included(encoded(e)),
+            Bound::Excluded(e) => Bound::Excluded(encoded(e)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        self.pool.fetch_page(id)?;
+        Ok(RangeScan { tree: self, leaf: id, idx, end, marker: PhantomData })
+    }
+
+    pub fn iter(&mut self) -> io::Result<RangeScan<'_, K, V>> {
+        self.range(..)
+    }
+
+    /// 树高, 只有根为1
+    pub fn height(&mut self) -> io::Result<usize> {
+        let mut height = 1;
+        let mut id = self.root;
+        while let Node::Internal { children, .. } = self.read_node(id)? {
+            id = children[0];
+            height += 1;
+        }
+        Ok(height)
+    }
+
+    /// 检查结构, 同`BPlusTree::check_invariants`
+    ///  另外检查没有页还被钉住, 以及每一页要么在树里要么在空闲链表里(没有泄漏)
+    pub fn check_invariants(&mut self) -> Result<(), String> {
+        let mut leaves = Vec::new();
+        let mut pages = self.check_node(self.root, None, None, 0, &mut leaves)?;
+        let mut free = self.free_head;
+        while free != NIL {
+            self.pool.fetch_page(free).map_err(|e| e.to_string())?;
+            let next = u32::decode(self.pool.page(free));
+            self.pool.unpin_page(free);
+            free = next;
+            pages += 1;
+        }
+        if pages + 1 != self.page_count as usize {
+            return Err(format!("{} pages reachable, file has {}", pages + 1, self.page_count));
+        }
+        if leaves.windows(2).any(|w| w[0].1 != w[1].1) {
+            return Err("leaves at different depths".to_string());
+        }
+        let mut len = 0;
+        for (i, &(leaf, _)) in leaves.iter().enumerate() {
+            let Node::Leaf { keys, next, .. } = self.read_node(leaf).map_err(|e| e.to_string())? else { unreachable!() };
+            let expect = leaves.get(i + 1).map_or(NIL, |l| l.0);
+            if next != expect {
+                return Err(format!("leaf {} links to {}, expected {}", leaf, next, expect));
+            }
+            len += keys.len();
+        }
+        if len != self.len {
+            return Err(format!("len {} != {} kv in leaves", self.len, len));
+        }
+        if self.pool.pinned() != 0 {
+            return Err(format!("{} pages still pinned", self.pool.pinned()));
+        }
+        Ok(())
+    }
+
+    fn check_node(&mut self, id: PageId, lo: Option<&K>, hi: Option<&K>, depth: usize, leaves: &mut Vec<(PageId, usize)>) -> Result<usize, String> {
+        let node = self.read_node(id).map_err(|e| e.to_string())?;
+        let len = node.len();
+        if len > self.order - 1 {
+            return Err(format!("page {} overflows: {} keys", id, len));
+        }
+        if id != self.root && len < self.min_keys() {
+            return Err(format!("page {} underflows: {} keys", id, len));
+        }
+        let keys = match &node {
+            Node::Internal { keys, .. } | Node::Leaf { keys, .. } => keys,
+        };
+        if keys.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(format!("page {} keys out of order", id));
+        }
+        if keys.first().is_some_and(|k| lo.is_some_and(|lo| k < lo)) || keys.last().is_some_and(|k| hi.is_some_and(|hi| k >= hi)) {
+            return Err(format!("page {} keys out of parent's range", id));
+        }
+        // 返回子树的页数
+        let mut pages = 1;
+        match &node {
+            Node::Leaf { .. } => leaves.push((id, depth)),
+            Node::Internal { keys, children } => {
+                if keys.is_empty() {
+                    return Err(format!("internal page {} has no keys", id));
+                }
+                for (i, &child) in children.iter().enumerate() {
+                    let lo = if i == 0 { lo } else { Some(&keys[i - 1]) };
+                    let hi = keys.get(i).or(hi);
+                    pages += self.check_node(child, lo, hi, depth + 1, leaves)?;
+                }
+            }
+        }
+        Ok(pages)
+    }
+
+    fn read_node(&mut self, id: PageId) -> io::Result<Node<K, V>> {
+        self.pool.fetch_page(id)?;
+        let node = Node::decode(self.pool.page(id), self.order);
+        self.pool.unpin_page(id);
+        Ok(node)
+    }
+
+    fn write_node(&mut self, id: PageId, node: &Node<K, V>) -> io::Result<()> {
+        self.pool.fetch_page(id)?;
+        node.encode(self.pool.page_mut(id), self.order);
+        self.pool.unpin_page(id);
+        Ok(())
+    }
+
+    /// 优先复用空闲页, 否则追加到文件末尾
+    ///  新页直接在池里清零, 不用读盘
+    fn alloc_page(&mut self) -> io::Result<PageId> {
+        let page_id = if self.free_head == NIL {
+            self.page_count += 1;
+            self.page_count - 1
+        } else {
+            let page_id = self.free_head;
+            self.pool.fetch_page(page_id)?;
+            self.free_head = u32::decode(self.pool.page(page_id));
+            self.pool.unpin_page(page_id);
+            page_id
+        };
+        self.pool.new_page(page_id)?;
+        self.pool.unpin_page(page_id);
+        Ok(page_id)
+    }
+
+    fn free_page(&mut self, page_id: PageId) -> io::Result<()> {
+        self.pool.new_page(page_id)?;
+        self.free_head.encode(self.pool.page_mut(page_id));
+        self.pool.unpin_page(page_id);
+        self.free_head = page_id;
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        self.pool.fetch_page(0)?;
+        let page = self.pool.page_mut(0);
+        page[0..8].copy_from_slice(MAGIC);
+        (K::SIZE as u32).encode(&mut page[8..]);
+        (V::SIZE as u32).encode(&mut page[12..]);
+        (self.order as u32).encode(&mut page[16..]);
+        self.root.encode(&mut page[20..]);
+        self.page_count.encode(&mut page[24..]);
+        self.free_head.encode(&mut page[28..]);
+        (self.len as u64).encode(&mut page[32..]);
+        self.pool.unpin_page(0);
+        Ok(())
+    }
+}
+
+/// 尽量写回头页, 要确认落盘就先调`flush`
+impl<K, V> Drop for DiskBPlusTree<K, V> {
+    fn drop(&mut self) {
+        if self.pool.fetch_page(0).is_ok() {
+            let page = self.pool.page_mut(0);
+            self.root.encode(&mut page[20..]);
+            self.page_count.encode(&mut page[24..]);
+            self.free_head.encode(&mut page[28..]);
+            (self.len as u64).encode(&mut page[32..]);
+            self.pool.unpin_page(0);
+        }
+    }
+}
+
+/// K只要求Codec, 拷贝一份就编码再解码
+fn encoded<K: Codec>(key: &K) -> Vec<u8> {
+    let mut buf = vec![0; K::SIZE];
+    key.encode(&mut buf);
+    buf
+}
+
+/// 范围扫描, 当前叶子一直钉在池里, 换到下一个叶子时才放开
+pub struct RangeScan<'a, K: Codec + Ord, V: Codec> {
+    tree: &'a mut DiskBPlusTree<K, V>,
+    // NIL为走完了
+    leaf: PageId,
+    idx: usize,
+    // 终点编码后存着, 省得要求K: Clone
+    end: Bound<Vec<u8>>,
+    marker: PhantomData<V>,
+}
+
+impl<K: Codec + Ord, V: Codec> RangeScan<'_, K, V> {
+    fn finish(&mut self) {
+        if self.leaf != NIL {
+            self.tree.pool.unpin_page(self.leaf);
+            self.leaf = NIL;
+        }
+    }
+}
+
+impl<K: Codec + Ord, V: Codec> Iterator for RangeScan<'_, K, V> {
+    type Item = io::Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.leaf == NIL {
+                return None;
+            }
+            let page = self.tree.pool.page(self.leaf);
+            let count = u32::decode(&page[4..]) as usize;
+            if self.idx < count {
+                let slot = &page[NODE_HEADER_SIZE + self.idx * (K::SIZE + V::SIZE)..];
+                let key = K::decode(slot);
+                let in_range = match &self.end {
+                    Bound::Included(e) => key <= K::decode(e),
+                    Bound::Excluded(e) => key < K::decode(e),
+                    Bound::Unbounded => true,
+                };
+                if !in_range {
+                    self.finish();
+                    return None;
+                }
+                self.idx += 1;
+                return Some(Ok((key, V::decode(&slot[K::SIZE..]))));
+            }
+            // 先钉住下一个叶子, 再放开当前的
+            let next = u32::decode(&page[8..]);
+            if next != NIL {
+                if let Err(e) = self.tree.pool.fetch_page(next) {
+                    self.finish();
+                    return Some(Err(e));
+                }
+            }
+            self.finish();
+            self.leaf = next;
+            self.idx = 0;
+        }
+    }
+}
+
+impl<K: Codec + Ord, V: Codec> Drop for RangeScan<'_, K, V> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiskBPlusTree;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    /// 每个测试用自己的文件, 测试是并行跑的
+    fn disk_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("bplus-tree-{}-{}.db", std::process::id(), name))
+    }
+
+    #[test]
+    fn small_pool() {
+        // 池里只有4页, 几千个key肯定要不停换出
+        let path = disk_path("small_pool");
+        let mut tree = DiskBPlusTree::<u64, u64>::create(&path, 8, 4).unwrap();
+        for i in 0..2000 {
+            assert_eq!(tree.insert(i * 7 % 2000, i).unwrap(), None);
+        }
+        assert_eq!(tree.insert(0, 42).unwrap(), Some(0));
+        tree.check_invariants().unwrap();
+        assert!(tree.height().unwrap() > 3);
+        assert!(tree.pool().reads() > 0 && tree.pool().writes() > 0);
+        for i in 1..2000 {
+            assert_eq!(tree.get(&(i * 7 % 2000)).unwrap(), Some(i));
+        }
+        assert_eq!(tree.get(&2000).unwrap(), None);
+        assert_eq!(tree.pool().pinned(), 0);
+
+        for i in 0..2000 {
+            assert!(tree.remove(&i).unwrap().is_some());
+        }
+        tree.check_invariants().unwrap();
+        assert!(tree.is_empty());
+        assert_eq!(tree.height().unwrap(), 1);
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn random() {
+        let path = disk_path("random");
+        let mut tree = DiskBPlusTree::<u32, u64>::create(&path, 5, 8).unwrap();
+        let mut expect = BTreeMap::new();
+        for _ in 0..4000 {
+            let k = (rand::random::<u64>() % 500) as u32;
+            if rand::random::<u64>().is_multiple_of(3) {
+                assert_eq!(tree.remove(&k).unwrap(), expect.remove(&k));
+            } else {
+                let v = rand::random::<u64>();
+                assert_eq!(tree.insert(k, v).unwrap(), expect.insert(k, v));
+            }
+        }
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.len(), expect.len());
+        let all: Vec<_> = tree.iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(all, expect.into_iter().collect::<Vec<_>>());
+        // 删掉的页进空闲链表, 同样的插入再来一遍只会复用, 文件不会涨
+        let mut pages = 0;
+        for round in 0..3 {
+            for k in 0..500 {
+                tree.remove(&k).unwrap();
+            }
+            tree.check_invariants().unwrap();
+            for k in 0..500 {
+                tree.insert(k, 0).unwrap();
+            }
+            if round == 0 {
+                pages = tree.page_count();
+            }
+        }
+        assert_eq!(tree.page_count(), pages);
+        tree.check_invariants().unwrap();
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn range() {
+        let path = disk_path("range");
+        let mut tree = DiskBPlusTree::<u64, u64>::create(&path, 4, 3).unwrap();
+        for i in (0..200).step_by(2) {
+            tree.insert(i, i * 10).unwrap();
+        }
+        let keys = |tree: &mut DiskBPlusTree<u64, u64>, r: std::ops::Range<u64>| -> Vec<u64> {
+            tree.range(r).unwrap().map(|kv| kv.unwrap().0).collect()
+        };
+        assert_eq!(keys(&mut tree, 10..20), vec![10, 12, 14, 16, 18]);
+        assert_eq!(keys(&mut tree, 11..12), Vec::<u64>::new());
+        assert_eq!(keys(&mut tree, 195..1000), vec![196, 198]);
+        assert_eq!(tree.range(..=4).unwrap().map(|kv| kv.unwrap()).collect::<Vec<_>>(), vec![(0, 0), (2, 20), (4, 40)]);
+        assert_eq!(tree.iter().unwrap().count(), 100);
+        // 扫描到一半扔掉也会放开钉住的叶子
+        assert_eq!(tree.iter().unwrap().nth(50).unwrap().unwrap(), (100, 1000));
+        assert_eq!(tree.pool().pinned(), 0);
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reopen() {
+        let path = disk_path("reopen");
+        {
+            let mut tree = DiskBPlusTree::<u64, [u8; 16]>::create(&path, 16, 4).unwrap();
+            for i in 0..1000u64 {
+                let mut v = [0; 16];
+                v[..8].copy_from_slice(&i.to_le_bytes());
+                tree.insert(i, v).unwrap();
+            }
+            for i in 0..100 {
+                tree.remove(&i).unwrap();
+            }
+            tree.flush().unwrap();
+        }
+        let mut tree = DiskBPlusTree::<u64, [u8; 16]>::open(&path, 4).unwrap();
+        assert_eq!(tree.len(), 900);
+        assert_eq!(tree.order(), 16);
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.get(&50).unwrap(), None);
+        assert_eq!(tree.get(&500).unwrap().unwrap()[..8], 500u64.to_le_bytes());
+        drop(tree);
+
+        // kv大小对不上
+        assert!(DiskBPlusTree::<u64, u64>::open(&path, 4).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn read_error() {
+        let path = disk_path("read_error");
+        let mut tree = DiskBPlusTree::<u64, u64>::create(&path, 4, 2).unwrap();
+        for i in 0..100 {
+            tree.insert(i, i).unwrap();
+        }
+        tree.flush().unwrap();
+        drop(tree);
+        // 读文件末尾之后的页会失败, 池子不能因此变大或者丢帧
+        let mut tree = DiskBPlusTree::<u64, u64>::open(&path, 2).unwrap();
+        for _ in 0..4 {
+            assert!(tree.pool.fetch_page(1 << 20).is_err());
+            assert_eq!(tree.pool().pool_size(), 2);
+            assert_eq!(tree.pool().pinned(), 0);
+        }
+        for i in 0..100 {
+            assert_eq!(tree.get(&i).unwrap(), Some(i));
+        }
+        tree.check_invariants().unwrap();
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn all_pinned() {
+        let path = disk_path("all_pinned");
+        let mut tree = DiskBPlusTree::<u64, u64>::create(&path, 4, 2).unwrap();
+        for i in 0..100 {
+            tree.insert(i, i).unwrap();
+        }
+        tree.flush().unwrap();
+        drop(tree);
+        // 手动钉住两页, 池满了再读就报错而不是换出钉住的页
+        let mut tree = DiskBPlusTree::<u64, u64>::open(&path, 2).unwrap();
+        let mut scan = tree.iter().unwrap();
+        assert_eq!(scan.next().unwrap().unwrap(), (0, 0));
+        scan.tree.pool.fetch_page(0).unwrap();
+        assert!(scan.find(|kv| kv.is_err()).is_some());
+        scan.tree.pool.unpin_page(0);
+        drop(scan);
+        assert_eq!(tree.pool().pinned(), 0);
+        assert_eq!(tree.get(&99).unwrap(), Some(99));
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
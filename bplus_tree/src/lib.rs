@@ -0,0 +1,7 @@
+pub mod bplus_tree;
+pub mod buffer_pool;
+pub mod disk_bplus_tree;
+
+pub use crate::bplus_tree::*;
+pub use crate::buffer_pool::*;
+pub use crate::disk_bplus_tree::*;
@@ -1,14 +1,180 @@
-pub struct BinarySearchTree<T> {
-    left: Option<Box<BinarySearchTree<T>>>,
-    right: Option<Box<BinarySearchTree<T>>>,
+use std::mem;
+
+/// 平衡策略: 每个节点多存一份B::Meta, 插入/删除回溯时对路径上的每个节点调用rebalance
+///  `Unbalanced`什么都不做, 就是原来的二叉搜索树; `Treap`按随机优先级旋转
+///  策略只能通过`rotate_left`/`rotate_right`改变树的形状, 子树大小由它们维护
+pub trait Balance: Sized {
+    type Meta;
+
+    /// 新节点的meta
+    fn meta() -> Self::Meta;
+
+    /// 子树已经调整完, 调整当前节点
+    fn rebalance<T: Ord>(tree: &mut BinarySearchTree<T, Self>);
+
+    /// 检查当前节点和孩子之间策略自己的性质, 默认没有
+    fn check<T: Ord>(_: &BinarySearchTree<T, Self>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// 不平衡, 有序插入会退化成链表
+pub struct Unbalanced;
+
+impl Balance for Unbalanced {
+    type Meta = ();
+
+    fn meta() {}
+
+    fn rebalance<T: Ord>(_: &mut BinarySearchTree<T, Self>) {}
+}
+
+/// 树堆: key满足二叉搜索树, 随机优先级满足大根堆, 期望高度O(log n)
+///  ⭐插入时新节点在叶子, 回溯时优先级比父节点大就转上去
+///  删除只搬value, 节点和优先级都留在原位, 堆的性质不会被破坏
+pub struct Treap;
+
+impl Balance for Treap {
+    type Meta = u64;
+
+    fn meta() -> u64 {
+        rand::random()
+    }
+
+    fn rebalance<T: Ord>(tree: &mut BinarySearchTree<T, Self>) {
+        let priority = |child: &Option<Box<BinarySearchTree<T, Self>>>| child.as_ref().map(|n| n.meta);
+        if priority(&tree.left) > Some(tree.meta) {
+            tree.rotate_right();
+        } else if priority(&tree.right) > Some(tree.meta) {
+            tree.rotate_left();
+        }
+    }
+
+    /// 孩子的优先级不比父节点大
+    fn check<T: Ord>(tree: &BinarySearchTree<T, Self>) -> Result<(), String> {
+        match [tree.left(), tree.right()].into_iter().flatten().find(|c| c.meta > tree.meta) {
+            Some(c) => Err(format!("child priority {} > {}", c.meta, tree.meta)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 每个节点都是一棵树, 根节点的value为None时是空树
+///  B是平衡策略, 默认不平衡
+pub struct BinarySearchTree<T, B: Balance = Unbalanced> {
+    left: Option<Box<BinarySearchTree<T, B>>>,
+    right: Option<Box<BinarySearchTree<T, B>>>,
     value: Option<T>,
+    // 子树节点数
+    size: usize,
+    meta: B::Meta,
 }
 
-impl<T> BinarySearchTree<T> 
+impl<T> BinarySearchTree<T>
 where T: Ord,
 {
     pub fn new() -> Self {
-        Self { left: None, right: None, value: None }
+        Self::with_balance()
+    }
+}
+
+impl<T, B> BinarySearchTree<T, B>
+where T: Ord, B: Balance,
+{
+    /// 指定平衡策略: `BinarySearchTree::<T, Treap>::with_balance()`
+    pub fn with_balance() -> Self {
+        Self { left: None, right: None, value: None, size: 0, meta: B::meta() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// 空树为0, 只有根为1
+    ///  遍历整棵树, O(n)
+    pub fn height(&self) -> usize {
+        if self.value.is_none() {
+            return 0;
+        }
+        let height = |child: &Option<Box<Self>>| child.as_ref().map_or(0, |n| n.height());
+        1 + height(&self.left).max(height(&self.right))
+    }
+
+    pub fn meta(&self) -> &B::Meta {
+        &self.meta
+    }
+
+    pub fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    pub fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+
+    /// 左旋: 右孩子成为新根, 旧根成为它的左孩子
+    ///  节点就是树, 根节点不在Box里, 所以交换的是节点内容
+    pub fn rotate_left(&mut self) {
+        let mut child = self.right.take().expect("rotate_left: no right child");
+        self.right = child.left.take();
+        self.update_size();
+        mem::swap(self, &mut child);
+        self.left = Some(child);
+        self.update_size();
+    }
+
+    /// 同rotate_left, 方向相反
+    pub fn rotate_right(&mut self) {
+        let mut child = self.left.take().expect("rotate_right: no left child");
+        self.left = child.right.take();
+        self.update_size();
+        mem::swap(self, &mut child);
+        self.right = Some(child);
+        self.update_size();
+    }
+
+    /// 检查结构, 出错时返回描述
+    ///  1. 每个节点的size等于重新算出来的
+    ///  2. 中序不递减(可以重复)
+    ///  3. 平衡策略自己的性质, 见`Balance::check`
+    pub fn check_invariants(&self) -> Result<(), String> {
+        if self.value.is_none() {
+            return if self.size == 0 && self.left.is_none() && self.right.is_none() {
+                Ok(())
+            } else {
+                Err("empty root has children".to_string())
+            };
+        }
+        self.check_node()?;
+        if self.iter().zip(self.iter().skip(1)).any(|(a, b)| a > b) {
+            return Err("values out of order".to_string());
+        }
+        Ok(())
+    }
+
+    /// 返回子树节点数
+    fn check_node(&self) -> Result<usize, String> {
+        if self.value.is_none() {
+            return Err("empty node below root".to_string());
+        }
+        B::check(self)?;
+        let mut size = 1;
+        for child in [&self.left, &self.right].into_iter().flatten() {
+            size += child.check_node()?;
+        }
+        if self.size != size {
+            return Err(format!("size {} != {}", self.size, size));
+        }
+        Ok(size)
+    }
+
+    fn update_size(&mut self) {
+        let size = |child: &Option<Box<Self>>| child.as_ref().map_or(0, |n| n.size);
+        self.size = 1 + size(&self.left) + size(&self.right);
     }
 
     pub fn search(&self, val: &T) -> bool {
@@ -30,9 +196,11 @@ where T: Ord,
         }
     }
 
+    /// 可以重复插入相同的值, 相同的放右边
     pub fn insert(&mut self, val: T)  {
         if self.value.is_none() {
             self.value = Some(val);
+            self.size = 1;
             return
         }
         if let Some(v) = &self.value {
@@ -46,12 +214,76 @@ where T: Ord,
             match target {
                 Some(node) => node.insert(val),
                 None => {
-                    let mut new_node = BinarySearchTree::new();
+                    let mut new_node = BinarySearchTree::with_balance();
                     new_node.value = Some(val);
+                    new_node.size = 1;
                     // TIPS: 直接操作引用, 原地修改left/right
                     *target = Some(Box::new(new_node));
                 }
             }
+            self.size += 1;
+            B::rebalance(self);
+        }
+    }
+
+    /// 删除一个等于val的值, 返回被删除的值
+    pub fn remove(&mut self, val: &T) -> Option<T> {
+        let removed = match val.cmp(self.value.as_ref()?) {
+            std::cmp::Ordering::Less => Self::remove_from(&mut self.left, val),
+            std::cmp::Ordering::Equal => Some(self.remove_root()),
+            std::cmp::Ordering::Greater => Self::remove_from(&mut self.right, val),
+        };
+        if removed.is_some() && self.value.is_some() {
+            self.update_size();
+            B::rebalance(self);
+        }
+        removed
+    }
+
+    /// 子树删空了就把孩子也删掉, 空树只能出现在根
+    fn remove_from(child: &mut Option<Box<Self>>, val: &T) -> Option<T> {
+        let node = child.as_mut()?;
+        let removed = node.remove(val);
+        if node.value.is_none() {
+            *child = None;
+        }
+        removed
+    }
+
+    /// 删除当前节点的value
+    ///  1. 没有孩子: 变成空树, 由父节点摘掉
+    ///  2. 只有一个孩子: 孩子顶替自己
+    ///  3. ⭐有两个孩子: 右子树的最小值(后继)搬上来, 节点本身不动
+    fn remove_root(&mut self) -> T {
+        match (self.left.take(), self.right.take()) {
+            (None, None) => {
+                self.size = 0;
+                self.value.take().unwrap()
+            }
+            (Some(child), None) | (None, Some(child)) => {
+                mem::replace(self, *child).value.unwrap()
+            }
+            (left, mut right) => {
+                let min = Self::take_min(&mut right);
+                self.left = left;
+                self.right = right;
+                self.value.replace(min).unwrap()
+            }
+        }
+    }
+
+    /// 摘掉子树里最小的节点, 返回它的value
+    fn take_min(child: &mut Option<Box<Self>>) -> T {
+        let node = child.as_mut().unwrap();
+        if node.left.is_some() {
+            let min = Self::take_min(&mut node.left);
+            node.update_size();
+            B::rebalance(node);
+            min
+        } else {
+            let mut node = child.take().unwrap();
+            *child = node.right.take();
+            node.value.take().unwrap()
         }
     }
 
@@ -135,17 +367,23 @@ where T: Ord,
         }
     }
 
-    pub fn iter(&self) -> BinarySearchTreeIter<T> {
+    pub fn iter(&self) -> BinarySearchTreeIter<'_, T, B> {
         BinarySearchTreeIter::new(self)
     }
 }
 
-pub struct BinarySearchTreeIter<'a, T> {
-    stack: Vec<&'a BinarySearchTree<T>>
+impl<T: Ord, B: Balance> Default for BinarySearchTree<T, B> {
+    fn default() -> Self {
+        Self::with_balance()
+    }
+}
+
+pub struct BinarySearchTreeIter<'a, T, B: Balance = Unbalanced> {
+    stack: Vec<&'a BinarySearchTree<T, B>>
 }
 
-impl<'a, T> BinarySearchTreeIter<'a, T> {
-    pub fn new(tree: &BinarySearchTree<T>) -> BinarySearchTreeIter<T> {
+impl<'a, T, B: Balance> BinarySearchTreeIter<'a, T, B> {
+    pub fn new(tree: &'a BinarySearchTree<T, B>) -> Self {
         let mut iter = BinarySearchTreeIter { stack: vec![tree] };
         // 二叉树的先序遍历, 初始先先递归调用(即压栈)
         //  dfs(root.left)
@@ -162,7 +400,7 @@ impl<'a, T> BinarySearchTreeIter<'a, T> {
     }
 }
 
-impl<'a, T> Iterator for BinarySearchTreeIter<'a, T> {
+impl<'a, T, B: Balance> Iterator for BinarySearchTreeIter<'a, T, B> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -183,7 +421,8 @@ impl<'a, T> Iterator for BinarySearchTreeIter<'a, T> {
 
 #[cfg(test)]
 mod test {
-    use super::BinarySearchTree;
+    use super::{Balance, BinarySearchTree, Treap};
+    use std::collections::BTreeMap;
 
     fn prequel_memes_tree() -> BinarySearchTree<&'static str> {
         let mut tree = BinarySearchTree::new();
@@ -295,5 +534,91 @@ mod test {
         assert_eq!(iter.next(), None);
         assert_eq!(iter.next(), None);
     }
-}
 
+    #[test]
+    fn test_remove() {
+        let mut tree = prequel_memes_tree();
+        assert_eq!(tree.len(), 7);
+        // 叶子
+        assert_eq!(tree.remove(&"you fool"), Some("you fool"));
+        // 只有一个孩子
+        assert_eq!(tree.remove(&"general kenobi"), Some("general kenobi"));
+        // 两个孩子的根
+        assert_eq!(tree.remove(&"hello there"), Some("hello there"));
+        assert_eq!(tree.remove(&"hello there"), None);
+        assert_eq!(tree.len(), 4);
+        assert_eq!(
+            tree.iter().copied().collect::<Vec<_>>(),
+            ["back away...I will deal with this jedi slime myself", "kill him", "you are a bold one", "your move"]
+        );
+        for v in tree.iter().copied().collect::<Vec<_>>() {
+            assert_eq!(tree.remove(&v), Some(v));
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.height(), 0);
+        assert!(tree.minimum().is_none());
+        tree.insert("hello there");
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn test_height() {
+        let mut tree = BinarySearchTree::new();
+        assert_eq!(tree.height(), 0);
+        // 有序插入退化成链表
+        for i in 0..100 {
+            tree.insert(i);
+        }
+        assert_eq!(tree.height(), 100);
+        assert_eq!(tree.len(), 100);
+
+        let mut treap = BinarySearchTree::<_, Treap>::with_balance();
+        for i in 0..1024 {
+            treap.insert(i);
+        }
+        assert!(treap.iter().copied().eq(0..1024));
+        assert!(treap.height() < 40, "height {}", treap.height());
+    }
+
+    fn heap_ok(tree: &BinarySearchTree<u64, Treap>) -> bool {
+        [tree.left(), tree.right()].into_iter().flatten().all(|c| c.meta() <= tree.meta() && heap_ok(c))
+    }
+
+    /// 可重复插入, 和BTreeMap计数对比
+    fn random<B: Balance>(check: impl Fn(&BinarySearchTree<u64, B>) -> bool) {
+        let mut tree = BinarySearchTree::<u64, B>::with_balance();
+        let mut expect = BTreeMap::new();
+        let mut len = 0;
+        for i in 0..4096u64 {
+            let v = Treap::meta() % 512;
+            if i % 3 == 2 {
+                let removed = tree.remove(&v);
+                match expect.get_mut(&v) {
+                    Some(n) => {
+                        assert_eq!(removed, Some(v));
+                        *n -= 1;
+                        if *n == 0 {
+                            expect.remove(&v);
+                        }
+                        len -= 1;
+                    }
+                    None => assert_eq!(removed, None),
+                }
+            } else {
+                tree.insert(v);
+                *expect.entry(v).or_insert(0) += 1;
+                len += 1;
+            }
+            assert_eq!(tree.len(), len);
+        }
+        assert!(tree.iter().copied().eq(expect.iter().flat_map(|(&v, &n)| std::iter::repeat_n(v, n))));
+        assert!(check(&tree));
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_random() {
+        random::<super::Unbalanced>(|_| true);
+        random::<Treap>(heap_ok);
+    }
+}
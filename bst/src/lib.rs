@@ -1 +1,3 @@
-mod bst;
+pub mod bst;
+
+pub use crate::bst::*;
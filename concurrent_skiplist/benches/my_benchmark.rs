@@ -1,8 +1,14 @@
+// 早期代码里的写法, 保持原样
+#![allow(dead_code, non_snake_case)]
+
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use concurrent_skiplist::safe_array_rcrefcell_skiplist::Skiplist as SafeARRSkiplist;
 use concurrent_skiplist::safe_array_rc_skiplist::Skiplist as SafeARSkiplist;
 use concurrent_skiplist::unsafe_array_skiplist::Skiplist as UnsafeASkiplst;
 use concurrent_skiplist::safe_list_skiplist::Skiplist as SafeLSkiplst;
+use concurrent_skiplist::concurrent_skiplist::Skiplist as LockFreeSkiplist;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
 
 // use concurrent_skiplist::skiplist_jj::Skiplist as SkiplistJ;
 
@@ -10,6 +16,7 @@ type K = i32;
 type V = i32;
 const SET_SIZE: usize = 1 << 10;
 const BUKCET_SIZE: usize = 1000;
+const THREADS: usize = 4;
 
 fn create_workload() -> Vec<(K, V)> {
     let mut v = vec![];
@@ -72,6 +79,60 @@ fn unsafe_array__base(workload: &Vec<(K, V)>) {
     }
 }
 
+fn lockfree_base(workload: &[(K, V)]) {
+    let s = LockFreeSkiplist::new();
+    for (k, _) in workload {
+        s.add(*k);
+    }
+    for (k, _) in workload {
+        s.search(k);
+    }
+    for (k, _) in workload {
+        s.erase(k);
+    }
+}
+
+/// 每个线程负责workload中的一段
+fn lockfree_concurrent(workload: &[(K, V)]) {
+    let s = LockFreeSkiplist::new();
+    std::thread::scope(|scope| {
+        for part in workload.chunks(workload.len().div_ceil(THREADS)) {
+            let s = &s;
+            scope.spawn(move || {
+                for (k, _) in part {
+                    s.add(*k);
+                }
+                for (k, _) in part {
+                    s.search(k);
+                }
+                for (k, _) in part {
+                    s.erase(k);
+                }
+            });
+        }
+    });
+}
+
+fn std_mutex_concurrent(workload: &[(K, V)]) {
+    let s = Mutex::new(BTreeSet::new());
+    std::thread::scope(|scope| {
+        for part in workload.chunks(workload.len().div_ceil(THREADS)) {
+            let s = &s;
+            scope.spawn(move || {
+                for (k, _) in part {
+                    s.lock().unwrap().insert(*k);
+                }
+                for (k, _) in part {
+                    s.lock().unwrap().contains(k);
+                }
+                for (k, _) in part {
+                    s.lock().unwrap().remove(k);
+                }
+            });
+        }
+    });
+}
+
 
 fn criterion_benchmark(c: &mut Criterion) {
     let workload = create_workload();
@@ -79,6 +140,9 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("safe rc array base", |b| b.iter(|| safe_array_rc_base(black_box(&workload))));
     c.bench_function("unsafe array base", |b| b.iter(|| unsafe_array__base(black_box(&workload))));
     c.bench_function("safe list base", |b| b.iter(|| safe_list_base(black_box(&workload))));
+    c.bench_function("lock free base", |b| b.iter(|| lockfree_base(black_box(&workload))));
+    c.bench_function("lock free: 4 threads", |b| b.iter(|| lockfree_concurrent(black_box(&workload))));
+    c.bench_function("std mutex btreeset: 4 threads", |b| b.iter(|| std_mutex_concurrent(black_box(&workload))));
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,292 @@
+use std::cmp::Ordering::*;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned, Shared};
+
+const MAX_HEIGHT: usize = 32;
+
+/// 一层的链表: head的塔或者节点的塔
+type Tower<T> = [Atomic<Node<T>>];
+
+/// 同unsafe_array_skiplist的塔结构, 只是next换成了原子指针
+///  next[i]的tag位为1表示该节点在第i层被逻辑删除, 之后这一层的next不会再改变
+struct Node<T> {
+    elem: T,
+    next: Box<Tower<T>>,
+    /// 引用计数 = 被链接的层数 + 插入线程持有的1
+    ///  每摘掉一层减1, 归零时说明已经没有任何一层能访问到它, 可以交给epoch回收
+    refs: AtomicUsize,
+}
+
+/// 无锁跳表
+///
+/// - 插入: 自底向上CAS建塔, 第0层CAS成功即插入成功
+/// - 删除: 自顶向下标记每层的next, 谁标记了第0层谁就删除成功, 然后通过find物理摘除
+/// - 回收: crossbeam-epoch, 节点从所有层摘除后defer_destroy
+pub struct Skiplist<T> {
+    head: [Atomic<Node<T>>; MAX_HEIGHT],
+    current_height: AtomicUsize,
+}
+
+/// find的结果: 每层的前驱塔和后继
+///  preds[i][i]就是待修改的原子指针, 满足 preds[i].elem < elem <= succs[i].elem
+struct Position<'g, T> {
+    preds: [&'g Tower<T>; MAX_HEIGHT],
+    succs: [Shared<'g, Node<T>>; MAX_HEIGHT],
+    found: bool,
+}
+
+impl<T> Node<T> {
+    fn new(elem: T, height: usize) -> Self {
+        Self {
+            elem,
+            next: (0..height).map(|_| Atomic::null()).collect(),
+            refs: AtomicUsize::new(1),
+        }
+    }
+
+    fn height(&self) -> usize {
+        self.next.len()
+    }
+}
+
+impl<T: Ord + Send + Sync + 'static> Default for Skiplist<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Send + Sync + 'static> Skiplist<T> {
+    pub fn new() -> Self {
+        Self {
+            // 哨兵
+            head: std::array::from_fn(|_| Atomic::null()),
+            current_height: AtomicUsize::new(1),
+        }
+    }
+
+    /// 无等待的查找: 只跳过被标记的节点, 不帮忙摘除
+    pub fn search(&self, target: &T) -> bool {
+        let guard = &epoch::pin();
+        let mut pred: &Tower<T> = &self.head;
+
+        for height in (0..self.current_height.load(Relaxed)).rev() {
+            let mut curr = pred[height].load(Acquire, guard);
+            while let Some(node) = unsafe { curr.as_ref() } {
+                let succ = node.next[height].load(Acquire, guard);
+                // 已被删除的节点直接跳过
+                if succ.tag() == 1 {
+                    curr = succ.with_tag(0);
+                    continue;
+                }
+                match node.elem.cmp(target) {
+                    Less => {
+                        pred = &node.next;
+                        curr = succ;
+                    },
+                    // 第height层未被标记, 说明此刻第0层也未被标记(自顶向下标记)
+                    Equal => return true,
+                    Greater => break,
+                }
+            }
+        }
+        false
+    }
+
+    /// 元素已存在时返回false
+    pub fn add(&self, target: T) -> bool {
+        let guard = &epoch::pin();
+        let height = Self::rand_height();
+        self.current_height.fetch_max(height, Relaxed);
+
+        // 1. 第0层CAS, 成功即插入成功
+        let mut new_node = Owned::new(Node::new(target, height));
+        let (node, mut pos) = loop {
+            let pos = self.find(&new_node.elem, guard);
+            if pos.found {
+                return false;
+            }
+            new_node.next[0].store(pos.succs[0], Relaxed);
+            // 先算上第0层的引用再发布, 防止发布后被摘掉时计数提前归零
+            new_node.refs.store(2, Relaxed);
+            match pos.preds[0][0].compare_exchange(pos.succs[0], new_node, Release, Relaxed, guard) {
+                Ok(node) => break (node, pos),
+                Err(e) => {
+                    new_node = e.new;
+                    new_node.refs.store(1, Relaxed);
+                },
+            }
+        };
+
+        // 2. 自底向上建塔
+        let n = unsafe { node.deref() };
+        'build: for height in 1..height {
+            loop {
+                let (pred, succ) = (pos.preds[height], pos.succs[height]);
+                // 先接后继再接前驱, 这样并发访问到新节点时一定有后继
+                // 后继被标记说明节点已经在删除了, 不用再往上建
+                let next = n.next[height].load(Acquire, guard);
+                if next.tag() == 1 {
+                    break 'build;
+                }
+                if n.next[height].compare_exchange(next, succ, AcqRel, Acquire, guard).is_err() {
+                    break 'build;
+                }
+
+                n.refs.fetch_add(1, Relaxed);
+                if pred[height].compare_exchange(succ, node, Release, Relaxed, guard).is_ok() {
+                    // 链接的同时被标记了, 删除线程的find可能已经走过去了, 自己负责摘除
+                    if n.next[height].load(Acquire, guard).tag() == 1 {
+                        self.find(&n.elem, guard);
+                        break 'build;
+                    }
+                    break;
+                }
+                // 插入线程还持有1, 不会归零
+                n.refs.fetch_sub(1, Relaxed);
+
+                // 前驱变了, 重新定位; 如果自己已经被删除了就不再建塔
+                pos = self.find(&n.elem, guard);
+                if !pos.found || pos.succs[0] != node {
+                    break 'build;
+                }
+            }
+        }
+
+        // 3. 插入线程放弃持有
+        unsafe { Self::release(node, guard) };
+        true
+    }
+
+    pub fn erase(&self, target: &T) -> bool {
+        let guard = &epoch::pin();
+        let pos = self.find(target, guard);
+        if !pos.found {
+            return false;
+        }
+        let n = unsafe { pos.succs[0].deref() };
+
+        // 自顶向下标记, 第0层之外谁标记都无所谓
+        for height in (1..n.height()).rev() {
+            let mut next = n.next[height].load(Acquire, guard);
+            while next.tag() == 0 {
+                match n.next[height].compare_exchange(next, next.with_tag(1), AcqRel, Acquire, guard) {
+                    Ok(_) => break,
+                    Err(e) => next = e.current,
+                }
+            }
+        }
+
+        // 第0层: 谁标记成功谁删除成功
+        let mut next = n.next[0].load(Acquire, guard);
+        loop {
+            if next.tag() == 1 {
+                return false;
+            }
+            match n.next[0].compare_exchange(next, next.with_tag(1), AcqRel, Acquire, guard) {
+                Ok(_) => break,
+                Err(e) => next = e.current,
+            }
+        }
+
+        // 物理摘除
+        self.find(target, guard);
+        true
+    }
+
+    /// 逐层找最大小于target的节点, 顺便摘掉路过的已标记节点
+    ///  前驱被标记时CAS会失败(期望值tag为0), 这时从头再来
+    fn find<'g>(&'g self, target: &T, guard: &'g Guard) -> Position<'g, T> {
+        'retry: loop {
+            let mut pos = Position {
+                preds: [&self.head[..]; MAX_HEIGHT],
+                succs: [Shared::null(); MAX_HEIGHT],
+                found: false,
+            };
+            let mut pred: &'g Tower<T> = &self.head;
+
+            for height in (0..self.current_height.load(Relaxed)).rev() {
+                let mut curr = pred[height].load(Acquire, guard);
+                // 前驱本身被删除了
+                if curr.tag() == 1 {
+                    continue 'retry;
+                }
+                while let Some(node) = unsafe { curr.as_ref() } {
+                    let succ = node.next[height].load(Acquire, guard);
+                    if succ.tag() == 1 {
+                        // curr在这一层被删除, 帮忙摘掉
+                        match pred[height].compare_exchange(curr, succ.with_tag(0), AcqRel, Acquire, guard) {
+                            Ok(_) => {
+                                unsafe { Self::release(curr, guard) };
+                                curr = succ.with_tag(0);
+                                continue;
+                            },
+                            Err(_) => continue 'retry,
+                        }
+                    }
+                    if node.elem < *target {
+                        pred = &node.next;
+                        curr = succ;
+                    } else {
+                        break;
+                    }
+                }
+                pos.preds[height] = pred;
+                pos.succs[height] = curr;
+            }
+
+            pos.found = unsafe { pos.succs[0].as_ref() }.is_some_and(|n| n.elem == *target);
+            return pos;
+        }
+    }
+
+    /// 放弃一个引用, 归零则交给epoch回收
+    unsafe fn release(node: Shared<'_, Node<T>>, guard: &Guard) {
+        if node.deref().refs.fetch_sub(1, AcqRel) == 1 {
+            guard.defer_destroy(node);
+        }
+    }
+
+    fn rand_height() -> usize {
+        let x = rand::random::<u32>() | 1 << (MAX_HEIGHT - 1);
+        1 + x.trailing_zeros() as usize
+    }
+
+    pub fn display(&self)
+        where T: std::fmt::Display
+    {
+        let guard = &epoch::pin();
+        for i in (0..self.current_height.load(Relaxed)).rev() {
+            let mut curr = self.head[i].load(Acquire, guard);
+            while let Some(node) = unsafe { curr.as_ref() } {
+                let next = node.next[i].load(Acquire, guard);
+                if next.tag() == 0 {
+                    print!("{} ", node.elem);
+                }
+                curr = next.with_tag(0);
+            }
+            println!();
+        }
+    }
+}
+
+impl<T> Drop for Skiplist<T> {
+    /// 独占访问, 不需要pin
+    ///  自顶向下每层走一遍, 每访问一次相当于摘掉一层, 计数归零的节点就是它最后一层了
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            for height in (0..MAX_HEIGHT).rev() {
+                let mut curr = self.head[height].load(Relaxed, guard);
+                while let Some(node) = curr.as_ref() {
+                    let next = node.next[height].load(Relaxed, guard).with_tag(0);
+                    if node.refs.fetch_sub(1, Relaxed) == 1 {
+                        drop(curr.into_owned());
+                    }
+                    curr = next;
+                }
+            }
+        }
+    }
+}
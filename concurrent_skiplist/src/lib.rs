@@ -1,4 +1,4 @@
-// pub mod concurrent_skiplist;
+pub mod concurrent_skiplist;
 pub mod unsafe_array_skiplist;
 pub mod safe_array_rc_skiplist;
 pub mod safe_array_rcrefcell_skiplist;
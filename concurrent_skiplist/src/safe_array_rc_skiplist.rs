@@ -1,8 +1,6 @@
-use std::ptr::{NonNull, self};
 use std::cmp::Ordering::*;
 
 use std::rc::Rc;
-use std::cell::RefCell;
 
 const MAX_HEIGHT: usize = 32;
 
@@ -82,8 +80,8 @@ impl Skiplist {
             //  如果new_height > curr_height时, 则自然会新建层
             //  如果new_height <= curr_height时, new_height就是要新建的随机高度
             for (i, spot) in update.iter().enumerate().take(rand_height) {
-                (*(Rc::as_ptr(&new_node) as *mut Node)).next[i] = spot.next[i].as_ref().cloned();
-                (*(Rc::as_ptr(spot) as *mut Node)).next[i] = Some(new_node.clone());
+                (&mut *(Rc::as_ptr(&new_node) as *mut Node)).next[i] = spot.next[i].as_ref().cloned();
+                (&mut *(Rc::as_ptr(spot) as *mut Node)).next[i] = Some(new_node.clone());
             }
 
         }
@@ -115,7 +113,7 @@ impl Skiplist {
                     continue;
                 }
                 // cloned() 相当于 .map(|x|x.clone());
-                unsafe { (*(Rc::as_ptr(update[i]) as *mut Node)).next[i] = n.next[i].as_ref().cloned(); }
+                unsafe { (&mut *(Rc::as_ptr(update[i]) as *mut Node)).next[i] = n.next[i].as_ref().cloned(); }
             }
         }
 
@@ -1,6 +1,8 @@
 use rand::{thread_rng, Rng};
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
 
 
 struct Node<T, U> {
@@ -22,55 +24,91 @@ impl<T, U> Node<T, U> {
     }
 }
 
-pub struct Skiplist {
-    head: Option<Rc<RefCell<Node<i32, i32>>>>,
+/// 每层的节点都是独立的一份, 所以key要Clone
+///  key: 哨兵为None, 正好比所有Some都小
+///  value: kv在entries中的下标, 所有层的节点共享同一个下标
+type NodeRef<K> = Rc<RefCell<Node<Option<K>, usize>>>;
+type Link<K> = Option<NodeRef<K>>;
+
+/// 有序kv跳表
+///
+/// kv不放在节点里, 而是放在`entries`中: RefCell里的数据没法返回纯真的`&K`/`&V`,
+/// 放在外面就可以直接借出`&V`/`&mut V`, 迭代时也能借出`&K`
+pub struct SkipMap<K, V> {
+    head: Link<K>,
+    entries: Vec<Option<(K, V)>>,
+    // 被删除后空出来的下标, 插入时复用
+    free: Vec<usize>,
+    len: usize,
+}
+
+/// leetcode 1206的接口: 允许重复元素, 所以用SkipMap记录每个元素出现的次数
+pub struct Skiplist<T = i32> {
+    map: SkipMap<T, usize>,
 }
 
-impl Skiplist {
+impl<K: Ord + Clone, V> Default for SkipMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone, V> SkipMap<K, V> {
     pub fn new() -> Self {
         Self {
-            head: Some(Rc::new(RefCell::new(Node::new(-1, -1)))),
+            head: Some(Rc::new(RefCell::new(Node::new(None, 0)))),
+            entries: Vec::new(),
+            free: Vec::new(),
+            len: 0,
         }
     }
 
-    // 能右就右: key < target
-    // 不能就下
-    // 需要记录上一个down的节点, 这里用Vec保存所有
-    pub fn search(&self, target: i32) -> bool {
-        let mut curr = self.head.clone();
-        let mut qu = Vec::new();
-        // 遍历, 向next找到最接近的范围, 再down提升精度
-        while let Some(node) = curr {
-            curr = if node.borrow().next.clone().filter(|n| n.borrow().key <= target).is_some() {
-                node.borrow().next.clone()
-            } else {
-                qu.push(node.clone());
-                node.borrow().down.clone()
-            }
+    pub fn len(&self) -> usize {
+        self.len
+    }
 
-        }
-        qu.last().filter(|n| n.borrow().key == target).is_some()
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
-    pub fn add(&mut self, target: i32) {
-        let mut curr = self.head.clone();
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find(key).and_then(|i| self.entries[i].as_ref()).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.find(key).and_then(|i| self.entries[i].as_mut()).map(|(_, v)| v)
+    }
+
+    /// key已存在时覆盖value, 返回旧value
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
         // 所有拐点保存到栈中, 最后逐层插入
-        let mut qu = Vec::new();
+        let mut qu = self.turning_points(&key);
+        if let Some(i) = Self::matched(&qu, &key) {
+            return self.entries[i].as_mut().map(|(_, v)| mem::replace(v, value));
+        }
+
+        let slot = match self.free.pop() {
+            Some(i) => {
+                self.entries[i] = Some((key.clone(), value));
+                i
+            },
+            None => {
+                self.entries.push(Some((key.clone(), value)));
+                self.entries.len() - 1
+            },
+        };
+        self.len += 1;
+
         let mut is_insert = true;
         let mut down = None;
-        while let Some(node) = curr {
-            curr = if node.borrow().next.clone().filter(|n| n.borrow().key <= target).is_some() {
-                node.borrow().next.clone()
-            } else {
-                qu.push(node.clone());
-                node.borrow().down.clone()
-            }
-
-        }
         // 弹出栈中记录的拐点, 逐层插入, 利用随机数判断是否插入从而保证均匀性
         while is_insert && !qu.is_empty() {
             let node = qu.pop().unwrap();
-            let new_node = Rc::new(RefCell::new(Node::new(target, target)));
+            let new_node = Rc::new(RefCell::new(Node::new(Some(key.clone()), slot)));
             // 插入链表
             new_node.borrow_mut().next = node.as_ref().borrow().next.clone();
             new_node.borrow_mut().down = down;
@@ -80,33 +118,237 @@ impl Skiplist {
         }
         // 若随机到一直插入直到顶部, 则在顶部新增层
         if is_insert {
-            let new_node = Rc::new(RefCell::new(Node::new(-1, -1)));
+            let new_node = Rc::new(RefCell::new(Node::new(None, 0)));
             new_node.borrow_mut().down = self.head.clone();
             self.head = Some(new_node);
         }
+        None
     }
 
-    pub fn erase(&self, target: i32) -> bool {
-        // 找到所有down, 保存到栈中, 则栈顶就是目标
+    /// 返回被删除的value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
         let mut curr = self.head.clone();
-        let mut is_found = false;
+        let mut slot = None;
         while let Some(node) = curr {
             // 遍历 key == target时要down防止多删
-            curr = if node.borrow().next.is_some() && node.borrow().next.as_ref().unwrap().borrow().key < target {
+            curr = match node.borrow().next.as_ref() {
+                Some(next) if next.borrow().key.as_ref() < Some(key) => Some(next.clone()),
+                _ => node.borrow().down.clone(),
+            };
+
+            // curr已经next或down, 可以安全删除
+            let next = node.borrow().next.clone();
+            if let Some(next) = next.filter(|n| n.borrow().key.as_ref() == Some(key)) {
+                slot = Some(next.borrow().value);
+                node.borrow_mut().next = next.borrow_mut().next.take();
+            }
+        }
+
+        let slot = slot?;
+        self.free.push(slot);
+        self.len -= 1;
+        self.entries[slot].take().map(|(_, v)| v)
+    }
+
+    /// 按key顺序遍历最底层链表
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        // 从最顶层的哨兵一路down到最底层
+        let mut head = self.head.clone().unwrap();
+        while let Some(down) = head.clone().borrow().down.clone() {
+            head = down;
+        }
+        let curr = head.borrow().next.clone();
+        Iter { curr, entries: &self.entries }
+    }
+
+    /// 先逐层定位到区间起点, 再沿最底层链表走到区间终点
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V, R> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.last_before(|k| k < start),
+            Bound::Excluded(start) => self.last_before(|k| k <= start),
+            Bound::Unbounded => return Range { iter: self.iter(), range },
+        };
+        let curr = start.borrow().next.clone();
+        Range {
+            iter: Iter { curr, entries: &self.entries },
+            range,
+        }
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    /// 能右就右, 一直走到最底层的最后一个节点
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.entry(&self.last_before(|_| true))
+    }
+
+    /// 小于等于key的最大kv
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        self.entry(&self.last_before(|k| k <= key))
+    }
+
+    /// 大于等于key的最小kv: 最大小于key的节点在最底层的后继
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        let next = self.last_before(|k| k < key).borrow().next.clone();
+        next.and_then(|n| self.entry(&n))
+    }
+
+    /// 逐层能右就右(满足pred), 返回最底层最后一个满足pred的节点, 可能是哨兵
+    ///  要求pred在key上单调: 前面满足的后面才可能满足
+    fn last_before(&self, pred: impl Fn(&K) -> bool) -> NodeRef<K> {
+        let mut curr = self.head.clone().unwrap();
+        loop {
+            let next = curr.borrow().next.clone()
+                .filter(|n| n.borrow().key.as_ref().is_some_and(&pred));
+            curr = match next {
+                Some(next) => next,
+                None => match curr.clone().borrow().down.clone() {
+                    Some(down) => down,
+                    None => return curr,
+                },
+            };
+        }
+    }
+
+    /// 节点对应的kv, 哨兵返回None
+    fn entry(&self, node: &NodeRef<K>) -> Option<(&K, &V)> {
+        let node = node.borrow();
+        node.key.as_ref()?;
+        self.entries[node.value].as_ref().map(|(k, v)| (k, v))
+    }
+
+    // 能右就右: key <= target
+    // 不能就下
+    // 需要记录上一个down的节点, 这里用Vec保存所有, 栈顶就是最底层的拐点
+    fn turning_points(&self, key: &K) -> Vec<NodeRef<K>> {
+        let mut curr = self.head.clone();
+        let mut qu = Vec::new();
+        // 遍历, 向next找到最接近的范围, 再down提升精度
+        while let Some(node) = curr {
+            curr = if node.borrow().next.clone().filter(|n| n.borrow().key.as_ref() <= Some(key)).is_some() {
                 node.borrow().next.clone()
             } else {
+                qu.push(node.clone());
                 node.borrow().down.clone()
-            };
+            }
+        }
+        qu
+    }
 
-            // curr已经next或down, 可以安全删除
-            if node.borrow().next.is_some() && node.borrow().next.as_ref().unwrap().borrow().key == target {
-                is_found = true;
-                let next = node.borrow().next.as_ref().unwrap().borrow_mut().next.clone();
-                node.borrow_mut().next = next;
+    /// 最底层的拐点就是最大的<=key的节点, 相等说明找到了
+    fn matched(qu: &[NodeRef<K>], key: &K) -> Option<usize> {
+        qu.last()
+            .filter(|n| n.borrow().key.as_ref() == Some(key))
+            .map(|n| n.borrow().value)
+    }
+
+    fn find(&self, key: &K) -> Option<usize> {
+        Self::matched(&self.turning_points(key), key)
+    }
+}
+
+/// 沿最底层链表的迭代器
+///  节点只能拿到Rc, kv从entries中借出
+pub struct Iter<'a, K, V> {
+    curr: Link<K>,
+    entries: &'a [Option<(K, V)>],
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.curr.take()?;
+        self.curr = node.borrow().next.clone();
+        let slot = node.borrow().value;
+        self.entries[slot].as_ref().map(|(k, v)| (k, v))
+    }
+}
+
+/// 区间迭代器: 起点在`range()`中定位好了, 这里只需要检查终点
+pub struct Range<'a, K, V, R> {
+    iter: Iter<'a, K, V>,
+    range: R,
+}
+
+impl<'a, K: Ord, V, R: RangeBounds<K>> Iterator for Range<'a, K, V, R> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (k, v) = self.iter.next()?;
+        let in_range = match self.range.end_bound() {
+            Bound::Included(end) => k <= end,
+            Bound::Excluded(end) => k < end,
+            Bound::Unbounded => true,
+        };
+        if in_range {
+            Some((k, v))
+        } else {
+            // 超过终点后就不用再走了
+            self.iter.curr = None;
+            None
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone, V> IntoIterator for &'a SkipMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V> Drop for SkipMap<K, V> {
+    /// 默认的drop会沿着next递归, 长链表会爆栈, 所以逐层逐个断开
+    fn drop(&mut self) {
+        let mut level = self.head.take();
+        while let Some(head) = level {
+            let mut next = head.borrow_mut().next.take();
+            while let Some(node) = next {
+                next = node.borrow_mut().next.take();
             }
+            level = head.borrow_mut().down.take();
+        }
+    }
+}
+
+impl<T: Ord + Clone> Default for Skiplist<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Clone> Skiplist<T> {
+    pub fn new() -> Self {
+        Self { map: SkipMap::new() }
+    }
+
+    pub fn search(&self, target: T) -> bool {
+        self.map.contains_key(&target)
+    }
+
+    pub fn add(&mut self, target: T) {
+        match self.map.get_mut(&target) {
+            Some(count) => *count += 1,
+            None => {
+                self.map.insert(target, 1);
+            },
+        }
+    }
 
+    pub fn erase(&mut self, target: T) -> bool {
+        match self.map.get_mut(&target) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.map.remove(&target);
+            },
+            None => return false,
         }
-        is_found
+        true
     }
 }
 
@@ -135,4 +377,5 @@ fn skiplist() {
 }
 
 
+#[allow(dead_code)]
 fn main() { }
@@ -1,134 +1,254 @@
-use std::ptr::{NonNull, self};
+use std::ptr::NonNull;
 use std::cmp::Ordering::*;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
 
 const MAX_HEIGHT: usize = 32;
 
-type Link = Option<NonNull<Node>>;
+type Link<K, V> = Option<NonNull<Node<K, V>>>;
+type Tower<K, V> = [Link<K, V>; MAX_HEIGHT];
 
-struct Node {
-    next: [Link; MAX_HEIGHT],
-    elem: i32,
+struct Node<K, V> {
+    next: Tower<K, V>,
+    key: K,
+    value: V,
 }
 
-pub struct Skiplist {
-    head: NonNull<Node>,
+/// 有序kv跳表
+pub struct SkipMap<K, V> {
+    // 哨兵: 只需要一座塔, 不需要key
+    head: Box<Tower<K, V>>,
     current_height: usize,
+    len: usize,
 }
 
-impl Node {
-    pub fn new(elem: i32) -> Self {
+/// leetcode 1206的接口: 允许重复元素, 所以用SkipMap记录每个元素出现的次数
+pub struct Skiplist<T = i32> {
+    map: SkipMap<T, usize>,
+}
+
+impl<K, V> Node<K, V> {
+    pub fn new(key: K, value: V) -> Self {
         Self {
             next: [None; MAX_HEIGHT],
-            elem,
+            key,
+            value,
         }
     }
 }
 
-impl Skiplist {
+impl<K: Ord, V> Default for SkipMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V> SkipMap<K, V> {
     pub fn new() -> Self {
-        unsafe {
-        let head = NonNull::new_unchecked(Box::into_raw(Box::new(Node::new(0))));
         Self {
-            // 哨兵
-            head,
+            head: Box::new([None; MAX_HEIGHT]),
             current_height: 0,
-        }
+            len: 0,
         }
     }
 
-    pub fn search(&mut self, target: i32) -> bool {
-        unsafe {
+    pub fn len(&self) -> usize {
+        self.len
+    }
 
-        let mut curr_node = self.head.as_ref();
-        let current_height = self.current_height;
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 
-        for height in (0..current_height).rev() {
-            // 如果next存在
-            while let Some(next) = curr_node.next[height] {
-                // 且next < target
-				match next.as_ref().elem.cmp(&target) {
-					Less => curr_node = next.as_ref(),
-                    // 因为上层存在则下层必定存在
-					Equal => return true,
-					Greater => break,
-				}
-            }
-        }
-        false
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
 
-        }
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find(key).map(|node| unsafe { &(*node.as_ptr()).value })
     }
-    
-    pub fn add(&mut self, target: i32) {
-        unsafe {
 
-        let mut curr_node = self.head;
-        let mut update = [self.head.as_ptr(); MAX_HEIGHT];
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.find(key).map(|node| unsafe { &mut (*node.as_ptr()).value })
+    }
 
-        for height in (0..self.current_height).rev() {
-            while let Some(next) = curr_node.as_mut().next[height] {
-                if next.as_ref().elem >= target {
-                    break;
-                }
-                curr_node = next;
+    /// key已存在时覆盖value, 返回旧value
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        unsafe {
+
+        let update = self.find_update(&key);
+        if let Some(node) = (*update[0])[0] {
+            if node.as_ref().key == key {
+                return Some(mem::replace(&mut (*node.as_ptr()).value, value));
             }
-            update[height]= curr_node.as_ptr();
         }
 
         let rand_height = Self::rand_height();
         self.current_height = self.current_height.max(rand_height);
-        let mut new_node = NonNull::new_unchecked(Box::into_raw(Box::new(Node::new(target))));
+        let mut new_node = NonNull::new_unchecked(Box::into_raw(Box::new(Node::new(key, value))));
 
         // 自底向上插入
         // 不需要更新0..self.curr_height, 因为
-        //  如果new_height > curr_height时, 则自然会新建层
+        //  如果new_height > curr_height时, 则自然会新建层, update默认就是head
         //  如果new_height <= curr_height时, new_height就是要新建的随机高度
         for (i, spot) in update.iter().enumerate().take(rand_height) {
-            new_node.as_mut().next[i] = (**spot).next[i];
-            (**spot).next[i] = Some(new_node);
+            new_node.as_mut().next[i] = (**spot)[i];
+            (**spot)[i] = Some(new_node);
+        }
+        self.len += 1;
+        None
+
+        }
+    }
+
+    /// 返回被删除的value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        unsafe {
+
+        let update = self.find_update(key);
+
+        // 如果目标节点不存在则删除失败
+        let target = match (*update[0])[0] {
+            Some(node) if node.as_ref().key == *key => node,
+            _ => return None,
+        };
+
+        // 自顶向下拆
+        for i in (0..self.current_height).rev() {
+            if (*update[i])[i] == Some(target) {
+                (*update[i])[i] = target.as_ref().next[i];
+            }
+        }
+
+        // 当顶层为空时高度下降
+        while self.current_height > 0 && self.head[self.current_height-1].is_none() {
+            self.current_height -= 1;
+        }
+
+        self.len -= 1;
+        Some(Box::from_raw(target.as_ptr()).value)
+
         }
+    }
+
+    /// 按key顺序遍历最底层链表
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            curr: self.head[0],
+            marker: PhantomData,
+        }
+    }
 
+    /// 先逐层定位到区间起点, 再沿最底层链表走到区间终点
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V, R> {
+        let curr = match range.start_bound() {
+            Bound::Included(start) => self.next_of(self.last_before(|k| k < start)),
+            Bound::Excluded(start) => self.next_of(self.last_before(|k| k <= start)),
+            Bound::Unbounded => self.head[0],
+        };
+        Range {
+            iter: Iter { curr, marker: PhantomData },
+            range,
         }
     }
-    
-    pub fn erase(&mut self, target: i32) -> bool {
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.head[0].map(|node| unsafe { Self::entry(node) })
+    }
+
+    /// 能右就右, 一直走到最底层的最后一个节点
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.last_before(|_| true).map(|node| unsafe { Self::entry(node) })
+    }
+
+    /// 小于等于key的最大kv
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        self.last_before(|k| k <= key).map(|node| unsafe { Self::entry(node) })
+    }
+
+    /// 大于等于key的最小kv: 最大小于key的节点在最底层的后继
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        self.next_of(self.last_before(|k| k < key)).map(|node| unsafe { Self::entry(node) })
+    }
+
+    /// 逐层能右就右(满足pred), 返回最底层最后一个满足pred的节点, None表示哨兵
+    ///  要求pred在key上单调: 前面满足的后面才可能满足
+    fn last_before(&self, pred: impl Fn(&K) -> bool) -> Link<K, V> {
         unsafe {
 
-        let mut curr_node = self.head;
-        let mut update = [self.head.as_ptr(); MAX_HEIGHT];
+        let mut curr: &Tower<K, V> = &self.head;
+        let mut last = None;
 
         for height in (0..self.current_height).rev() {
-            while let Some(next) = curr_node.as_mut().next[height] {
-                if next.as_ref().elem >= target {
+            while let Some(next) = curr[height] {
+                if !pred(&next.as_ref().key) {
                     break;
                 }
-                curr_node = next;
+                curr = &(*next.as_ptr()).next;
+                last = Some(next);
             }
-            update[height]= curr_node.as_ptr();
         }
+        last
 
-        // 如果目标节点不存在则删除失败
-        if curr_node.as_ref().next[0].map_or(true, |x| x.as_ref().elem != target) {
-            return false;
         }
+    }
 
-        // 自顶向下拆
-        for i in (0..self.current_height).rev() {
-            if let Some(mut n) = (*update[i]).next[i] {
-                if n.as_ref().elem != target {
-                    continue;
-                }
+    /// 最底层的后继, None表示哨兵
+    fn next_of(&self, node: Link<K, V>) -> Link<K, V> {
+        match node {
+            Some(node) => unsafe { node.as_ref().next[0] },
+            None => self.head[0],
+        }
+    }
+
+    unsafe fn entry<'a>(node: NonNull<Node<K, V>>) -> (&'a K, &'a V) {
+        let node = &*node.as_ptr();
+        (&node.key, &node.value)
+    }
+
+    /// 逐层找等于key的节点
+    fn find(&self, key: &K) -> Link<K, V> {
+        unsafe {
+
+        let mut curr: &Tower<K, V> = &self.head;
 
-                (*update[i]).next[i] = n.as_mut().next[i];
+        for height in (0..self.current_height).rev() {
+            // 如果next存在
+            while let Some(next) = curr[height] {
+                // 且next < key
+                match next.as_ref().key.cmp(key) {
+                    Less => curr = &(*next.as_ptr()).next,
+                    // 因为上层存在则下层必定存在
+                    Equal => return Some(next),
+                    Greater => break,
+                }
             }
         }
+        None
 
-        // 当顶层为空时高度下降
-        while self.current_height > 0 && self.head.as_ref().next[self.current_height-1].is_none() {
-            self.current_height -= 1;
         }
+    }
+
+    /// 逐层找最大小于key的节点, 返回每层的前驱塔, 即待修改的位置
+    fn find_update(&mut self, key: &K) -> [*mut Tower<K, V>; MAX_HEIGHT] {
+        unsafe {
+
+        let head: *mut Tower<K, V> = &mut *self.head;
+        let mut curr = head;
+        let mut update = [head; MAX_HEIGHT];
+
+        for height in (0..self.current_height).rev() {
+            while let Some(next) = (*curr)[height] {
+                if next.as_ref().key >= *key {
+                    break;
+                }
+                curr = &mut (*next.as_ptr()).next;
+            }
+            update[height] = curr;
+        }
+        update
 
-        true
         }
     }
 
@@ -137,15 +257,17 @@ impl Skiplist {
         1 + x.trailing_zeros() as usize
     }
 
-    pub fn display(&self) {
+    pub fn display(&self)
+        where K: std::fmt::Display
+    {
         unsafe {
 
         for i in (0..self.current_height).rev() {
-            let mut curr = self.head;
-            while let Some(node) = curr.as_ref().next[i] {
-                print!("{} ", node.as_ref().elem);
+            let mut curr = self.head[i];
+            while let Some(node) = curr {
+                print!("{} ", node.as_ref().key);
 
-                curr = node;
+                curr = node.as_ref().next[i];
             }
             println!();
         }
@@ -154,3 +276,108 @@ impl Skiplist {
     }
 }
 
+/// 沿最底层链表的迭代器
+pub struct Iter<'a, K, V> {
+    curr: Link<K, V>,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.curr.map(|node| unsafe {
+            let node = &*node.as_ptr();
+            self.curr = node.next[0];
+            (&node.key, &node.value)
+        })
+    }
+}
+
+/// 区间迭代器: 起点在`range()`中定位好了, 这里只需要检查终点
+pub struct Range<'a, K, V, R> {
+    iter: Iter<'a, K, V>,
+    range: R,
+}
+
+impl<'a, K: Ord, V, R: RangeBounds<K>> Iterator for Range<'a, K, V, R> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (k, v) = self.iter.next()?;
+        let in_range = match self.range.end_bound() {
+            Bound::Included(end) => k <= end,
+            Bound::Excluded(end) => k < end,
+            Bound::Unbounded => true,
+        };
+        if in_range {
+            Some((k, v))
+        } else {
+            // 超过终点后就不用再走了
+            self.iter.curr = None;
+            None
+        }
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a SkipMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V> Drop for SkipMap<K, V> {
+    fn drop(&mut self) {
+        // 最底层就是所有元素
+        let mut curr = self.head[0];
+        while let Some(node) = curr {
+            let node = unsafe { Box::from_raw(node.as_ptr()) };
+            curr = node.next[0];
+        }
+    }
+}
+
+impl<T: Ord> Default for Skiplist<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> Skiplist<T> {
+    pub fn new() -> Self {
+        Self { map: SkipMap::new() }
+    }
+
+    pub fn search(&self, target: T) -> bool {
+        self.map.contains_key(&target)
+    }
+
+    pub fn add(&mut self, target: T) {
+        match self.map.get_mut(&target) {
+            Some(count) => *count += 1,
+            None => {
+                self.map.insert(target, 1);
+            },
+        }
+    }
+
+    pub fn erase(&mut self, target: T) -> bool {
+        match self.map.get_mut(&target) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.map.remove(&target);
+            },
+            None => return false,
+        }
+        true
+    }
+
+    pub fn display(&self)
+        where T: std::fmt::Display
+    {
+        self.map.display();
+    }
+}
@@ -1,10 +1,16 @@
-use concurrent_skiplist::unsafe_skiplist::Skiplist as ArraySkiplist;
+use concurrent_skiplist::unsafe_array_skiplist::Skiplist as ArraySkiplist;
 use concurrent_skiplist::safe_list_skiplist::Skiplist as ListSkiplist;
+use concurrent_skiplist::concurrent_skiplist::Skiplist as LockFreeSkiplist;
+use concurrent_skiplist::unsafe_array_skiplist::SkipMap as ArraySkipMap;
+use concurrent_skiplist::safe_list_skiplist::SkipMap as ListSkipMap;
+use std::collections::BTreeMap;
+use std::ops::Bound::{Excluded, Included};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
 
 type K = i32;
 type V = i32;
 const SET_SIZE: usize = 1 << 10;
-const BUKCET_SIZE: usize = 1000;
 
 fn create_workload() -> Vec<(K, V)> {
     let mut v = vec![];
@@ -48,3 +54,225 @@ fn array_base() {
 
 
 
+/// 随便一个带堆内存的记录
+#[derive(Debug, Clone, PartialEq)]
+struct Record {
+    name: String,
+    score: u64,
+}
+
+fn record(i: u64) -> Record {
+    Record { name: format!("user-{}", i), score: i * 10 }
+}
+
+/// 用同一个宏对两种SkipMap跑相同的用例, 结果和BTreeMap对比
+macro_rules! skipmap_tests {
+    ($map:ident, $basic:ident, $random:ident, $ordered:ident) => {
+        #[test]
+        fn $basic() {
+            let mut m = $map::new();
+            assert!(m.is_empty());
+            assert_eq!(m.insert("b".to_string(), record(2)), None);
+            assert_eq!(m.insert("a".to_string(), record(1)), None);
+            assert_eq!(m.insert("b".to_string(), record(3)), Some(record(2)));
+            assert_eq!(m.len(), 2);
+
+            assert_eq!(m.get(&"a".to_string()), Some(&record(1)));
+            assert_eq!(m.get(&"c".to_string()), None);
+            m.get_mut(&"a".to_string()).unwrap().score += 1;
+            assert_eq!(m.get(&"a".to_string()).unwrap().score, 11);
+
+            assert_eq!(m.remove(&"b".to_string()), Some(record(3)));
+            assert_eq!(m.remove(&"b".to_string()), None);
+            assert!(!m.contains_key(&"b".to_string()));
+            assert_eq!(m.len(), 1);
+        }
+
+        #[test]
+        fn $random() {
+            let mut m = $map::new();
+            let mut expect = BTreeMap::new();
+            for _ in 0..SET_SIZE * 8 {
+                let k = rand::random::<u64>() % (SET_SIZE as u64);
+                match rand::random::<u8>() % 3 {
+                    0 => assert_eq!(m.insert(k, record(k)), expect.insert(k, record(k))),
+                    1 => assert_eq!(m.remove(&k), expect.remove(&k)),
+                    _ => assert_eq!(m.get(&k), expect.get(&k)),
+                }
+                assert_eq!(m.len(), expect.len());
+            }
+        }
+
+        #[test]
+        fn $ordered() {
+            let mut m = $map::new();
+            let mut expect = BTreeMap::new();
+            assert_eq!(m.first(), None);
+            assert_eq!(m.last(), None);
+            assert_eq!(m.range(..).next(), None);
+            // 只放偶数, 奇数用来测floor/ceiling
+            for _ in 0..SET_SIZE {
+                let k = rand::random::<u64>() % (SET_SIZE as u64) * 2;
+                m.insert(k, record(k));
+                expect.insert(k, record(k));
+            }
+
+            assert!(m.iter().eq(expect.iter()));
+            assert!((&m).into_iter().eq(&expect));
+            assert_eq!(m.first(), expect.first_key_value());
+            assert_eq!(m.last(), expect.last_key_value());
+
+            for _ in 0..SET_SIZE {
+                let a = rand::random::<u64>() % (SET_SIZE as u64 * 2 + 2);
+                let b = rand::random::<u64>() % (SET_SIZE as u64 * 2 + 2);
+                let (a, b) = (a.min(b), a.max(b));
+                assert!(m.range(a..b).eq(expect.range(a..b)));
+                assert!(m.range(a..=b).eq(expect.range(a..=b)));
+                assert!(m.range(a..).eq(expect.range(a..)));
+                assert!(m.range(..b).eq(expect.range(..b)));
+                assert!(m.range((Excluded(a), Included(b))).eq(expect.range((Excluded(a), Included(b)))));
+
+                assert_eq!(m.floor(&a), expect.range(..=a).next_back());
+                assert_eq!(m.ceiling(&a), expect.range(a..).next());
+            }
+        }
+    };
+}
+
+skipmap_tests!(ArraySkipMap, array_map_basic, array_map_random, array_map_ordered);
+skipmap_tests!(ListSkipMap, list_map_basic, list_map_random, list_map_ordered);
+
+const THREADS: usize = 8;
+
+#[test]
+fn lockfree_base() {
+    let workload = &create_workload();
+    let s = LockFreeSkiplist::new();
+    for (k, _) in workload {
+        assert!(s.add(*k));
+    }
+    for (k, _) in workload {
+        assert!(!s.add(*k));
+        assert!(s.search(k));
+    }
+    for (k, _) in workload {
+        assert!(s.erase(k));
+        assert!(!s.search(k));
+    }
+    for (k, _) in workload {
+        assert!(!s.erase(k));
+    }
+}
+
+#[test]
+fn lockfree_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<LockFreeSkiplist<K>>();
+}
+
+#[test]
+fn lockfree_concurrent_add() {
+    let s = LockFreeSkiplist::new();
+    std::thread::scope(|scope| {
+        for t in 0..THREADS {
+            let s = &s;
+            scope.spawn(move || {
+                for i in 0..SET_SIZE {
+                    assert!(s.add((i * THREADS + t) as K));
+                }
+            });
+        }
+    });
+    for i in 0..SET_SIZE * THREADS {
+        assert!(s.search(&(i as K)));
+    }
+}
+
+#[test]
+fn lockfree_concurrent_same_elems() {
+    // 所有线程抢着插入/删除同一批元素, 每个元素只能有一个线程成功
+    let s = LockFreeSkiplist::new();
+    let added = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..SET_SIZE {
+                    if s.add(i as K) {
+                        added.fetch_add(1, Relaxed);
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(added.load(Relaxed), SET_SIZE);
+
+    let erased = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..SET_SIZE {
+                    if s.erase(&(i as K)) {
+                        erased.fetch_add(1, Relaxed);
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(erased.load(Relaxed), SET_SIZE);
+    for i in 0..SET_SIZE {
+        assert!(!s.search(&(i as K)));
+    }
+}
+
+#[test]
+fn lockfree_concurrent_mixed() {
+    // 偶数线程反复插入删除自己的元素, 奇数线程反复查找常驻元素
+    let s = LockFreeSkiplist::new();
+    for i in 0..SET_SIZE {
+        s.add((i * THREADS) as K);
+    }
+    std::thread::scope(|scope| {
+        for t in 1..THREADS {
+            let s = &s;
+            scope.spawn(move || {
+                for round in 0..16 {
+                    for i in 0..SET_SIZE {
+                        let k = (i * THREADS + t) as K;
+                        if t % 2 == 0 {
+                            if round % 2 == 0 {
+                                assert!(s.add(k));
+                            } else {
+                                assert!(s.erase(&k));
+                            }
+                        } else {
+                            assert!(s.search(&((i * THREADS) as K)));
+                        }
+                    }
+                }
+            });
+        }
+    });
+    for i in 0..SET_SIZE * THREADS {
+        assert_eq!(s.search(&(i as K)), i % THREADS == 0);
+    }
+}
+
+#[test]
+fn lockfree_drop_owned_elems() {
+    // 元素带堆内存, 配合miri/valgrind检查回收
+    let s = LockFreeSkiplist::new();
+    std::thread::scope(|scope| {
+        for t in 0..THREADS {
+            let s = &s;
+            scope.spawn(move || {
+                for i in 0..SET_SIZE {
+                    let k = format!("{:08}", i * THREADS + t);
+                    s.add(k.clone());
+                    if i % 3 == 0 {
+                        s.erase(&k);
+                    }
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,59 @@
+//! 单次insert的延迟分布, 对比一次做完和渐进模式的尾延迟
+//!
+//! criterion只给平均值, 看不到翻倍/分裂时的毛刺, 所以这里自己逐个计时
+//!  cargo bench --bench latency
+
+use std::time::{Duration, Instant};
+
+use extendible_hashing::ExtendiableHash;
+
+type K = u64;
+type V = u64;
+const SET_SIZE: usize = 1 << 20;
+const BUCKET_SIZE: usize = 64;
+const ROUNDS: usize = 5;
+
+fn create_workload() -> Vec<(K, V)> {
+    (0..SET_SIZE).map(|i| (i as K, i as V)).collect()
+}
+
+fn insert_latency(workload: &[(K, V)], incremental: bool) -> Vec<Duration> {
+    let mut m = ExtendiableHash::new(1, BUCKET_SIZE);
+    m.set_incremental(incremental);
+    let mut latency = Vec::with_capacity(workload.len());
+    for &(k, v) in workload {
+        let start = Instant::now();
+        m.insert(k, v);
+        latency.push(start.elapsed());
+    }
+    latency
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    sorted[((sorted.len() - 1) as f64 * p) as usize]
+}
+
+fn report(name: &str, workload: &[(K, V)], incremental: bool) {
+    let mut latency = vec![];
+    for _ in 0..ROUNDS {
+        latency.extend(insert_latency(workload, incremental));
+    }
+    latency.sort();
+    let total: Duration = latency.iter().sum();
+    println!(
+        "{:<12} mean {:>8.0?} p50 {:>8.0?} p99 {:>8.0?} p99.9 {:>8.0?} p99.99 {:>8.0?} max {:>8.0?}",
+        name,
+        total / latency.len() as u32,
+        percentile(&latency, 0.5),
+        percentile(&latency, 0.99),
+        percentile(&latency, 0.999),
+        percentile(&latency, 0.9999),
+        latency.last().unwrap(),
+    );
+}
+
+fn main() {
+    let workload = create_workload();
+    report("normal", &workload, false);
+    report("incremental", &workload, true);
+}
@@ -1,13 +1,17 @@
 use std::collections::HashMap;
+use std::sync::Mutex;
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
-use extendible_hashing::{ExtendiableHash, Mode};
+use extendible_hashing::{ConcurrentExtendibleHash, ExtendiableHash, LinearHash, Mode};
 
 type K = u64;
 type V = u64;
 const SET_SIZE: usize = 1 << 10;
 const BUKCET_SIZE: usize = 1000;
+/// 桶开到1000时两种哈希都几乎不分裂, 再用小桶比一下分裂的开销
+const SMALL_BUCKET_SIZE: usize = 16;
+const THREADS: usize = 4;
 fn create_workload() -> Vec<(K, V)> {
     let mut v = vec![];
     v.resize(SET_SIZE, (0, 0));
@@ -44,6 +48,43 @@ fn stress_crate_shrink(workload: &Vec<(K, V)>) {
         m.remove(&k, Mode::Shrink);
     }
 }
+/// 线性哈希: 同样的参数和workload, 和可扩展哈希对比
+fn stress_linear_no(workload: &[(K, V)]) {
+    let mut m = LinearHash::new(2, BUKCET_SIZE);
+    for (k, v) in workload {
+        m.insert(k, v);
+    }
+    for (k, _) in workload {
+        m.remove(&k, Mode::No);
+    }
+}
+fn stress_linear_merge(workload: &[(K, V)]) {
+    let mut m = LinearHash::new(2, BUKCET_SIZE);
+    for (k, v) in workload {
+        m.insert(k, v);
+    }
+    for (k, _) in workload {
+        m.remove(&k, Mode::Merge);
+    }
+}
+fn stress_crate_small(workload: &[(K, V)]) {
+    let mut m = ExtendiableHash::new(2, SMALL_BUCKET_SIZE);
+    for (k, v) in workload {
+        m.insert(k, v);
+    }
+    for (k, _) in workload {
+        m.remove(&k, Mode::Merge);
+    }
+}
+fn stress_linear_small(workload: &[(K, V)]) {
+    let mut m = LinearHash::new(2, SMALL_BUCKET_SIZE);
+    for (k, v) in workload {
+        m.insert(k, v);
+    }
+    for (k, _) in workload {
+        m.remove(&k, Mode::Merge);
+    }
+}
 fn stress_std(workload: &Vec<(K, V)>) {
     let mut m = HashMap::new();
     for (k, v) in workload {
@@ -54,12 +95,55 @@ fn stress_std(workload: &Vec<(K, V)>) {
     }
 }
 
+/// 每个线程负责workload中的一段
+fn stress_concurrent(workload: &[(K, V)], threads: usize) {
+    let m = ConcurrentExtendibleHash::new(2, BUKCET_SIZE);
+    let chunk = workload.len().div_ceil(threads);
+    std::thread::scope(|s| {
+        for part in workload.chunks(chunk) {
+            let m = &m;
+            s.spawn(move || {
+                for (k, v) in part {
+                    m.insert(*k, *v);
+                }
+                for (k, _) in part {
+                    m.remove(k, Mode::No);
+                }
+            });
+        }
+    });
+}
+fn stress_std_mutex(workload: &[(K, V)], threads: usize) {
+    let m = Mutex::new(HashMap::new());
+    let chunk = workload.len().div_ceil(threads);
+    std::thread::scope(|s| {
+        for part in workload.chunks(chunk) {
+            let m = &m;
+            s.spawn(move || {
+                for (k, v) in part {
+                    m.lock().unwrap().insert(*k, *v);
+                }
+                for (k, _) in part {
+                    m.lock().unwrap().remove(k);
+                }
+            });
+        }
+    });
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     let workload = create_workload();
     c.bench_function("crate: no mode", |b| b.iter(|| stress_crate_no(black_box(&workload))));
     c.bench_function("crate: merge mode", |b| b.iter(|| stress_crate_merge(black_box(&workload))));
     c.bench_function("crate: shrink mode", |b| b.iter(|| stress_crate_shrink(black_box(&workload))));
+    c.bench_function("linear: no mode", |b| b.iter(|| stress_linear_no(black_box(&workload))));
+    c.bench_function("linear: merge mode", |b| b.iter(|| stress_linear_merge(black_box(&workload))));
+    c.bench_function("crate: small buckets", |b| b.iter(|| stress_crate_small(black_box(&workload))));
+    c.bench_function("linear: small buckets", |b| b.iter(|| stress_linear_small(black_box(&workload))));
     c.bench_function("std", |b| b.iter(|| stress_std(black_box(&workload))));
+    c.bench_function("concurrent: 1 thread", |b| b.iter(|| stress_concurrent(black_box(&workload), 1)));
+    c.bench_function("concurrent: 4 threads", |b| b.iter(|| stress_concurrent(black_box(&workload), THREADS)));
+    c.bench_function("std mutex: 4 threads", |b| b.iter(|| stress_std_mutex(black_box(&workload), THREADS)));
 }
 
 criterion_group!(benches, criterion_benchmark);
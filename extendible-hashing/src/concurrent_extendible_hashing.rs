@@ -0,0 +1,303 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::extendible_hashing::{Bucket, DefaultHashBuilder, InsertError, Mode, DEFAULT_MAX_GLOBAL_DEPTH};
+
+/// 目录: 所有结构性修改(split, grow, merge, shrink)都在目录写锁下进行
+struct Directory<K, V, S> {
+    entries: Vec<Arc<Mutex<Bucket<K, V>>>>,
+    global_depth: usize,
+    bucket_cap: usize,
+    // 同单线程版本: 目录最大深度, 桶不能再分裂时最多还能多放几个kv
+    max_global_depth: usize,
+    max_overflow: usize,
+    hash_builder: S,
+}
+
+/// 线程安全的可扩展哈希
+///
+/// 锁的层次: 目录读写锁 -> 桶锁
+///  - 普通的增删改查只拿目录读锁, 再锁住对应的桶, 所以不同桶上的操作可以并行
+///  - 只有桶满需要分裂, 或者桶空需要合并/收缩时才拿目录写锁
+///  - 拿着目录写锁时不可能有其他线程持有桶锁, 因为桶锁总是在目录读锁之下获取的
+pub struct ConcurrentExtendibleHash<K, V, S = DefaultHashBuilder> {
+    dir: RwLock<Directory<K, V, S>>,
+}
+
+impl<K, V, S> Directory<K, V, S>
+    where K: Hash + Eq + Clone, S: BuildHasher
+{
+    fn hash(&self, key: &K) -> usize {
+        self.hash_builder.hash_one(key) as usize & ((1<<self.global_depth)-1)
+    }
+
+    /// 持有写锁时的插入, 逻辑同单线程版本: 桶满时分裂后重试
+    ///  桶不能再分裂时溢出, 最多多放max_overflow个
+    fn insert(&mut self, key: K, value: V, max_overflow: usize) -> Result<bool, InsertError> {
+        loop {
+            let bucket_id = self.hash(&key);
+            let bucket = self.entries[bucket_id].clone();
+            let mut b = bucket.lock().unwrap();
+            if b.contains_key(&key) {
+                return Ok(false);
+            }
+            if !b.is_full() {
+                b.insert(key, value);
+                return Ok(true);
+            }
+            if !self.can_split(&b, &key) {
+                if b.len() >= self.bucket_cap.saturating_add(max_overflow) {
+                    return Err(InsertError::Overflow);
+                }
+                b.insert(key, value);
+                return Ok(true);
+            }
+            drop(b);
+            self.split(bucket_id);
+        }
+    }
+
+    /// 同单线程版本: 到了max_global_depth, 或者所有key和新key的完整哈希值都相同时不能分裂
+    fn can_split(&self, bucket: &Bucket<K, V>, key: &K) -> bool {
+        if bucket.local_depth() >= self.max_global_depth {
+            return false;
+        }
+        let h = self.hash_builder.hash_one(key);
+        bucket.table().keys().any(|k| self.hash_builder.hash_one(k) != h)
+    }
+
+    /// 同单线程版本的split, 只是重新映射时直接用后缀算法:
+    /// 后缀与pair_bucket_id相同的目录项从最小的那个开始, 每隔1<<new_depth一个
+    fn split(&mut self, bucket_id: usize) {
+        let bucket = self.entries[bucket_id].clone();
+        let new_depth = bucket.lock().unwrap().depth_up();
+
+        if new_depth > self.global_depth {
+            self.grow();
+        }
+
+        let pair_bucket_id = bucket.lock().unwrap().pair_index(bucket_id);
+        let pair_bucket = Arc::new(Mutex::new(Bucket::new(self.bucket_cap, new_depth)));
+
+        let mask = (1 << new_depth) - 1;
+        for i in ((pair_bucket_id & mask)..1<<self.global_depth).step_by(1 << new_depth) {
+            self.entries[i] = pair_bucket.clone();
+        }
+
+        // 旧桶中属于新桶的数据直接搬过去
+        //  ⭐不能再走insert: 溢出的桶分裂后可能仍然超过bucket_cap, 再insert会接着分裂
+        let mut b = bucket.lock().unwrap();
+        let keys: Vec<K> = b.table_mut()
+            .keys()
+            .filter(|k| self.hash(k) & mask == pair_bucket_id & mask)
+            .cloned()
+            .collect();
+        let mut pair = pair_bucket.lock().unwrap();
+        for k in keys {
+            let v = b.table_mut().remove(&k).unwrap();
+            pair.insert(k, v);
+        }
+    }
+
+    fn grow(&mut self) {
+        self.entries.reserve(self.entries.len());
+        for i in 0..1<<self.global_depth {
+            self.entries.push(self.entries[i].clone())
+        }
+        self.global_depth += 1;
+    }
+
+    fn merge(&mut self, bucket_id: usize) {
+        let bucket = self.entries[bucket_id].clone();
+        let (current_depth, pair_bucket_id) = {
+            let b = bucket.lock().unwrap();
+            // 只有桶为空, local_depth>1时才会有合并
+            if !b.is_empty() || b.local_depth() <= 1 {
+                return;
+            }
+            (b.local_depth(), b.pair_index(bucket_id))
+        };
+
+        let pair_bucket = self.entries[pair_bucket_id].clone();
+        // 另一半已经扩容, 不能合并
+        if pair_bucket.lock().unwrap().local_depth() != current_depth {
+            return;
+        }
+
+        let mask = (1 << current_depth) - 1;
+        for i in ((bucket_id & mask)..1<<self.global_depth).step_by(1 << current_depth) {
+            self.entries[i] = pair_bucket.clone();
+        }
+        pair_bucket.lock().unwrap().depth_down();
+    }
+
+    fn shrink(&mut self, bucket_id: usize) {
+        self.merge(bucket_id);
+
+        if self.global_depth == 0 {
+            return;
+        }
+
+        for b in &self.entries {
+            if b.lock().unwrap().local_depth() == self.global_depth { return; }
+        }
+
+        self.global_depth -= 1;
+        self.entries.truncate(1 << self.global_depth);
+    }
+}
+
+impl<K, V> ConcurrentExtendibleHash<K, V>
+    where K: Hash + Eq + Clone
+{
+    pub fn new(global_depth: usize, bucket_cap: usize) -> Self {
+        Self::with_hasher(global_depth, bucket_cap, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, S> ConcurrentExtendibleHash<K, V, S>
+    where K: Hash + Eq + Clone, S: BuildHasher
+{
+    pub fn with_hasher(global_depth: usize, bucket_cap: usize, hash_builder: S) -> Self {
+        assert!(global_depth > 0);
+
+        let mut entries = vec![];
+        for _ in 0..(1<<global_depth) {
+            entries.push(Arc::new(Mutex::new(Bucket::new(bucket_cap, global_depth))));
+        }
+        Self {
+            dir: RwLock::new(Directory {
+                entries,
+                global_depth,
+                bucket_cap,
+                max_global_depth: DEFAULT_MAX_GLOBAL_DEPTH.max(global_depth),
+                max_overflow: bucket_cap,
+                hash_builder,
+            }),
+        }
+    }
+
+    pub fn max_global_depth(&self) -> usize {
+        self.dir.read().unwrap().max_global_depth
+    }
+
+    /// 只影响之后的分裂, 已经比它深的目录不会收缩
+    pub fn set_max_global_depth(&self, max_global_depth: usize) {
+        assert!(max_global_depth > 0);
+        self.dir.write().unwrap().max_global_depth = max_global_depth;
+    }
+
+    pub fn max_overflow(&self) -> usize {
+        self.dir.read().unwrap().max_overflow
+    }
+
+    pub fn set_max_overflow(&self, max_overflow: usize) {
+        self.dir.write().unwrap().max_overflow = max_overflow;
+    }
+
+    /// 快路径: 目录读锁 + 桶锁, 桶未满直接插入
+    /// 慢路径: 桶满时释放所有锁, 拿目录写锁后重新查找再分裂
+    ///  释放读锁和拿到写锁之间其他线程可能已经分裂过了, 所以写锁下要从头再走一遍
+    ///  桶不能再分裂时一直溢出, 不会无限分裂下去; 要限制溢出用`try_insert`
+    pub fn insert(&self, key: K, value: V) -> bool {
+        match self.insert_bounded(key, value, usize::MAX) {
+            Ok(inserted) => inserted,
+            Err(_) => unreachable!("overflow is unbounded"),
+        }
+    }
+
+    /// 同`insert`, 桶不能再分裂且溢出超过`max_overflow`时返回错误
+    pub fn try_insert(&self, key: K, value: V) -> Result<bool, InsertError> {
+        let max_overflow = self.max_overflow();
+        self.insert_bounded(key, value, max_overflow)
+    }
+
+    fn insert_bounded(&self, key: K, value: V, max_overflow: usize) -> Result<bool, InsertError> {
+        {
+            let dir = self.dir.read().unwrap();
+            let bucket_id = dir.hash(&key);
+            let mut bucket = dir.entries[bucket_id].lock().unwrap();
+            if bucket.contains_key(&key) {
+                return Ok(false);
+            }
+            if !bucket.is_full() {
+                bucket.insert(key, value);
+                return Ok(true);
+            }
+        }
+
+        self.dir.write().unwrap().insert(key, value, max_overflow)
+    }
+
+    /// 更新kv, 只覆盖已存在的key
+    pub fn update(&self, key: K, value: V) {
+        let dir = self.dir.read().unwrap();
+        let bucket_id = dir.hash(&key);
+        dir.entries[bucket_id].lock().unwrap().update(key, value);
+    }
+
+    /// 删除只需要读锁, 删除后桶空且需要合并时再拿写锁
+    pub fn remove(&self, key: &K, mode: Mode) {
+        let is_empty = {
+            let dir = self.dir.read().unwrap();
+            let bucket_id = dir.hash(key);
+            let mut bucket = dir.entries[bucket_id].lock().unwrap();
+            bucket.remove(key);
+            bucket.is_empty()
+        };
+        if !is_empty {
+            return;
+        }
+
+        // 拿到写锁时目录可能已经变化, 重新计算桶号
+        // merge自己会检查桶是否仍然为空
+        match mode {
+            Mode::No => {},
+            Mode::Merge => {
+                let mut dir = self.dir.write().unwrap();
+                let bucket_id = dir.hash(key);
+                dir.merge(bucket_id);
+            },
+            Mode::Shrink => {
+                let mut dir = self.dir.write().unwrap();
+                let bucket_id = dir.hash(key);
+                dir.shrink(bucket_id);
+            },
+        }
+    }
+
+    /// 因为锁不能跨越返回值, 所以返回value的拷贝
+    pub fn get(&self, key: &K) -> Option<V>
+        where V: Clone
+    {
+        let dir = self.dir.read().unwrap();
+        let bucket_id = dir.hash(key);
+        let bucket = dir.entries[bucket_id].lock().unwrap();
+        bucket.get(key).cloned()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        let dir = self.dir.read().unwrap();
+        let bucket_id = dir.hash(key);
+        let bucket = dir.entries[bucket_id].lock().unwrap();
+        bucket.contains_key(key)
+    }
+
+    pub fn global_depth(&self) -> usize {
+        self.dir.read().unwrap().global_depth
+    }
+
+    /// 显示目录项映射关系和内容
+    pub fn display(&self)
+        where V: std::fmt::Display + Ord
+    {
+        let dir = self.dir.read().unwrap();
+        println!("global_depth: {}\n", dir.global_depth);
+        for i in 0..1<<dir.global_depth {
+            let bucket = dir.entries[i].lock().unwrap();
+            let d = bucket.local_depth();
+            print!("{:0width$b}: ", i & ((1 << d) - 1), width = d);
+            bucket.display();
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use crate::extendible_hashing::Mode;
+
+pub const PAGE_SIZE: usize = 4096;
+
+const MAGIC: &[u8; 8] = b"EXTHASH1";
+/// 头页中目录页号之前的部分
+const HEADER_SIZE: usize = 64;
+/// 一个目录页放多少个目录项(u32页号)
+const DIR_ENTRIES_PER_PAGE: usize = PAGE_SIZE / 4;
+/// 头页只存目录页的页号: 最多(4096-64)/4 = 1008个目录页, 每页1024项
+///  不超过1008*1024的最大2的幂是2^19, 即最多52万个桶页(2GB), u64/u64时大约1.3亿个kv
+pub const MAX_GLOBAL_DEPTH: usize = 19;
+/// 桶页开头的local_depth和count
+const BUCKET_HEADER_SIZE: usize = 8;
+
+/// 定长编码: 桶是定长的页, 所以kv也要定长才能算出桶容量
+pub trait Codec: Sized {
+    const SIZE: usize;
+    fn encode(&self, buf: &mut [u8]);
+    fn decode(buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_codec {
+    ($($t:ty),*) => {
+        $(
+            impl Codec for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+                fn encode(&self, buf: &mut [u8]) {
+                    buf[..Self::SIZE].copy_from_slice(&self.to_le_bytes());
+                }
+                fn decode(buf: &[u8]) -> Self {
+                    <$t>::from_le_bytes(buf[..Self::SIZE].try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_codec!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl<const N: usize> Codec for [u8; N] {
+    const SIZE: usize = N;
+    fn encode(&self, buf: &mut [u8]) {
+        buf[..N].copy_from_slice(self);
+    }
+    fn decode(buf: &[u8]) -> Self {
+        buf[..N].try_into().unwrap()
+    }
+}
+
+/// 桶页解码到内存中的样子
+///  页布局: local_depth: u32 | count: u32 | count个(key, value)
+struct Bucket<K, V> {
+    local_depth: usize,
+    table: Vec<(K, V)>,
+}
+
+/// 磁盘版可扩展哈希
+///
+/// 文件按PAGE_SIZE分页:
+///  - 第0页是头页: magic, 全局深度, kv大小, 桶容量, 页数, 空闲页链表头, 以及目录页的页号
+///  - 目录页: 目录按1024项一页切开存放
+///  - 其他页是桶页或空闲页, 空闲页的前4字节是下一个空闲页号(0表示没有)
+///
+/// 目录在内存中缓存一份, 只写回改到的目录页; 桶每次都从磁盘读
+/// 分裂只写旧桶, 新桶, 改到的目录页和头页; 合并只写pair桶, 回收的空页, 改到的目录页和头页
+///
+/// 没有日志, 不保证崩溃安全: 一次分裂要写好几页, 中途崩溃文件就不一致了
+pub struct DiskExtendibleHash<K, V> {
+    file: File,
+    entries: Vec<u32>,
+    dir_pages: Vec<u32>,
+    global_depth: usize,
+    bucket_cap: usize,
+    page_count: u32,
+    free_head: u32,
+    marker: PhantomData<(K, V)>,
+}
+
+impl<K: Codec, V: Codec> Bucket<K, V> {
+    fn new(local_depth: usize) -> Self {
+        Self { local_depth, table: Vec::new() }
+    }
+
+    fn decode(page: &[u8]) -> Self {
+        let local_depth = u32::decode(&page[0..]) as usize;
+        let count = u32::decode(&page[4..]) as usize;
+        let table = page[BUCKET_HEADER_SIZE..]
+            .chunks_exact(K::SIZE + V::SIZE)
+            .take(count)
+            .map(|slot| (K::decode(slot), V::decode(&slot[K::SIZE..])))
+            .collect();
+        Self { local_depth, table }
+    }
+
+    fn encode(&self, page: &mut [u8]) {
+        (self.local_depth as u32).encode(&mut page[0..]);
+        (self.table.len() as u32).encode(&mut page[4..]);
+        let slots = page[BUCKET_HEADER_SIZE..].chunks_exact_mut(K::SIZE + V::SIZE);
+        for ((k, v), slot) in self.table.iter().zip(slots) {
+            k.encode(slot);
+            v.encode(&mut slot[K::SIZE..]);
+        }
+    }
+}
+
+impl<K: Codec + Eq, V: Codec> Bucket<K, V> {
+    fn position(&self, key: &K) -> Option<usize> {
+        self.table.iter().position(|(k, _)| k == key)
+    }
+}
+
+impl<K: Codec + Eq, V: Codec> DiskExtendibleHash<K, V> {
+    /// 一页最多能放多少kv
+    pub fn page_capacity() -> usize {
+        (PAGE_SIZE - BUCKET_HEADER_SIZE) / (K::SIZE + V::SIZE)
+    }
+
+    /// 新建索引文件, 已存在则清空
+    pub fn create<P: AsRef<Path>>(path: P, global_depth: usize, bucket_cap: usize) -> io::Result<Self> {
+        assert!(global_depth > 0 && global_depth <= MAX_GLOBAL_DEPTH);
+        assert!(bucket_cap > 0 && bucket_cap <= Self::page_capacity());
+
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        let mut m = Self {
+            file,
+            entries: Vec::new(),
+            dir_pages: Vec::new(),
+            global_depth,
+            bucket_cap,
+            page_count: 1,
+            free_head: 0,
+            marker: PhantomData,
+        };
+        // 初始1:1映射
+        for _ in 0..(1<<global_depth) {
+            let page_id = m.alloc_page()?;
+            m.write_bucket(page_id, &Bucket::new(global_depth))?;
+            m.entries.push(page_id);
+        }
+        m.reserve_dir_pages(Self::dir_pages_for(global_depth))?;
+        m.write_dir_pages(0..m.dir_pages.len())?;
+        m.write_header()?;
+        Ok(m)
+    }
+
+    /// 打开已有的索引文件, 从头页恢复目录
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut page = [0u8; PAGE_SIZE];
+        file.read_exact_at(&mut page, 0)?;
+
+        if &page[0..8] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not an extendible hash file"));
+        }
+        if u32::decode(&page[12..]) as usize != K::SIZE || u32::decode(&page[16..]) as usize != V::SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "key/value size mismatch"));
+        }
+        let global_depth = u32::decode(&page[8..]) as usize;
+        if global_depth == 0 || global_depth > MAX_GLOBAL_DEPTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad global depth"));
+        }
+        let bucket_cap = u32::decode(&page[20..]) as usize;
+        if bucket_cap == 0 || bucket_cap > Self::page_capacity() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bucket capacity does not fit in a page"));
+        }
+        let dir_page_count = u32::decode(&page[32..]) as usize;
+        if dir_page_count < Self::dir_pages_for(global_depth) || HEADER_SIZE + dir_page_count * 4 > PAGE_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad directory page count"));
+        }
+
+        let mut m = Self {
+            file,
+            entries: Vec::with_capacity(1 << global_depth),
+            dir_pages: page[HEADER_SIZE..].chunks_exact(4).take(dir_page_count).map(u32::decode).collect(),
+            global_depth,
+            bucket_cap,
+            page_count: u32::decode(&page[24..]),
+            free_head: u32::decode(&page[28..]),
+            marker: PhantomData,
+        };
+        for i in 0..Self::dir_pages_for(global_depth) {
+            let page = m.read_page(m.dir_pages[i])?;
+            let n = DIR_ENTRIES_PER_PAGE.min((1 << global_depth) - m.entries.len());
+            m.entries.extend(page.chunks_exact(4).take(n).map(u32::decode));
+        }
+        Ok(m)
+    }
+
+    /// 找到相应的桶做插入操作, 当桶满时自动分裂
+    ///  已存在的key不插入也不分裂, 返回false
+    ///  目录已经到MAX_GLOBAL_DEPTH还需要扩容时返回错误
+    pub fn insert(&mut self, key: K, value: V) -> io::Result<bool> {
+        let bucket_id = self.hash(&key);
+        let page_id = self.entries[bucket_id];
+        let mut bucket = self.read_bucket(page_id)?;
+        if bucket.position(&key).is_some() {
+            return Ok(false);
+        }
+
+        if bucket.table.len() >= self.bucket_cap {
+            self.split(bucket_id, bucket)?;
+            self.insert(key, value)
+        } else {
+            bucket.table.push((key, value));
+            self.write_bucket(page_id, &bucket)?;
+            Ok(true)
+        }
+    }
+
+    /// 更新kv, 只覆盖已存在的key
+    pub fn update(&mut self, key: K, value: V) -> io::Result<()> {
+        let page_id = self.entries[self.hash(&key)];
+        let mut bucket = self.read_bucket(page_id)?;
+        if let Some(i) = bucket.position(&key) {
+            bucket.table[i].1 = value;
+            self.write_bucket(page_id, &bucket)?;
+        }
+        Ok(())
+    }
+
+    /// 模式同内存版本
+    pub fn remove(&mut self, key: &K, mode: Mode) -> io::Result<()> {
+        let bucket_id = self.hash(key);
+        let page_id = self.entries[bucket_id];
+        let mut bucket = self.read_bucket(page_id)?;
+        if let Some(i) = bucket.position(key) {
+            bucket.table.swap_remove(i);
+            self.write_bucket(page_id, &bucket)?;
+        }
+
+        match mode {
+            Mode::No => Ok(()),
+            Mode::Merge => self.merge(bucket_id),
+            Mode::Shrink => self.shrink(bucket_id),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> io::Result<Option<V>> {
+        let mut bucket = self.read_bucket(self.entries[self.hash(key)])?;
+        Ok(bucket.position(key).map(|i| bucket.table.swap_remove(i).1))
+    }
+
+    pub fn contains_key(&self, key: &K) -> io::Result<bool> {
+        let bucket = self.read_bucket(self.entries[self.hash(key)])?;
+        Ok(bucket.position(key).is_some())
+    }
+
+    pub fn global_depth(&self) -> usize {
+        self.global_depth
+    }
+
+    /// 文件总页数, 包括头页和空闲页
+    pub fn page_count(&self) -> usize {
+        self.page_count as usize
+    }
+
+    /// 所有写操作都是直接写文件的, 这里只是落盘
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    /// 不能用DefaultHasher: 它不保证跨版本稳定, 重新打开后桶号就对不上了
+    ///  这里对key的编码做FNV-1a
+    fn hash_key(key: &K) -> usize {
+        let mut buf = vec![0u8; K::SIZE];
+        key.encode(&mut buf);
+        buf.iter().fold(0xcbf29ce484222325u64, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3)) as usize
+    }
+
+    fn hash(&self, key: &K) -> usize {
+        Self::hash_key(key) & ((1<<self.global_depth)-1)
+    }
+
+    /// 同内存版本的split, 不过旧桶已经读出来了, 直接按新的后缀一分为二
+    ///  ⭐要分配的页(新桶, 翻倍后多出来的目录页)先分配好再改目录
+    ///  否则分配失败时内存中的目录已经翻倍, 和磁盘对不上
+    fn split(&mut self, bucket_id: usize, bucket: Bucket<K, V>) -> io::Result<()> {
+        let new_depth = bucket.local_depth + 1;
+        let grow = new_depth > self.global_depth;
+        if grow && self.global_depth == MAX_GLOBAL_DEPTH {
+            return Err(io::Error::other("directory is full"));
+        }
+        let pair_page_id = self.alloc_page()?;
+        let old_dir_pages = Self::dir_pages_for(self.global_depth);
+        if grow {
+            self.reserve_dir_pages(Self::dir_pages_for(self.global_depth + 1))?;
+            self.grow();
+        }
+
+        let pair_bucket_id = (1 << (new_depth-1)) ^ bucket_id;
+        let mask = (1 << new_depth) - 1;
+        let (moved, kept) = bucket.table
+            .into_iter()
+            .partition(|(k, _)| Self::hash_key(k) & mask == pair_bucket_id & mask);
+
+        self.write_bucket(self.entries[bucket_id], &Bucket { local_depth: new_depth, table: kept })?;
+        self.write_bucket(pair_page_id, &Bucket { local_depth: new_depth, table: moved })?;
+
+        let changed = self.redirect(pair_bucket_id, new_depth, pair_page_id);
+        if grow {
+            // 翻倍多出来的目录页都是新的, 全写
+            self.write_dir_pages(old_dir_pages.min(changed.start)..self.dir_pages_for_entries())?;
+        } else {
+            self.write_dir_pages(changed)?;
+        }
+        self.write_header()
+    }
+
+    /// 目录只在内存中翻倍, 由调用者写回
+    fn grow(&mut self) {
+        self.entries.extend_from_within(..);
+        self.global_depth += 1;
+    }
+
+    /// 后缀和bucket_id相同(depth位)的目录项都指向page_id, 返回改到的目录页范围
+    fn redirect(&mut self, bucket_id: usize, depth: usize, page_id: u32) -> std::ops::Range<usize> {
+        let mask = (1 << depth) - 1;
+        let first = bucket_id & mask;
+        for i in (first..1<<self.global_depth).step_by(1 << depth) {
+            self.entries[i] = page_id;
+        }
+        let last = first + (((1 << self.global_depth) - 1 - first) >> depth << depth);
+        first / DIR_ENTRIES_PER_PAGE..last / DIR_ENTRIES_PER_PAGE + 1
+    }
+
+    /// 空桶并入pair桶, 空桶的页放回空闲链表
+    fn merge(&mut self, bucket_id: usize) -> io::Result<()> {
+        let page_id = self.entries[bucket_id];
+        let bucket = self.read_bucket(page_id)?;
+        if !bucket.table.is_empty() || bucket.local_depth <= 1 {
+            return Ok(());
+        }
+
+        let current_depth = bucket.local_depth;
+        let pair_bucket_id = (1 << (current_depth-1)) ^ bucket_id;
+        let pair_page_id = self.entries[pair_bucket_id];
+        let mut pair_bucket = self.read_bucket(pair_page_id)?;
+        // 另一半已经扩容, 不能合并
+        if pair_bucket.local_depth != current_depth {
+            return Ok(());
+        }
+
+        pair_bucket.local_depth -= 1;
+        self.write_bucket(pair_page_id, &pair_bucket)?;
+
+        let changed = self.redirect(bucket_id, current_depth, pair_page_id);
+        self.write_dir_pages(changed)?;
+        self.free_page(page_id)?;
+        self.write_header()
+    }
+
+    /// 不用读桶页看local_depth: 前后两半目录完全相同就说明没有桶用到最高位
+    fn shrink(&mut self, bucket_id: usize) -> io::Result<()> {
+        self.merge(bucket_id)?;
+
+        let half = 1 << (self.global_depth - 1);
+        if self.global_depth <= 1 || self.entries[..half] != self.entries[half..] {
+            return Ok(());
+        }
+        self.global_depth -= 1;
+        self.entries.truncate(half);
+        // 后一半的目录页不要了, 目录页没改, 不用写
+        while self.dir_pages.len() > self.dir_pages_for_entries() {
+            let page_id = self.dir_pages.pop().unwrap();
+            self.free_page(page_id)?;
+        }
+        self.write_header()
+    }
+
+    /// global_depth下目录要几页
+    fn dir_pages_for(global_depth: usize) -> usize {
+        (1usize << global_depth).div_ceil(DIR_ENTRIES_PER_PAGE)
+    }
+
+    fn dir_pages_for_entries(&self) -> usize {
+        Self::dir_pages_for(self.global_depth)
+    }
+
+    /// 目录页不够count页时分配, 只改内存, 由调用者写回头页
+    fn reserve_dir_pages(&mut self, count: usize) -> io::Result<()> {
+        while self.dir_pages.len() < count {
+            let page_id = self.alloc_page()?;
+            self.dir_pages.push(page_id);
+        }
+        Ok(())
+    }
+
+    /// 把目录的第range页写回
+    fn write_dir_pages(&mut self, range: std::ops::Range<usize>) -> io::Result<()> {
+        for i in range {
+            let mut page = [0u8; PAGE_SIZE];
+            let entries = &self.entries[i * DIR_ENTRIES_PER_PAGE..self.entries.len().min((i + 1) * DIR_ENTRIES_PER_PAGE)];
+            for (page_id, slot) in entries.iter().zip(page.chunks_exact_mut(4)) {
+                page_id.encode(slot);
+            }
+            self.write_page(self.dir_pages[i], &page)?;
+        }
+        Ok(())
+    }
+
+    /// 优先复用空闲页, 否则追加到文件末尾
+    ///  追加时先写一个空页把文件撑大, 写成功了才改page_count, 失败时计数不变
+    fn alloc_page(&mut self) -> io::Result<u32> {
+        if self.free_head == 0 {
+            let page_id = self.page_count;
+            self.write_page(page_id, &[0u8; PAGE_SIZE])?;
+            self.page_count += 1;
+            return Ok(page_id);
+        }
+        let page_id = self.free_head;
+        let page = self.read_page(page_id)?;
+        self.free_head = u32::decode(&page);
+        Ok(page_id)
+    }
+
+    fn free_page(&mut self, page_id: u32) -> io::Result<()> {
+        let mut page = [0u8; PAGE_SIZE];
+        self.free_head.encode(&mut page);
+        self.write_page(page_id, &page)?;
+        self.free_head = page_id;
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut page = [0u8; PAGE_SIZE];
+        page[0..8].copy_from_slice(MAGIC);
+        (self.global_depth as u32).encode(&mut page[8..]);
+        (K::SIZE as u32).encode(&mut page[12..]);
+        (V::SIZE as u32).encode(&mut page[16..]);
+        (self.bucket_cap as u32).encode(&mut page[20..]);
+        self.page_count.encode(&mut page[24..]);
+        self.free_head.encode(&mut page[28..]);
+        (self.dir_pages.len() as u32).encode(&mut page[32..]);
+        for (page_id, slot) in self.dir_pages.iter().zip(page[HEADER_SIZE..].chunks_exact_mut(4)) {
+            page_id.encode(slot);
+        }
+        self.write_page(0, &page)
+    }
+
+    fn read_bucket(&self, page_id: u32) -> io::Result<Bucket<K, V>> {
+        Ok(Bucket::decode(&self.read_page(page_id)?))
+    }
+
+    fn write_bucket(&mut self, page_id: u32, bucket: &Bucket<K, V>) -> io::Result<()> {
+        let mut page = [0u8; PAGE_SIZE];
+        bucket.encode(&mut page);
+        self.write_page(page_id, &page)
+    }
+
+    /// ⭐用定位读(pread), 不动文件游标
+    ///  读操作只要`&self`, 多个线程可以同时get; 先seek再read的话两个线程会抢同一个游标, 读到别的页
+    fn read_page(&self, page_id: u32) -> io::Result<[u8; PAGE_SIZE]> {
+        let mut page = [0u8; PAGE_SIZE];
+        self.file.read_exact_at(&mut page, page_id as u64 * PAGE_SIZE as u64)?;
+        Ok(page)
+    }
+
+    fn write_page(&mut self, page_id: u32, page: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        self.file.write_all_at(page, page_id as u64 * PAGE_SIZE as u64)
+    }
+}
@@ -1,32 +1,135 @@
-#![allow(dead_code)]
-use std::collections::{HashMap};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
-use std::ops::BitAnd;
-use std::rc::Rc;
-use std::cell::{RefCell, Ref, RefMut};
+use std::collections::hash_map::{self, DefaultHasher, HashMap};
+use std::fmt;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash};
+use std::{slice, vec};
+
+#[cfg(feature = "serde")]
+mod snapshot;
 
 #[derive(Debug)]
-struct Bucket<K, V> {
+pub(crate) struct Bucket<K, V> {
     table: HashMap<K, V>,
     bucket_cap: usize,
     local_depth: usize,
 }
 
-pub struct ExtendiableHash<K, V> {
-    entries: Vec<Rc<RefCell<Bucket<K, V>>>>,
+/// 默认的哈希函数: 同`HashMap`的`S`参数, 但默认不用`RandomState`
+///  `DefaultHasher::new()`每次都一样, 这样分裂行为是确定的
+pub type DefaultHashBuilder = BuildHasherDefault<DefaultHasher>;
+
+/// 目录最多2^20项, 再大就不扩容了, 改用溢出
+pub const DEFAULT_MAX_GLOBAL_DEPTH: usize = 20;
+
+/// `Default`和`FromIterator`用的初始参数
+pub const DEFAULT_GLOBAL_DEPTH: usize = 1;
+pub const DEFAULT_BUCKET_CAP: usize = 32;
+
+/// 渐进模式下每次操作顺带复制的目录项数
+const GROW_STEP: usize = 32;
+/// 渐进模式下每次操作顺带搬迁的kv数
+const MIGRATE_STEP: usize = 8;
+
+/// 桶不再用`Rc<RefCell>`共享, 而是放在`buckets`里, 目录项只记录桶的下标
+///  这样就能借出原汁原味的`&V`/`&mut V`了
+pub struct ExtendiableHash<K, V, S = DefaultHashBuilder> {
+    entries: Vec<usize>,
+    buckets: Vec<Bucket<K, V>>,
+    // merge后空出来的桶下标, 分裂时复用
+    free: Vec<usize>,
+    len: usize,
     global_depth: usize,
     bucket_cap: usize,
+    // 目录最大深度
+    max_global_depth: usize,
+    // 桶不能再分裂时, 最多还能多放几个kv
+    max_overflow: usize,
+    // 渐进模式: 目录翻倍和分裂后的数据搬迁分摊到之后的操作中
+    incremental: bool,
+    // 还没复制完的目录翻倍
+    growing: Option<Growing>,
+    // 分裂后还没搬完的桶, 按新桶下标索引
+    //  ⭐每个桶最多参与一个搬迁: 分裂前会先把旧桶的搬迁做完, 新桶是刚分配的
+    migrations: HashMap<usize, Migration>,
+    // 旧桶下标 -> 新桶下标
+    migrating_from: HashMap<usize, usize>,
+    hash_builder: S,
+}
+
+/// 渐进翻倍: entries只复制了一部分, 后半还没复制的目录项i就是前半的i - 2^(global_depth-1)
+///  分裂/合并时会改到还没复制的目录项, 这些改动先记在overrides中, 复制时再取出来
+#[derive(Debug, Default)]
+struct Growing {
+    overrides: HashMap<usize, usize>,
+}
+
+/// 渐进分裂: from中哈希后缀为suffix的key属于to, 但还没搬过去
+///  remaining是还没搬的个数, 判断桶满时要算上
+#[derive(Debug)]
+struct Migration {
+    from: usize,
+    to: usize,
+    mask: usize,
+    suffix: usize,
+    remaining: usize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     No,     // 什么都不做
     Merge,  // 自动合并
     Shrink, // 自动合并加压缩
 }
 
-impl<K, V> Bucket<K, V> 
-    where K: Hash + Eq + Clone, V: std::fmt::Display + Clone + Copy + Ord
+/// 桶已经不能再分裂, 溢出也满了
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertError {
+    Overflow,
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertError::Overflow => write!(f, "bucket can not split and overflow is full"),
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+/// `stats()`的结果, 调试分裂/合并用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub global_depth: usize,
+    /// 不同的桶的个数, 不是目录项个数
+    pub bucket_count: usize,
+    pub len: usize,
+    /// 装填率直方图: 下标i表示装填率在[i*10%, (i+1)*10%)的桶数, 最后一项是满了(包括溢出)的桶
+    pub fill: [usize; 11],
+    /// 超过bucket_cap的桶数
+    pub overflowed: usize,
+    /// 下标是local_depth, 值是该深度的桶数
+    pub local_depths: Vec<usize>,
+}
+
+/// 同`HashMap::entry`
+pub enum Entry<'a, K, V, S> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+/// key已存在: 直接借用桶里的哈希表的entry
+pub struct OccupiedEntry<'a, K, V> {
+    inner: hash_map::OccupiedEntry<'a, K, V>,
+}
+
+/// key不存在: 插入时可能要分裂, 所以要拿着整个表
+pub struct VacantEntry<'a, K, V, S> {
+    map: &'a mut ExtendiableHash<K, V, S>,
+    key: K,
+}
+
+impl<K, V> Bucket<K, V>
+    where K: Hash + Eq
 {
     pub fn new(bucket_cap: usize, local_depth: usize) -> Self {
         let mut table = HashMap::new();
@@ -39,11 +142,9 @@ impl<K, V> Bucket<K, V>
         }
     }
 
-    /// 当容量满时返回false, 触发分裂
-    pub fn insert(&mut self, key: K, value: V) -> bool {
-        // 如果不存在才插入, 不做覆盖
-        self.table.entry(key).or_insert(value);
-        true
+    /// 不检查容量, 由调用者保证桶未满或者key已存在
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.table.insert(key, value)
     }
 
     /// 直接覆盖key对应的value
@@ -53,8 +154,8 @@ impl<K, V> Bucket<K, V>
     }
 
     /// 删除kv
-    pub fn remove(&mut self, key: &K) {
-        self.table.remove(key);
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.table.remove(key)
     }
 
     /// 查询kv
@@ -73,6 +174,10 @@ impl<K, V> Bucket<K, V>
         self.table.len() >= self.bucket_cap
     }
 
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.table.is_empty()
     }
@@ -82,6 +187,10 @@ impl<K, V> Bucket<K, V>
         self.local_depth
     }
 
+    pub fn table(&self) -> &HashMap<K, V> {
+        &self.table
+    }
+
     pub fn table_mut(&mut self) -> &mut HashMap<K, V> {
         &mut self.table
     }
@@ -103,7 +212,9 @@ impl<K, V> Bucket<K, V>
     }
 
     /// 显示桶中的数据
-    pub fn display(&self) {
+    pub fn display(&self)
+        where V: std::fmt::Display + Ord
+    {
         let mut ve = vec![];
         for (_, v) in &self.table {
             ve.push(v);
@@ -116,101 +227,351 @@ impl<K, V> Bucket<K, V>
     }
 }
 
-impl<K, V> ExtendiableHash<K, V> 
-    where K: Hash + Eq + Clone, V: std::fmt::Display + Clone + Copy + Ord
+impl<K, V> ExtendiableHash<K, V>
+    where K: Hash + Eq
 {
     pub fn new(global_depth: usize, bucket_cap: usize) -> Self {
+        Self::with_hasher(global_depth, bucket_cap, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, S> ExtendiableHash<K, V, S>
+    where K: Hash + Eq, S: BuildHasher
+{
+    /// 指定哈希函数, e.g. 测试时构造冲突, 或者换更快的哈希
+    pub fn with_hasher(global_depth: usize, bucket_cap: usize, hash_builder: S) -> Self {
         // 这里保证了每个目录项必有一个桶
         assert!(global_depth > 0);
+        // 桶容量为0时什么都放不进, stats()还要除以它
+        assert!(bucket_cap > 0);
 
-        let mut entries = vec![];
+        let mut buckets = vec![];
         for _ in 0..(1<<global_depth) {
-            entries.push(Rc::new(RefCell::new(Bucket::new(bucket_cap, global_depth))));
+            buckets.push(Bucket::new(bucket_cap, global_depth));
         }
         Self {
-            entries,
+            entries: (0..1<<global_depth).collect(),
+            buckets,
+            free: vec![],
+            len: 0,
             global_depth,
             bucket_cap,
+            max_global_depth: DEFAULT_MAX_GLOBAL_DEPTH.max(global_depth),
+            max_overflow: bucket_cap,
+            incremental: false,
+            growing: None,
+            migrations: HashMap::new(),
+            migrating_from: HashMap::new(),
+            hash_builder,
+        }
+    }
+
+    pub fn max_global_depth(&self) -> usize {
+        self.max_global_depth
+    }
+
+    /// 只影响之后的分裂, 已经比它深的目录不会收缩
+    pub fn set_max_global_depth(&mut self, max_global_depth: usize) {
+        assert!(max_global_depth > 0);
+        self.max_global_depth = max_global_depth;
+    }
+
+    pub fn max_overflow(&self) -> usize {
+        self.max_overflow
+    }
+
+    pub fn set_max_overflow(&mut self, max_overflow: usize) {
+        self.max_overflow = max_overflow;
+    }
+
+    pub fn incremental(&self) -> bool {
+        self.incremental
+    }
+
+    /// ⭐渐进模式: 目录翻倍不再一次复制完, 分裂也不再一次搬完旧桶
+    ///  而是之后的每次insert/remove/entry顺带复制GROW_STEP个目录项, 搬MIGRATE_STEP个kv, 类似线性哈希
+    ///  关闭时会先完成所有推迟的工作
+    pub fn set_incremental(&mut self, incremental: bool) {
+        self.incremental = incremental;
+        if !incremental {
+            self.finish_resize();
         }
     }
 
+    /// 一次性完成渐进模式下推迟的目录复制和数据搬迁
+    pub fn finish_resize(&mut self) {
+        self.grow_step(usize::MAX);
+        while let Some(&to) = self.migrations.keys().next() {
+            self.migrate(to, usize::MAX);
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn global_depth(&self) -> usize {
+        self.global_depth
+    }
+
     // 目录为空时的插入, 怎么保证目录自动增长?
     // 不可能目录为空, 因为global_depth: usize最小为0, 即会至少有一个桶
     //
     /// 找到相应的桶做插入操作, 当桶满时自动分裂
-    pub fn insert(&mut self, key: K, value: V) -> bool {
-        let bucket_id = self.hash(&key);
-        assert!(bucket_id < 1 << self.global_depth);
-
-        let bucket = self.entries[bucket_id].clone();
-        if bucket.borrow().contains_key(&key) {
-            return false;
+    ///  key已存在时覆盖, 返回旧value
+    ///  桶不能再分裂时一直溢出, 不会无限分裂下去; 要限制溢出用`try_insert`
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.insert_bounded(key, value, usize::MAX) {
+            Ok(old) => old,
+            Err(_) => unreachable!("overflow is unbounded"),
         }
+    }
 
-        if bucket.borrow_mut().is_full() {
-            self.split(bucket_id);
-            self.insert(key, value);
-        } else {
-            bucket.borrow_mut().insert(key, value);
-        }
-        true
+    /// 同`insert`, 桶不能再分裂且溢出超过`max_overflow`时返回错误
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<Option<V>, InsertError> {
+        self.insert_bounded(key, value, self.max_overflow)
     }
 
     /// 更新kv, 不存在时自动插入, 存在时自动覆盖
     pub fn update(&mut self, key: K, value: V) {
-        let bucket_id = self.hash(&key);
-        let mut bucket = self.entries[bucket_id].borrow_mut();
-        bucket.update(key, value);
+        self.insert(key, value);
     }
 
-    /// 3中模式: 
+    fn insert_bounded(&mut self, key: K, value: V, max_overflow: usize) -> Result<Option<V>, InsertError> {
+        self.step();
+        let b = self.bucket_for_insert(&key, max_overflow)?;
+        let old = self.buckets[b].insert(key, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        Ok(old)
+    }
+
+    /// 3中模式:
     ///  Merge: 自动合并
     ///  Shrink: 自动合并+自动收缩
     ///  No: 不压缩
-    pub fn remove(&mut self, key: &K, mode: Mode) {
-        let bucket_id = self.hash(&key);
-        let bucket = self.entries[bucket_id].clone();
-        bucket.borrow_mut().remove(key);
+    pub fn remove(&mut self, key: &K, mode: Mode) -> Option<V> {
+        self.step();
+        let bucket_id = self.hash(key);
+        let b = self.slot(bucket_id);
+        self.migrate_key(key, b);
+        let value = self.buckets[b].remove(key);
+        if value.is_some() {
+            self.len -= 1;
+        }
 
         match mode {
             Mode::No => {},
             Mode::Merge => self.merge(bucket_id),
             Mode::Shrink => self.shrink(bucket_id),
         }
+        value
+    }
+
+    /// 渐进模式下key可能还在分裂前的旧桶里
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let b = self.slot(self.hash(key));
+        self.buckets[b].get(key)
+            .or_else(|| self.migration_to(b).and_then(|m| self.buckets[m.from].get(key)))
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let b = self.slot(self.hash(key));
+        self.migrate_key(key, b);
+        self.buckets[b].get_mut(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// ⭐多个目录项会指向同一个桶, 所以不能按目录遍历, 而是直接遍历`buckets`
+    ///  free中的桶是merge掉的空桶, 遍历到也没关系
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { outer: self.buckets.iter(), inner: None, len: self.len }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut { outer: self.buckets.iter_mut(), inner: None, len: self.len }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { iter: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { iter: self.iter() }
+    }
+
+    /// 同`HashMap::clear`: 只清空数据, 目录和桶都保留
+    pub fn clear(&mut self) {
+        for bucket in &mut self.buckets {
+            bucket.table.clear();
+        }
+        self.migrations.clear();
+        self.migrating_from.clear();
+        self.len = 0;
+    }
+
+    /// 取出所有kv, 目录和桶都保留
+    ///  即使Drain没有遍历完, drop时也会清空剩下的桶
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        let len = std::mem::take(&mut self.len);
+        // 反正都要清空, 没搬完的也不用搬了
+        self.migrations.clear();
+        self.migrating_from.clear();
+        Drain { outer: self.buckets.iter_mut(), inner: None, len }
     }
 
-    pub fn get(&self, key: &K) -> Option<Ref<V>> {
-        let bucket_id = self.hash(&key);
-        let bucket = self.entries[bucket_id].clone();
-        if bucket.borrow().contains_key(&key) {
-            Some(Ref::map(self.entries[bucket_id].borrow(), |b| b.get(&key).unwrap()))
-        } else {
-            None
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        self.step();
+        let b = self.slot(self.hash(&key));
+        self.migrate_key(&key, b);
+        if !self.buckets[b].contains_key(&key) {
+            return Entry::Vacant(VacantEntry { map: self, key });
+        }
+        match self.buckets[b].table.entry(key) {
+            hash_map::Entry::Occupied(inner) => Entry::Occupied(OccupiedEntry { inner }),
+            hash_map::Entry::Vacant(_) => unreachable!(),
         }
     }
 
-    pub fn get_mut(&self, key: &K) -> Option<RefMut<V>> {
-        let bucket_id = self.hash(&key);
-        let bucket = self.entries[bucket_id].borrow();
-        if bucket.contains_key(&key) {
-            Some(RefMut::map(self.entries[bucket_id].borrow_mut(), |b| b.get_mut(&key).unwrap()))
-        } else {
-            None
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            global_depth: self.global_depth,
+            bucket_count: 0,
+            len: self.len,
+            fill: [0; 11],
+            overflowed: 0,
+            local_depths: vec![0; self.global_depth + 1],
+        };
+        for b in self.live_buckets() {
+            let bucket = &self.buckets[b];
+            stats.bucket_count += 1;
+            let len = self.effective_len(b);
+            stats.fill[(len * 10 / self.bucket_cap).min(10)] += 1;
+            stats.overflowed += (len > self.bucket_cap) as usize;
+            stats.local_depths[bucket.local_depth()] += 1;
+        }
+        stats
+    }
+
+    /// 检查目录结构, 出错时返回描述
+    ///  1. 目录大小是2^global_depth, 0 < local_depth <= global_depth
+    ///  2. ⭐指向同一个桶的目录项后local_depth位都相同, 且后缀相同的目录项都指向这个桶
+    ///  3. 桶里每个key的哈希值后local_depth位也是这个后缀, 渐进模式下也可以是还没搬走的新桶的后缀
+    ///  4. 所有桶的kv总数等于len; `insert`不限制溢出, 所以不检查桶大小
+    ///  5. free中的桶不在目录中
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let size = 1 << self.global_depth;
+        let copied = match self.growing {
+            Some(_) => self.entries.len() >= size / 2 && self.entries.len() < size,
+            None => self.entries.len() == size,
+        };
+        if !copied {
+            return Err(format!("directory size {} does not match 2^{}", self.entries.len(), self.global_depth));
+        }
+
+        // 每个桶的后缀
+        let mut suffix = vec![None; self.buckets.len()];
+        for i in 0..size {
+            let b = self.slot(i);
+            let d = self.buckets[b].local_depth();
+            if d == 0 || d > self.global_depth {
+                return Err(format!("bucket {} local_depth {} not in 1..={}", b, d, self.global_depth));
+            }
+            let s = i & ((1 << d) - 1);
+            if self.slot(s) != b {
+                return Err(format!("slot {:b} -> bucket {}, but slot {:b} -> bucket {}", i, b, s, self.slot(s)));
+            }
+            match suffix[b] {
+                Some(old) if old != s => {
+                    return Err(format!("bucket {} has two suffixes {:b} and {:b}", b, old, s));
+                },
+                _ => suffix[b] = Some(s),
+            }
+        }
+
+        if self.migrating_from.len() != self.migrations.len() {
+            return Err(format!("{} migrations but {} indexed by old bucket", self.migrations.len(), self.migrating_from.len()));
         }
+        for (&to, m) in &self.migrations {
+            if m.to != to || self.migrating_from.get(&m.from) != Some(&to) {
+                return Err(format!("migration {} -> {} is indexed wrongly", m.from, m.to));
+            }
+            if suffix[m.from].is_none() || suffix[m.to] != Some(m.suffix) {
+                return Err(format!("migration {} -> {} does not match directory", m.from, m.to));
+            }
+        }
+
+        let mut len = 0;
+        for b in self.live_buckets() {
+            let bucket = &self.buckets[b];
+            let mask = (1 << bucket.local_depth()) - 1;
+            let out = self.migration_from(b);
+            for k in bucket.table.keys() {
+                let h = self.hash_builder.hash_one(k) as usize;
+                if h & mask != suffix[b].unwrap() && out.is_none_or(|m| h & m.mask != m.suffix) {
+                    return Err(format!("key in bucket {} does not match suffix {:b}", b, suffix[b].unwrap()));
+                }
+            }
+            len += bucket.len();
+        }
+        if len != self.len {
+            return Err(format!("len {} != {} kv in buckets", self.len, len));
+        }
+
+        for &b in &self.free {
+            if suffix[b].is_some() {
+                return Err(format!("free bucket {} is still in directory", b));
+            }
+        }
+        Ok(())
+    }
+
+    /// 每个桶只返回一次: 后local_depth位就是目录项下标本身的那个目录项
+    fn live_buckets(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..1 << self.global_depth)
+            .map(|i| (i, self.slot(i)))
+            .filter(|&(i, b)| i < 1 << self.buckets[b].local_depth())
+            .map(|(_, b)| b)
+    }
+
+    /// 目录项指向的桶的local_depth
+    pub fn local_depth(&self, slot: usize) -> usize {
+        self.buckets[self.slot(slot)].local_depth()
+    }
+
+    /// 遍历目录项指向的桶
+    ///  渐进模式下遍历的是桶里实际存的kv, 可能有还没搬走的
+    pub fn bucket_iter(&self, slot: usize) -> Iter<'_, K, V> {
+        let table = &self.buckets[self.slot(slot)].table;
+        Iter { outer: [].iter(), inner: Some(table.iter()), len: table.len() }
     }
 
     /// 显示目录项映射关系和内容
-    pub fn display(&self) {
+    pub fn display(&self)
+        where V: std::fmt::Display + Ord
+    {
         println!("global_depth: {}\n", self.global_depth);
         for i in 0..1<<self.global_depth {
             print!("{}: ", self.bucket_id_string(i));
-            self.entries[i].borrow().display();
+            self.buckets[self.slot(i)].display();
         }
     }
 
     pub fn bucket_id_string(&self, bucket_id: usize) -> String {
         let mut str = String::new();
-        let mut d = self.entries[bucket_id].borrow().local_depth();
+        let mut d = self.local_depth(bucket_id);
         let mut n = bucket_id;
         while n > 0 && d > 0 {
             str = format!("{}{}", n%2, str);
@@ -228,9 +589,182 @@ impl<K, V> ExtendiableHash<K, V>
     ///  global_depth表示使用的bit数
     ///  e.g. 3: 1<<3 = 8 = 1000 而 (1<<3) - 1 = 111
     fn hash(&self, key: &K) -> usize {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        hasher.finish() as usize & ((1<<self.global_depth)-1)
+        self.hash_builder.hash_one(key) as usize & ((1<<self.global_depth)-1)
+    }
+
+    /// 目录项i指向的桶, 渐进翻倍时后半可能还没复制
+    fn slot(&self, i: usize) -> usize {
+        if let Some(&b) = self.entries.get(i) {
+            return b;
+        }
+        let half = 1 << (self.global_depth - 1);
+        match self.growing.as_ref().and_then(|g| g.overrides.get(&i)) {
+            Some(&b) => b,
+            None => self.entries[i - half],
+        }
+    }
+
+    fn set_slot(&mut self, i: usize, b: usize) {
+        if let Some(growing) = &mut self.growing {
+            let half = 1 << (self.global_depth - 1);
+            if i >= self.entries.len() {
+                growing.overrides.insert(i, b);
+                return;
+            }
+            // 后半对应的目录项还没复制, 它要保持原来的值
+            if i < half && i + half >= self.entries.len() {
+                growing.overrides.entry(i + half).or_insert(self.entries[i]);
+            }
+        }
+        self.entries[i] = b;
+    }
+
+    /// 渐进模式下每次修改操作前顺带做一点推迟的工作
+    fn step(&mut self) {
+        self.grow_step(GROW_STEP);
+        if let Some(&to) = self.migrations.keys().next() {
+            self.migrate(to, MIGRATE_STEP);
+        }
+    }
+
+    /// 复制n个还没复制的目录项
+    fn grow_step(&mut self, n: usize) {
+        let Some(growing) = &mut self.growing else { return };
+        let size = 1 << self.global_depth;
+        for _ in 0..n {
+            let i = self.entries.len();
+            if i == size {
+                break;
+            }
+            let b = growing.overrides.remove(&i).unwrap_or(self.entries[i - size / 2]);
+            self.entries.push(b);
+        }
+        if self.entries.len() == size {
+            self.growing = None;
+        }
+    }
+
+    /// 以b为新桶的搬迁
+    fn migration_to(&self, b: usize) -> Option<&Migration> {
+        self.migrations.get(&b)
+    }
+
+    /// 以b为旧桶的搬迁
+    fn migration_from(&self, b: usize) -> Option<&Migration> {
+        self.migrating_from.get(&b).map(|to| &self.migrations[to])
+    }
+
+    /// 搬迁完成后桶里应有的kv数, 判断桶满用这个
+    fn effective_len(&self, b: usize) -> usize {
+        let mut len = self.buckets[b].len();
+        if let Some(m) = self.migration_to(b) {
+            len += m.remaining;
+        }
+        if let Some(m) = self.migration_from(b) {
+            len -= m.remaining;
+        }
+        len
+    }
+
+    /// 从旧桶搬最多n个kv到新桶to
+    fn migrate(&mut self, to: usize, n: usize) {
+        let Migration { from, mask, suffix, .. } = self.migrations[&to];
+        let hash_builder = &self.hash_builder;
+        let moved: Vec<(K, V)> = self.buckets[from].table
+            .extract_if(|k, _| hash_builder.hash_one(k) as usize & mask == suffix)
+            .take(n)
+            .collect();
+        self.moved(to, moved.len());
+        self.buckets[to].table.extend(moved);
+    }
+
+    /// 搬了n个kv到新桶to, 搬完了就把搬迁删掉
+    fn moved(&mut self, to: usize, n: usize) {
+        let m = self.migrations.get_mut(&to).unwrap();
+        m.remaining -= n;
+        if m.remaining == 0 {
+            let from = m.from;
+            self.migrations.remove(&to);
+            self.migrating_from.remove(&from);
+        }
+    }
+
+    /// 修改key前先把它单独搬到目录项指向的桶b, 之后就只用看b了
+    fn migrate_key(&mut self, key: &K, b: usize) {
+        let Some(m) = self.migrations.get(&b) else { return };
+        if let Some((k, v)) = self.buckets[m.from].table.remove_entry(key) {
+            self.buckets[b].table.insert(k, v);
+            self.moved(b, 1);
+        }
+    }
+
+    /// 搬完和桶b有关的搬迁, 返回是否搬了
+    fn finish_migrations(&mut self, b: usize) -> bool {
+        let mut done = false;
+        if self.migrations.contains_key(&b) {
+            self.migrate(b, usize::MAX);
+            done = true;
+        }
+        if let Some(&to) = self.migrating_from.get(&b) {
+            self.migrate(to, usize::MAX);
+            done = true;
+        }
+        done
+    }
+
+
+    /// 找到key应该插入的桶的下标, 桶满时先分裂
+    ///  key已存在时不分裂, 直接返回所在的桶
+    ///  桶不能再分裂时溢出: 直接放进同一个哈希表, 相当于溢出链, 最多多放max_overflow个
+    fn bucket_for_insert(&mut self, key: &K, max_overflow: usize) -> Result<usize, InsertError> {
+        loop {
+            let bucket_id = self.hash(key);
+            let b = self.slot(bucket_id);
+            self.migrate_key(key, b);
+            let len = self.effective_len(b);
+            if self.buckets[b].contains_key(key) || len < self.bucket_cap {
+                return Ok(b);
+            }
+            // 分裂前先把这个桶的搬迁做完
+            if self.finish_migrations(b) {
+                continue;
+            }
+            if !self.can_split(b, key) {
+                return if len < self.bucket_cap.saturating_add(max_overflow) {
+                    Ok(b)
+                } else {
+                    Err(InsertError::Overflow)
+                };
+            }
+            self.split(bucket_id);
+        }
+    }
+
+    /// ⭐不能分裂的两种情况:
+    ///  1. 目录已经到了max_global_depth
+    ///  2. 桶里所有key和新key的完整哈希值都相同: 分裂多少次都分不开, 只会让目录无限翻倍
+    fn can_split(&self, b: usize, key: &K) -> bool {
+        let bucket = &self.buckets[b];
+        if bucket.local_depth() >= self.max_global_depth {
+            return false;
+        }
+        let h = self.hash_builder.hash_one(key);
+        bucket.table.keys().any(|k| self.hash_builder.hash_one(k) != h)
+    }
+
+    /// 优先复用merge空出来的位置
+    fn alloc_bucket(&mut self, local_depth: usize) -> usize {
+        let bucket = Bucket::new(self.bucket_cap, local_depth);
+        match self.free.pop() {
+            Some(b) => {
+                self.buckets[b] = bucket;
+                b
+            },
+            None => {
+                self.buckets.push(bucket);
+                self.buckets.len() - 1
+            },
+        }
     }
 
     /// 对应id的桶需要分裂
@@ -238,84 +772,101 @@ impl<K, V> ExtendiableHash<K, V>
     ///  2. 插入新entry, 并重新映射
     ///  3. 数据迁移: ⭐
     fn split(&mut self, bucket_id: usize) {
-        let bucket = self.entries[bucket_id].clone();
-        let new_depth = bucket.borrow_mut().depth_up();
+        let b = self.slot(bucket_id);
+        self.finish_migrations(b);
+        let new_depth = self.buckets[b].depth_up();
 
         if new_depth > self.global_depth {
             self.grow();
         }
 
         // 新桶和旧桶关系: 仅最高bit不同
-        let pair_bucket_id = bucket.borrow().pair_index(bucket_id);
-        self.entries[pair_bucket_id] = Rc::new(RefCell::new(Bucket::new(self.bucket_cap, new_depth)));
+        let pair_bucket_id = self.buckets[b].pair_index(bucket_id);
+        let pair = self.alloc_bucket(new_depth);
 
         // ⭐计算其他待重新映射的桶⭐
         //
         // 目前已知两独立的桶: bucket_id, new_bucket_id
         // 所有entries中根据后缀重新映射桶
         // 原本是都是映射到bucket_id, 所以现在与new_bucket_id后缀相同的映射需要变动
-        // TODO: 试一下后缀算法
         let step = 1 << new_depth;
         for i in (pair_bucket_id..1<<self.global_depth).step_by(step) {
-            self.entries[i] = self.entries[pair_bucket_id].clone();
+            self.set_slot(i, pair);
         }
         for i in (0..=pair_bucket_id).rev().step_by(step) {
-            self.entries[i] = self.entries[pair_bucket_id].clone();
+            self.set_slot(i, pair);
         }
 
-        // TODO: 数据拷贝开销怎么解决
-        // 旧桶中数据重新分配
-        let mut kv_store = vec![];
-        for (k, v) in bucket.borrow_mut().table_mut() {
-            let id = self.hash(k);
-            // println!("======{:x} {:x} {:x}== {} {}", id & (1<<new_depth)-1, pair_bucket_id & (1<<new_depth)-1, bucket_id & (1<<new_depth)-1, new_depth, self.global_depth);
-            if id & (1<<new_depth)-1 == pair_bucket_id & (1<<new_depth)-1 {
-                kv_store.push((k.clone(), v.clone()));
+        let mask = (1 << new_depth) - 1;
+        let hash_builder = &self.hash_builder;
+        if self.incremental {
+            // 只数出要搬几个, 数据留给之后的操作慢慢搬
+            let remaining = self.buckets[b].table
+                .keys()
+                .filter(|k| hash_builder.hash_one(k) as usize & mask == pair_bucket_id & mask)
+                .count();
+            if remaining > 0 {
+                self.migrations.insert(pair, Migration { from: b, to: pair, mask, suffix: pair_bucket_id & mask, remaining });
+                self.migrating_from.insert(b, pair);
             }
+            return;
         }
 
-        for (k, v) in kv_store {
-            bucket.borrow_mut().table_mut().remove(&k);
-            self.insert(k, v);
-        }
+        // 旧桶中属于新桶的数据直接搬到新桶, 不用再走一遍insert
+        //  新桶的数据来自旧桶, 所以不会超过容量
+        let moved: Vec<(K, V)> = self.buckets[b].table
+            .extract_if(|k, _| hash_builder.hash_one(k) as usize & mask == pair_bucket_id & mask)
+            .collect();
+        self.buckets[pair].table.extend(moved);
     }
 
     /// 当前桶元素删除, 如果删除后桶空则合并
     /// 桶重新映射, 回收空entries
     fn merge(&mut self, bucket_id: usize) {
-        let bucket = self.entries[bucket_id].clone();
+        let b = self.slot(bucket_id);
+        self.finish_migrations(b);
+        let bucket = &self.buckets[b];
         // 只有桶为空, local_depth>0时才会有合并
-        if !bucket.borrow().is_empty() || bucket.borrow().local_depth() <= 1 {
+        if !bucket.is_empty() || bucket.local_depth() <= 1 {
             return;
         }
 
-        let current_depth = bucket.borrow().local_depth();
-        let pair_bucket_id = bucket.borrow().pair_index(bucket_id);
-        let pair_bucket = self.entries[pair_bucket_id].clone();
+        let current_depth = bucket.local_depth();
+        let pair_bucket_id = bucket.pair_index(bucket_id);
+        let pair = self.slot(pair_bucket_id);
         // 另一半已经扩容, 不能合并
-        if pair_bucket.borrow().local_depth() != current_depth {
+        if self.buckets[pair].local_depth() != current_depth {
             return;
         }
+        self.finish_migrations(pair);
         let step = 1 << current_depth;
         // 找到待重新映射的所有桶
         for i in (bucket_id..1<<self.global_depth).step_by(step) {
-            self.entries[i] = pair_bucket.clone();
+            self.set_slot(i, pair);
         }
         for i in (0..=bucket_id).rev().step_by(step) {
-            self.entries[i] = pair_bucket.clone();
+            self.set_slot(i, pair);
         }
-        pair_bucket.borrow_mut().depth_down();
+        self.buckets[pair].depth_down();
+        self.free.push(b);
     }
 
     /// 目录项翻倍, 全局深度增加, 目录项重新映射
     ///  重新映射的方法: 直接从头到位append, 因为二进制翻倍的特定, 后缀刚好能够相同
+    ///  渐进模式下只记下翻倍了, 之后的操作再慢慢复制
     fn grow(&mut self) {
+        // 上一次翻倍还没复制完, 只能先复制完
+        self.grow_step(usize::MAX);
+        self.global_depth += 1;
+        if self.incremental {
+            self.growing = Some(Growing::default());
+            return;
+        }
         // 使用reserve预留len+additional的空间, 防止频繁分配
         self.entries.reserve(self.entries.len());
-        for i in 0..1<<self.global_depth {
-            self.entries.push(self.entries[i].clone())
+        for i in 0..1<<(self.global_depth - 1) {
+            self.entries.push(self.entries[i])
         }
-        self.global_depth += 1;
     }
 
     /// 先merge回收空桶, 再shrink回收目录项
@@ -325,10 +876,12 @@ impl<K, V> ExtendiableHash<K, V>
         if self.global_depth == 0 {
             return;
         }
+        // 压缩要看完整的目录
+        self.grow_step(usize::MAX);
 
         // 如果存在local_depth == global_depth的目录项, 说明存则使用后半项的桶, 不能压缩
-        for b in &self.entries {
-            if b.borrow().local_depth() == self.global_depth { return; }
+        for &b in &self.entries {
+            if self.buckets[b].local_depth() == self.global_depth { return; }
         }
 
         // 如果不存在local_depth与global_depth相同则说明每个桶至少有两个引用
@@ -340,3 +893,294 @@ impl<K, V> ExtendiableHash<K, V>
         }
     }
 }
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
+    where K: Hash + Eq, S: BuildHasher
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+        where V: Default
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(e) = &mut self {
+            f(e.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        self.inner.key()
+    }
+
+    pub fn get(&self) -> &V {
+        self.inner.get()
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.inner.get_mut()
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.inner.into_mut()
+    }
+
+    /// 覆盖value, 返回旧value
+    pub fn insert(&mut self, value: V) -> V {
+        self.inner.insert(value)
+    }
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S>
+    where K: Hash + Eq, S: BuildHasher
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// 同`ExtendiableHash::insert`, 桶满时先分裂
+    pub fn insert(self, value: V) -> &'a mut V {
+        match self.insert_bounded(value, usize::MAX) {
+            Ok(v) => v,
+            Err(_) => unreachable!("overflow is unbounded"),
+        }
+    }
+
+    /// 同`ExtendiableHash::try_insert`
+    pub fn try_insert(self, value: V) -> Result<&'a mut V, InsertError> {
+        let max_overflow = self.map.max_overflow;
+        self.insert_bounded(value, max_overflow)
+    }
+
+    fn insert_bounded(self, value: V, max_overflow: usize) -> Result<&'a mut V, InsertError> {
+        let b = self.map.bucket_for_insert(&self.key, max_overflow)?;
+        self.map.len += 1;
+        Ok(self.map.buckets[b].table.entry(self.key).or_insert(value))
+    }
+}
+
+impl<K, V, S> Default for ExtendiableHash<K, V, S>
+    where K: Hash + Eq, S: BuildHasher + Default
+{
+    fn default() -> Self {
+        Self::with_hasher(DEFAULT_GLOBAL_DEPTH, DEFAULT_BUCKET_CAP, S::default())
+    }
+}
+
+/// 同`insert`, 相同的key后面的覆盖前面的; 不会panic, 桶不能再分裂时一直溢出
+impl<K, V, S> Extend<(K, V)> for ExtendiableHash<K, V, S>
+    where K: Hash + Eq, S: BuildHasher
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for ExtendiableHash<K, V, S>
+    where K: Hash + Eq, S: BuildHasher + Default
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut m = Self::default();
+        m.extend(iter);
+        m
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a ExtendiableHash<K, V, S>
+    where K: Hash + Eq, S: BuildHasher
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut ExtendiableHash<K, V, S>
+    where K: Hash + Eq, S: BuildHasher
+{
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> IntoIterator for ExtendiableHash<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { outer: self.buckets.into_iter(), inner: None, len: self.len }
+    }
+}
+
+/// 以下迭代器都是逐个桶展开桶里的哈希表
+///  len记录剩余个数, 这样size_hint是准确的
+fn flat_next<B, I: Iterator>(
+    outer: &mut impl Iterator<Item = B>,
+    inner: &mut Option<I>,
+    len: &mut usize,
+    open: impl Fn(B) -> I,
+) -> Option<I::Item> {
+    loop {
+        if let Some(item) = inner.as_mut().and_then(Iterator::next) {
+            *len -= 1;
+            return Some(item);
+        }
+        *inner = Some(open(outer.next()?));
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    outer: slice::Iter<'a, Bucket<K, V>>,
+    inner: Option<hash_map::Iter<'a, K, V>>,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        flat_next(&mut self.outer, &mut self.inner, &mut self.len, |b| b.table.iter())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+pub struct IterMut<'a, K, V> {
+    outer: slice::IterMut<'a, Bucket<K, V>>,
+    inner: Option<hash_map::IterMut<'a, K, V>>,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        flat_next(&mut self.outer, &mut self.inner, &mut self.len, |b| b.table.iter_mut())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+pub struct IntoIter<K, V> {
+    outer: vec::IntoIter<Bucket<K, V>>,
+    inner: Option<hash_map::IntoIter<K, V>>,
+    len: usize,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        flat_next(&mut self.outer, &mut self.inner, &mut self.len, |b| b.table.into_iter())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+pub struct Drain<'a, K, V> {
+    outer: slice::IterMut<'a, Bucket<K, V>>,
+    inner: Option<hash_map::Drain<'a, K, V>>,
+    len: usize,
+}
+
+impl<K, V> Iterator for Drain<'_, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        flat_next(&mut self.outer, &mut self.inner, &mut self.len, |b| b.table.drain())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Drain<'_, K, V> {}
+
+impl<K, V> Drop for Drain<'_, K, V> {
+    /// 当前桶的hash_map::Drain自己会清空, 这里清空还没遍历到的桶
+    fn drop(&mut self) {
+        for bucket in &mut self.outer {
+            bucket.table.clear();
+        }
+    }
+}
+
+pub struct Keys<'a, K, V> {
+    iter: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+pub struct Values<'a, K, V> {
+    iter: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Values<'_, K, V> {}
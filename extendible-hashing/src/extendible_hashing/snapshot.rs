@@ -0,0 +1,130 @@
+//! serde支持: 保存完整的目录形状, 恢复后global_depth, local_depth和桶的共享关系都不变
+//!
+//! 哈希函数不保存, 恢复时用`S::default()`, 所以要和保存时用同一种哈希函数
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{Bucket, ExtendiableHash};
+
+/// 只保存还在目录中的桶, 目录项记录的是`buckets`中的下标
+///  渐进模式下推迟的工作按做完了保存
+#[derive(Serialize)]
+struct SnapshotRef<'a, K, V> {
+    global_depth: usize,
+    bucket_cap: usize,
+    max_global_depth: usize,
+    max_overflow: usize,
+    incremental: bool,
+    entries: Vec<usize>,
+    /// (local_depth, kv)
+    buckets: Vec<(usize, Vec<(&'a K, &'a V)>)>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot<K, V> {
+    global_depth: usize,
+    bucket_cap: usize,
+    max_global_depth: usize,
+    max_overflow: usize,
+    // 旧版本的快照没有这一项
+    #[serde(default)]
+    incremental: bool,
+    entries: Vec<usize>,
+    buckets: Vec<(usize, Vec<(K, V)>)>,
+}
+
+impl<K, V, S> Serialize for ExtendiableHash<K, V, S>
+    where K: Hash + Eq + Serialize, V: Serialize, S: BuildHasher
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        // 去掉free中的桶, 重新编号
+        let mut index = vec![0; self.buckets.len()];
+        let mut buckets: Vec<(usize, Vec<_>)> = vec![];
+        for b in self.live_buckets() {
+            index[b] = buckets.len();
+            buckets.push((self.buckets[b].local_depth(), vec![]));
+        }
+        // 还没搬走的kv直接放进新桶
+        for b in self.live_buckets() {
+            let out = self.migration_from(b);
+            for (k, v) in &self.buckets[b].table {
+                let h = self.hash_builder.hash_one(k) as usize;
+                let to = match out {
+                    Some(m) if h & m.mask == m.suffix => m.to,
+                    _ => b,
+                };
+                buckets[index[to]].1.push((k, v));
+            }
+        }
+
+        SnapshotRef {
+            global_depth: self.global_depth,
+            bucket_cap: self.bucket_cap,
+            max_global_depth: self.max_global_depth,
+            max_overflow: self.max_overflow,
+            incremental: self.incremental,
+            entries: (0..1 << self.global_depth).map(|i| index[self.slot(i)]).collect(),
+            buckets,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for ExtendiableHash<K, V, S>
+    where K: Hash + Eq + Deserialize<'de>, V: Deserialize<'de>, S: BuildHasher + Default
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot = Snapshot::<K, V>::deserialize(deserializer)?;
+
+        // 先检查能不能安全地建出来, 其余的交给check_invariants
+        if snapshot.global_depth == 0
+            || snapshot.global_depth >= usize::BITS as usize
+            || snapshot.entries.len() != 1 << snapshot.global_depth
+        {
+            return Err(D::Error::custom("directory size does not match global_depth"));
+        }
+        if snapshot.bucket_cap == 0 || snapshot.max_global_depth == 0 {
+            return Err(D::Error::custom("bucket_cap and max_global_depth must be positive"));
+        }
+        if snapshot.entries.iter().any(|&b| b >= snapshot.buckets.len()) {
+            return Err(D::Error::custom("directory entry out of range"));
+        }
+
+        let mut len = 0;
+        let mut buckets = Vec::with_capacity(snapshot.buckets.len());
+        for (local_depth, table) in snapshot.buckets {
+            let mut bucket = Bucket::new(snapshot.bucket_cap, local_depth);
+            // ⭐同一个桶里的重复key会被HashMap吞掉, check_invariants发现不了, 只能在这里查
+            for (k, v) in table {
+                if bucket.table.insert(k, v).is_some() {
+                    return Err(D::Error::custom("duplicate key"));
+                }
+            }
+            len += bucket.len();
+            buckets.push(bucket);
+        }
+
+        let m = Self {
+            entries: snapshot.entries,
+            buckets,
+            free: vec![],
+            len,
+            global_depth: snapshot.global_depth,
+            bucket_cap: snapshot.bucket_cap,
+            max_global_depth: snapshot.max_global_depth,
+            max_overflow: snapshot.max_overflow,
+            incremental: snapshot.incremental,
+            growing: None,
+            migrations: HashMap::new(),
+            migrating_from: HashMap::new(),
+            hash_builder: S::default(),
+        };
+        // 哈希函数不同, 桶没有被目录引用, 不同桶里的重复key等都会在这里发现
+        m.check_invariants().map_err(D::Error::custom)?;
+        Ok(m)
+    }
+}
@@ -1,3 +1,9 @@
 pub mod extendible_hashing;
+pub mod concurrent_extendible_hashing;
+pub mod disk_extendible_hashing;
+pub mod linear_hashing;
 
 pub use crate::extendible_hashing::*;
+pub use crate::concurrent_extendible_hashing::*;
+pub use crate::disk_extendible_hashing::*;
+pub use crate::linear_hashing::*;
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use crate::extendible_hashing::{DefaultHashBuilder, Mode, DEFAULT_BUCKET_CAP};
+
+/// 默认装填因子: 平均每个桶装到bucket_cap的80%就分裂
+pub const DEFAULT_MAX_LOAD_FACTOR: f64 = 0.8;
+/// 装填因子的下限: 太小的话一次insert要分裂出len/(bucket_cap*max_load_factor)个桶, e.g. 1e-9直接把内存吃光
+pub const MIN_MAX_LOAD_FACTOR: f64 = 0.1;
+
+/// 线性哈希
+///
+/// 和可扩展哈希不同, 没有目录, 桶满了也不是分裂这个桶:
+///  - 桶按顺序编号, 有一个分裂指针split, 每次只分裂split指向的桶, 然后split后移一位
+///  - 装填因子`len / (桶数 * bucket_cap)`超过`max_load_factor`时才分裂, 所以某个桶可以暂时超过bucket_cap, 相当于溢出链
+///  - 一轮(2^level个桶)分裂完后level加一, split回到0
+pub struct LinearHash<K, V, S = DefaultHashBuilder> {
+    buckets: Vec<HashMap<K, V>>,
+    // 本轮开始时有2^level个桶
+    level: usize,
+    // 初始的level, 收缩不会低于它
+    init_level: usize,
+    // 下一个要分裂的桶
+    split: usize,
+    len: usize,
+    bucket_cap: usize,
+    max_load_factor: f64,
+    hash_builder: S,
+}
+
+impl<K, V> LinearHash<K, V>
+    where K: Hash + Eq
+{
+    /// 参数同`ExtendiableHash::new`: 初始2^level个桶
+    pub fn new(level: usize, bucket_cap: usize) -> Self {
+        Self::with_hasher(level, bucket_cap, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, S> LinearHash<K, V, S>
+    where K: Hash + Eq, S: BuildHasher
+{
+    pub fn with_hasher(level: usize, bucket_cap: usize, hash_builder: S) -> Self {
+        assert!(bucket_cap > 0);
+        let buckets = (0..1 << level).map(|_| HashMap::with_capacity(bucket_cap)).collect();
+        Self {
+            buckets,
+            level,
+            init_level: level,
+            split: 0,
+            len: 0,
+            bucket_cap,
+            max_load_factor: DEFAULT_MAX_LOAD_FACTOR,
+            hash_builder,
+        }
+    }
+
+    pub fn max_load_factor(&self) -> f64 {
+        self.max_load_factor
+    }
+
+    /// 超过它就分裂; remove带Merge/Shrink时, 低于它的一半就收缩
+    ///  调小了会在下次insert时一口气分裂到满足为止, 所以不能小于MIN_MAX_LOAD_FACTOR
+    pub fn set_max_load_factor(&mut self, max_load_factor: f64) {
+        // 顺便挡住NaN
+        assert!(max_load_factor >= MIN_MAX_LOAD_FACTOR, "max_load_factor must be at least {}", MIN_MAX_LOAD_FACTOR);
+        self.max_load_factor = max_load_factor;
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / (self.buckets.len() * self.bucket_cap) as f64
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// 分裂指针
+    pub fn split_pointer(&self) -> usize {
+        self.split
+    }
+
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// key已存在时覆盖, 返回旧value
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let b = self.bucket_index(&key);
+        let old = self.buckets[b].insert(key, value);
+        if old.is_none() {
+            self.len += 1;
+            while self.load_factor() > self.max_load_factor {
+                self.split_next();
+            }
+        }
+        old
+    }
+
+    /// 更新kv, 不存在时自动插入, 存在时自动覆盖
+    ///  没有溢出上限, 所以不会失败
+    pub fn update(&mut self, key: K, value: V) {
+        self.insert(key, value);
+    }
+
+    /// 模式同`ExtendiableHash::remove`, 不过线性哈希只能从最后一个桶开始收缩
+    ///  所以Merge和Shrink一样: 装填因子低于max_load_factor的一半时收缩
+    pub fn remove(&mut self, key: &K, mode: Mode) -> Option<V> {
+        let b = self.bucket_index(key);
+        let value = self.buckets[b].remove(key);
+        if value.is_some() {
+            self.len -= 1;
+            if mode != Mode::No {
+                while self.buckets.len() > 1 << self.init_level && self.load_factor() < self.max_load_factor / 2.0 {
+                    self.merge_last();
+                }
+            }
+        }
+        value
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.buckets[self.bucket_index(key)].get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let b = self.bucket_index(key);
+        self.buckets[b].get_mut(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.buckets.iter().flatten()
+    }
+
+    /// 只清空数据, 桶都保留
+    pub fn clear(&mut self) {
+        for bucket in &mut self.buckets {
+            bucket.clear();
+        }
+        self.len = 0;
+    }
+
+    /// 检查结构, 出错时返回描述
+    ///  1. 桶数是2^level + split, split < 2^level
+    ///  2. 每个key都在bucket_index算出来的桶里
+    ///  3. 总数等于len
+    pub fn check_invariants(&self) -> Result<(), String> {
+        if self.split >= 1 << self.level || self.buckets.len() != (1 << self.level) + self.split {
+            return Err(format!("{} buckets, level {}, split {}", self.buckets.len(), self.level, self.split));
+        }
+        let mut len = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            for k in bucket.keys() {
+                if self.bucket_index(k) != i {
+                    return Err(format!("key in bucket {} should be in bucket {}", i, self.bucket_index(k)));
+                }
+            }
+            len += bucket.len();
+        }
+        if len != self.len {
+            return Err(format!("len {} != {} kv in buckets", self.len, len));
+        }
+        Ok(())
+    }
+
+    /// ⭐先用level位, 如果这个桶本轮已经分裂过了(在split前面), 再多用一位
+    fn bucket_index(&self, key: &K) -> usize {
+        let h = self.hash_builder.hash_one(key) as usize;
+        let i = h & ((1 << self.level) - 1);
+        if i < self.split {
+            h & ((1 << (self.level + 1)) - 1)
+        } else {
+            i
+        }
+    }
+
+    /// 分裂split指向的桶: 多看一位, 为1的搬到新桶split + 2^level
+    fn split_next(&mut self) {
+        let high = 1 << self.level;
+        let hash_builder = &self.hash_builder;
+        let moved: HashMap<K, V> = self.buckets[self.split]
+            .extract_if(|k, _| hash_builder.hash_one(k) as usize & high != 0)
+            .collect();
+        self.buckets.push(moved);
+
+        self.split += 1;
+        if self.split == high {
+            self.level += 1;
+            self.split = 0;
+        }
+    }
+
+    /// split_next的逆操作: 最后一个桶合并回它分裂前的桶
+    fn merge_last(&mut self) {
+        if self.split == 0 {
+            self.level -= 1;
+            self.split = 1 << self.level;
+        }
+        self.split -= 1;
+        let last = self.buckets.pop().unwrap();
+        let bucket = &mut self.buckets[self.split];
+        // 小的往大的里合并
+        if bucket.len() < last.len() {
+            let small = mem::replace(bucket, last);
+            bucket.extend(small);
+        } else {
+            bucket.extend(last);
+        }
+    }
+}
+
+impl<K, V, S> Default for LinearHash<K, V, S>
+    where K: Hash + Eq, S: BuildHasher + Default
+{
+    fn default() -> Self {
+        Self::with_hasher(0, DEFAULT_BUCKET_CAP, S::default())
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for LinearHash<K, V, S>
+    where K: Hash + Eq, S: BuildHasher
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for LinearHash<K, V, S>
+    where K: Hash + Eq, S: BuildHasher + Default
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut m = Self::default();
+        m.extend(iter);
+        m
+    }
+}
+
+impl<K, V, S> IntoIterator for LinearHash<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = std::iter::Flatten<std::vec::IntoIter<HashMap<K, V>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.buckets.into_iter().flatten()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a LinearHash<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = std::iter::Flatten<std::slice::Iter<'a, HashMap<K, V>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.buckets.iter().flatten()
+    }
+}
@@ -1,54 +1,252 @@
-use std::io;
+use std::fs::File;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::io::{self, BufRead, BufReader};
+use std::process::ExitCode;
 use ::extendible_hashing::*;
+use serde_json::json;
 
+const USAGE: &str = "usage: extendible_hashing [--json] [SCRIPT]
 
-fn main() {
-    let mut line = String::new();
-    io::stdin().read_line(&mut line).expect("error input\n");
-    let bucket_cap = line.trim().parse::<usize>().expect("not a num");
+Reads commands from SCRIPT, or stdin if no SCRIPT is given.
+The first two lines are bucket_cap and global_depth, then one command per line:
+    insert <key> <value>
+    delete <key>
+    update <key> <value>
+    search <key>
+    display
+    stats
+    exit
+Empty lines and lines starting with # are skipped.";
 
-    line.clear();
-    io::stdin().read_line(&mut line).expect("error input\n");
-    let global_depth = line.trim().parse::<usize>().expect("not a num");
-    // println!("bucket_size: {} , global_depth: {}", bucket_cap, global_depth);
+/// 桶会按bucket_cap预分配, 防止一个大数字直接把内存吃光
+const MAX_BUCKET_CAP: usize = 1 << 20;
 
-    let mut dir = ExtendiableHash::new(global_depth, bucket_cap);
+/// FNV-1a, 同磁盘版
+///  ⭐`DefaultHasher`不保证跨版本稳定, 换个rust版本golden测试就挂了
+struct Fnv(u64);
 
-    loop {
-        line.clear();
-        io::stdin().read_line(&mut line).expect("error input\n");
-        let mut words = line.trim().split_whitespace();
-        let cmd = words.next().unwrap();
-        match cmd {
-            "insert" => {
-                let key = words.next().unwrap().parse::<i64>().unwrap();
-                let value = words.next().unwrap().parse::<i64>().unwrap();
-                dir.insert(key, value);
-            },
-            "delete" => {
-                let key = words.next().unwrap().parse::<i64>().unwrap();
-                dir.remove(&key, Mode::No);
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+type Table = ExtendiableHash<i64, i64, BuildHasherDefault<Fnv>>;
+
+enum Command {
+    Insert(i64, i64),
+    Delete(i64),
+    Update(i64, i64),
+    Search(i64),
+    Display,
+    Stats,
+    Exit,
+}
+
+/// 输出格式, 错误也按这个格式输出到stderr
+struct Output {
+    json: bool,
+}
+
+fn parse_num<T: std::str::FromStr>(word: Option<&str>, name: &str) -> Result<T, String> {
+    let word = word.ok_or_else(|| format!("missing {}", name))?;
+    word.parse().map_err(|_| format!("invalid {}: {}", name, word))
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.trim().split_whitespace();
+    let cmd = words.next().unwrap_or_default();
+    let command = match cmd {
+        "insert" => Command::Insert(parse_num(words.next(), "key")?, parse_num(words.next(), "value")?),
+        "delete" => Command::Delete(parse_num(words.next(), "key")?),
+        "update" => Command::Update(parse_num(words.next(), "key")?, parse_num(words.next(), "value")?),
+        "search" => Command::Search(parse_num(words.next(), "key")?),
+        "display" => Command::Display,
+        "stats" => Command::Stats,
+        "exit" => Command::Exit,
+        _ => return Err(format!("invalid command: {}", cmd)),
+    };
+    match words.next() {
+        Some(word) => Err(format!("unexpected argument: {}", word)),
+        None => Ok(command),
+    }
+}
+
+impl Output {
+    fn error(&self, line: usize, msg: &str) {
+        if self.json {
+            eprintln!("{}", json!({"line": line, "error": msg}));
+        } else {
+            eprintln!("line {}: {}", line, msg);
+        }
+    }
+
+    fn search(&self, key: i64, value: Option<&i64>) {
+        if self.json {
+            println!("{}", json!({"key": key, "value": value}));
+        } else {
+            println!("{:?}", value);
+        }
+    }
+
+    fn display(&self, dir: &Table) {
+        if !self.json {
+            dir.display();
+            return;
+        }
+        let slots: Vec<_> = (0..1 << dir.global_depth())
+            .map(|i| {
+                let mut values: Vec<&i64> = dir.bucket_iter(i).map(|(_, v)| v).collect();
+                values.sort();
+                json!({"slot": dir.bucket_id_string(i), "local_depth": dir.local_depth(i), "values": values})
+            })
+            .collect();
+        println!("{}", json!({"global_depth": dir.global_depth(), "slots": slots}));
+    }
+
+    fn stats(&self, stats: &Stats) {
+        if self.json {
+            println!(
+                "{}",
+                json!({
+                    "global_depth": stats.global_depth,
+                    "bucket_count": stats.bucket_count,
+                    "len": stats.len,
+                    "fill": stats.fill,
+                    "overflowed": stats.overflowed,
+                    "local_depths": stats.local_depths,
+                })
+            );
+        } else {
+            println!("{:?}", stats);
+        }
+    }
+}
+
+/// 执行一条命令, 返回false表示exit
+fn execute(dir: &mut Table, command: Command, out: &Output) -> Result<bool, String> {
+    match command {
+        Command::Insert(key, value) => {
+            dir.try_insert(key, value).map_err(|e| e.to_string())?;
+        },
+        Command::Delete(key) => {
+            dir.remove(&key, Mode::No);
+        },
+        Command::Update(key, value) => {
+            dir.update(key, value);
+        },
+        Command::Search(key) => out.search(key, dir.get(&key)),
+        Command::Display => out.display(dir),
+        Command::Stats => out.stats(&dir.stats()),
+        Command::Exit => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// 读下一行有效的行, 返回(行号, 内容), EOF返回None
+fn next_line(lines: &mut impl Iterator<Item = (usize, io::Result<String>)>) -> io::Result<Option<(usize, String)>> {
+    for (i, line) in lines {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            return Ok(Some((i + 1, line.to_string())));
+        }
+    }
+    Ok(None)
+}
+
+/// 读取开头的bucket_cap或global_depth, 范围是1..=max
+fn header(lines: &mut impl Iterator<Item = (usize, io::Result<String>)>, name: &str, max: usize, out: &Output) -> Option<usize> {
+    match next_line(lines) {
+        Ok(Some((n, line))) => match line.parse::<usize>() {
+            Ok(v) if v > 0 && v <= max => Some(v),
+            _ => {
+                out.error(n, &format!("invalid {}: {}", name, line));
+                None
             },
-            "update" => {
-                let key = words.next().unwrap().parse::<i64>().unwrap();
-                let value = words.next().unwrap().parse::<i64>().unwrap();
-                dir.update(key, value);
+        },
+        Ok(None) => {
+            out.error(0, &format!("missing {}", name));
+            None
+        },
+        Err(e) => {
+            out.error(0, &e.to_string());
+            None
+        },
+    }
+}
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut script = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
             },
-            "search" => {
-                let key = words.next().unwrap().parse::<i64>().unwrap();
-                let value =dir.get(&key);
-                println!("{:#?}", value);
+            _ if arg.starts_with('-') || script.is_some() => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
             },
-            "display" => {
-                dir.display();
-                // println!("display");
+            _ => script = Some(arg),
+        }
+    }
+    let out = Output { json };
+
+    let input: Box<dyn BufRead> = match &script {
+        Some(path) => match File::open(path) {
+            Ok(f) => Box::new(BufReader::new(f)),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return ExitCode::from(2);
             },
-            "exit" => {
-                break;
+        },
+        None => Box::new(io::stdin().lock()),
+    };
+    let mut lines = input.lines().enumerate();
+
+    let Some(bucket_cap) = header(&mut lines, "bucket_cap", MAX_BUCKET_CAP, &out) else {
+        return ExitCode::from(2);
+    };
+    let Some(global_depth) = header(&mut lines, "global_depth", DEFAULT_MAX_GLOBAL_DEPTH, &out) else {
+        return ExitCode::from(2);
+    };
+    let mut dir = Table::with_hasher(global_depth, bucket_cap, Default::default());
+
+    // 出错的行跳过, 继续执行后面的命令, 最后以1退出
+    let mut failed = false;
+    loop {
+        let (n, line) = match next_line(&mut lines) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                out.error(0, &e.to_string());
+                return ExitCode::from(2);
             },
-            _ => {
-                println!("invalid command: {}", cmd);
+        };
+        match parse_command(&line).and_then(|command| execute(&mut dir, command, &out)) {
+            Ok(true) => {},
+            Ok(false) => break,
+            Err(msg) => {
+                out.error(n, &msg);
+                failed = true;
             },
         }
     }
+
+    if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
 }
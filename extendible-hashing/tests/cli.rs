@@ -0,0 +1,81 @@
+//! 用golden文件测试命令行
+//!  tests/golden/<name>.script是输入, <name>[.json].{out,err,code}是期望的stdout, stderr和退出码
+//!  命令行用固定的FNV哈希, 输出不随rust版本变, 需要更新时用`UPDATE_GOLDEN=1 cargo test --features cli`重新生成
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::io::Write;
+
+fn golden(name: &str, json: bool) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let script = dir.join(format!("{}.script", name));
+    let mut args = vec![];
+    if json {
+        args.push("--json".to_string());
+    }
+    args.push(script.to_str().unwrap().to_string());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_extendible_hashing"))
+        .args(&args)
+        .output()
+        .unwrap();
+    let actual = [
+        ("out", String::from_utf8(output.stdout).unwrap()),
+        ("err", String::from_utf8(output.stderr).unwrap()),
+        ("code", format!("{}\n", output.status.code().unwrap())),
+    ];
+
+    let prefix = if json { format!("{}.json", name) } else { name.to_string() };
+    for (ext, actual) in actual {
+        let path = dir.join(format!("{}.{}", prefix, ext));
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&path, &actual).unwrap();
+        }
+        let expect = fs::read_to_string(&path).unwrap();
+        assert_eq!(actual, expect, "{} differs", path.display());
+    }
+}
+
+#[test]
+fn golden_basic() {
+    golden("basic", false);
+}
+
+#[test]
+fn golden_basic_json() {
+    golden("basic", true);
+}
+
+#[test]
+fn golden_bad_header() {
+    golden("bad_header", false);
+}
+
+#[test]
+fn golden_bad_header_json() {
+    golden("bad_header", true);
+}
+
+#[test]
+fn stdin_eof() {
+    // 没有exit, 读到EOF正常退出
+    let mut child = Command::new(env!("CARGO_BIN_EXE_extendible_hashing"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"4\n2\ninsert 1 2\nsearch 1\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Some(2)\n");
+}
+
+#[test]
+fn bad_args() {
+    let output = Command::new(env!("CARGO_BIN_EXE_extendible_hashing"))
+        .arg("--unknown")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -1,4 +1,7 @@
-use extendible_hashing::{Mode,ExtendiableHash};
+use extendible_hashing::{Mode, ExtendiableHash, ConcurrentExtendibleHash, DiskExtendibleHash, InsertError, LinearHash};
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::path::PathBuf;
 
 const SET_SIZE: usize = 1 << 10;
 type K = u64;
@@ -15,12 +18,14 @@ fn stress_crate_no() {
     let mut m = ExtendiableHash::new(2, 32);
     for (k, v) in &workload {
         m.insert(k.clone(), v.clone());
+        m.check_invariants().unwrap();
     }
     for (k, v) in &workload {
-        assert_eq!(&*m.get(k).unwrap(), v);
+        assert_eq!(m.get(k), Some(v));
     }
     for (k, _) in &workload {
         m.remove(k, Mode::No);
+        m.check_invariants().unwrap();
     }
     for (k, _) in &workload {
         assert!(m.get(k).is_none());
@@ -38,12 +43,14 @@ fn stress_crate_merge() {
     let mut m = ExtendiableHash::new(2, 32);
     for (k, v) in &workload {
         m.insert(k.clone(), v.clone());
+        m.check_invariants().unwrap();
     }
     for (k, v) in &workload {
-        assert_eq!(&*m.get(k).unwrap(), v);
+        assert_eq!(m.get(k), Some(v));
     }
     for (k, _) in &workload {
         m.remove(k, Mode::Merge);
+        m.check_invariants().unwrap();
     }
     for (k, _) in &workload {
         assert!(m.get(k).is_none());
@@ -61,14 +68,1002 @@ fn stress_crate_shrink() {
     let mut m = ExtendiableHash::new(2, 32);
     for (k, v) in &workload {
         m.insert(k.clone(), v.clone());
+        m.check_invariants().unwrap();
     }
     for (k, v) in &workload {
-        assert_eq!(&*m.get(k).unwrap(), v);
+        assert_eq!(m.get(k), Some(v));
     }
     for (k, _) in &workload {
         m.remove(k, Mode::Shrink);
+        m.check_invariants().unwrap();
     }
     for (k, _) in &workload {
         assert!(m.get(k).is_none());
     }
 }
+
+#[test]
+fn non_copy_values() {
+    let mut m = ExtendiableHash::new(1, 4);
+    for i in 0..SET_SIZE {
+        assert_eq!(m.insert(format!("key-{}", i), vec![i; 3]), None);
+    }
+    // 已存在的key覆盖并返回旧值
+    assert_eq!(m.insert("key-0".to_string(), vec![]), Some(vec![0; 3]));
+    assert_eq!(m.get(&"key-0".to_string()), Some(&vec![]));
+
+    m.get_mut(&"key-1".to_string()).unwrap().push(42);
+    assert_eq!(m.get(&"key-1".to_string()), Some(&vec![1, 1, 1, 42]));
+
+    // update是upsert
+    m.update("new".to_string(), vec![7]);
+    assert_eq!(m.get(&"new".to_string()), Some(&vec![7]));
+
+    for i in 0..SET_SIZE {
+        assert!(m.remove(&format!("key-{}", i), Mode::Shrink).is_some());
+    }
+    assert!(m.remove(&"key-0".to_string(), Mode::Shrink).is_none());
+    assert!(m.contains_key(&"new".to_string()));
+}
+
+#[test]
+fn entry_api() {
+    // 按key % 10计数, 桶很小, 计数过程中会不断分裂
+    let mut m: ExtendiableHash<u64, usize> = ExtendiableHash::new(1, 2);
+    for i in 0..SET_SIZE as u64 {
+        *m.entry(i % 100).or_insert(0) += 1;
+    }
+    for i in 0..100 {
+        assert_eq!(m.get(&i), Some(&(SET_SIZE / 100 + (i < SET_SIZE as u64 % 100) as usize)));
+    }
+
+    m.entry(0).and_modify(|v| *v = 0).or_insert(100);
+    m.entry(1000).and_modify(|v| *v = 0).or_insert(100);
+    assert_eq!(m.get(&0), Some(&0));
+    assert_eq!(m.get(&1000), Some(&100));
+    assert_eq!(*m.entry(2000).or_default(), 0);
+    assert_eq!(m.entry(3000).key(), &3000);
+    assert!(!m.contains_key(&3000));
+}
+
+#[test]
+fn iteration() {
+    let workload: Vec<(K, V)> = (0..SET_SIZE).map(|i| (i as K, i as V)).collect();
+    // 桶很小, 大部分桶会被多个目录项引用
+    let mut m: ExtendiableHash<K, V> = ExtendiableHash::new(4, 4);
+    m.extend(workload.iter().copied());
+    assert_eq!(m.len(), SET_SIZE);
+
+    // 每个kv只出现一次
+    let expect: HashMap<K, V> = workload.iter().copied().collect();
+    assert_eq!(m.iter().len(), SET_SIZE);
+    assert_eq!(m.iter().map(|(k, v)| (*k, *v)).collect::<HashMap<_, _>>(), expect);
+    let mut keys: Vec<K> = m.keys().copied().collect();
+    keys.sort();
+    assert_eq!(keys, (0..SET_SIZE as K).collect::<Vec<_>>());
+    assert_eq!(m.values().sum::<V>(), expect.values().sum::<V>());
+
+    for (_, v) in m.iter_mut() {
+        *v += 1;
+    }
+    for (_, v) in &mut m {
+        *v *= 2;
+    }
+    for (k, v) in &m {
+        assert_eq!(*v, (*k + 1) * 2);
+    }
+
+    // 删掉一半并合并后仍然不重不漏
+    for i in 0..SET_SIZE / 2 {
+        m.remove(&(i as K), Mode::Shrink);
+    }
+    assert_eq!(m.len(), SET_SIZE / 2);
+    assert_eq!(m.iter().count(), SET_SIZE / 2);
+
+    let owned: HashMap<K, V> = m.into_iter().collect();
+    assert_eq!(owned.len(), SET_SIZE / 2);
+}
+
+#[test]
+fn clear_and_drain() {
+    let mut m: ExtendiableHash<K, V> = (0..SET_SIZE).map(|i| (i as K, i as V)).collect();
+    assert_eq!(m.len(), SET_SIZE);
+
+    let mut drained: Vec<(K, V)> = m.drain().collect();
+    drained.sort();
+    assert_eq!(drained, (0..SET_SIZE).map(|i| (i as K, i as V)).collect::<Vec<_>>());
+    assert!(m.is_empty());
+    assert!(m.get(&0).is_none());
+
+    // 没遍历完的Drain也会清空
+    m.extend((0..SET_SIZE).map(|i| (i as K, i as V)));
+    assert_eq!(m.drain().take(10).count(), 10);
+    assert!(m.is_empty());
+    assert_eq!(m.iter().count(), 0);
+
+    m.extend((0..SET_SIZE).map(|i| (i as K, i as V)));
+    m.clear();
+    assert!(m.is_empty());
+    assert_eq!(m.iter().count(), 0);
+    // 清空后还能正常使用
+    *m.entry(1).or_default() += 1;
+    assert_eq!(m.len(), 1);
+    assert_eq!(m.get(&1), Some(&1));
+}
+
+/// 随机操作和HashMap对比, 每步都检查结构
+fn random_ops(mode: Mode, incremental: bool) {
+    // 渐进模式用大一点的桶, 这样一次搬迁要分好几次操作才能搬完
+    let mut m = ExtendiableHash::new(1, if incremental { 32 } else { 4 });
+    m.set_incremental(incremental);
+    let mut expect = HashMap::new();
+    for _ in 0..SET_SIZE * 4 {
+        let k = rand::random::<u64>() % (SET_SIZE as u64 / 4);
+        match rand::random::<u64>() % 3 {
+            0 => assert_eq!(m.insert(k, k), expect.insert(k, k)),
+            1 => assert_eq!(m.remove(&k, mode), expect.remove(&k)),
+            _ => assert_eq!(m.get(&k), expect.get(&k)),
+        }
+        assert_eq!(m.len(), expect.len());
+        m.check_invariants().unwrap();
+    }
+}
+
+#[test]
+fn random_ops_no() {
+    random_ops(Mode::No, false);
+}
+
+#[test]
+fn random_ops_merge() {
+    random_ops(Mode::Merge, false);
+}
+
+#[test]
+fn random_ops_shrink() {
+    random_ops(Mode::Shrink, false);
+}
+
+#[test]
+fn incremental_no() {
+    random_ops(Mode::No, true);
+}
+
+#[test]
+fn incremental_merge() {
+    random_ops(Mode::Merge, true);
+}
+
+#[test]
+fn incremental_shrink() {
+    random_ops(Mode::Shrink, true);
+}
+
+#[test]
+fn incremental_pending() {
+    // 恒等哈希, 插入0..5: 桶0满了分裂成0和10, 2还留在桶0里没搬
+    let mut m = ExtendiableHash::with_hasher(1, 2, BuildHasherDefault::<IdentityHasher>::default());
+    m.set_incremental(true);
+    for i in 0..5 {
+        m.insert(i as K, i as V);
+        m.check_invariants().unwrap();
+    }
+    assert_eq!(m.global_depth(), 2);
+    assert_eq!(m.bucket_iter(0b10).count(), 0);
+    for i in 0..5 {
+        assert_eq!(m.get(&(i as K)), Some(&(i as V)));
+    }
+
+    // 修改时单独把这个key搬过去
+    *m.get_mut(&2).unwrap() += 10;
+    assert_eq!(m.bucket_iter(0b10).collect::<Vec<_>>(), vec![(&2, &12)]);
+    m.check_invariants().unwrap();
+
+    m.finish_resize();
+    assert_eq!(m.bucket_iter(0b00).count(), 2);
+    assert_eq!(m.bucket_iter(0b10).count(), 1);
+    m.check_invariants().unwrap();
+
+    // 结构和一次做完的一样
+    let mut expect = ExtendiableHash::with_hasher(1, 2, BuildHasherDefault::<IdentityHasher>::default());
+    for i in 0..5 {
+        expect.insert(i as K, if i == 2 { 12 } else { i as V });
+    }
+    assert_eq!(m.stats(), expect.stats());
+}
+
+#[test]
+fn incremental_large() {
+    // 大量插入时目录多次翻倍, 中途时不时检查一下
+    let mut m = ExtendiableHash::new(1, 64);
+    m.set_incremental(true);
+    for i in 0..SET_SIZE * 16 {
+        m.insert(i as K, i as V);
+        if i % 64 == 0 {
+            m.check_invariants().unwrap();
+        }
+    }
+    m.check_invariants().unwrap();
+    assert_eq!(m.len(), SET_SIZE * 16);
+    assert!((0..SET_SIZE * 16).all(|i| m.get(&(i as K)) == Some(&(i as V))));
+
+    m.set_incremental(false);
+    m.check_invariants().unwrap();
+    for i in 0..SET_SIZE * 16 {
+        assert_eq!(m.remove(&(i as K), Mode::Shrink), Some(i as V));
+    }
+    assert!(m.is_empty());
+    m.check_invariants().unwrap();
+}
+
+fn linear_random_ops(mode: Mode) {
+    let mut m = LinearHash::new(0, 4);
+    let mut expect = HashMap::new();
+    for _ in 0..SET_SIZE * 4 {
+        let k = rand::random::<u64>() % (SET_SIZE as u64 / 4);
+        match rand::random::<u64>() % 3 {
+            0 => assert_eq!(m.insert(k, k), expect.insert(k, k)),
+            1 => assert_eq!(m.remove(&k, mode), expect.remove(&k)),
+            _ => assert_eq!(m.get(&k), expect.get(&k)),
+        }
+        assert_eq!(m.len(), expect.len());
+        m.check_invariants().unwrap();
+    }
+}
+
+#[test]
+fn linear_random_ops_no() {
+    linear_random_ops(Mode::No);
+}
+
+#[test]
+fn linear_random_ops_merge() {
+    linear_random_ops(Mode::Merge);
+}
+
+#[test]
+fn linear_split_pointer() {
+    // 恒等哈希, 2个桶, 每个桶2个, 装填因子0.5: 第3个key插入后分裂桶0
+    let mut m = LinearHash::with_hasher(1, 2, BuildHasherDefault::<IdentityHasher>::default());
+    m.set_max_load_factor(0.5);
+    for i in 0..2 {
+        m.insert(i as K, i as V);
+    }
+    assert_eq!((m.level(), m.split_pointer(), m.bucket_count()), (1, 0, 2));
+    m.insert(2, 2);
+    assert_eq!((m.level(), m.split_pointer(), m.bucket_count()), (1, 1, 3));
+    // 再分裂桶1, 一轮分裂完level加一
+    m.insert(5, 5);
+    assert_eq!((m.level(), m.split_pointer(), m.bucket_count()), (2, 0, 4));
+    m.check_invariants().unwrap();
+
+    // 收缩回2个桶
+    for i in [0, 1, 2] {
+        m.remove(&(i as K), Mode::Merge);
+        m.check_invariants().unwrap();
+    }
+    assert_eq!((m.level(), m.split_pointer(), m.bucket_count()), (1, 0, 2));
+    assert_eq!(m.get(&5), Some(&5));
+
+    // 调小装填因子, 下次插入一口气分裂
+    m.set_max_load_factor(0.1);
+    m.insert(6, 6);
+    assert!(m.load_factor() <= 0.1);
+    m.check_invariants().unwrap();
+    let mut all: Vec<_> = m.iter().map(|(k, v)| (*k, *v)).collect();
+    all.sort();
+    assert_eq!(all, vec![(5, 5), (6, 6)]);
+}
+
+#[test]
+#[should_panic]
+fn linear_tiny_load_factor() {
+    let mut m: LinearHash<K, V> = LinearHash::new(1, 4);
+    m.set_max_load_factor(1e-9);
+}
+
+#[test]
+#[should_panic]
+fn linear_nan_load_factor() {
+    let mut m: LinearHash<K, V> = LinearHash::new(1, 4);
+    m.set_max_load_factor(f64::NAN);
+}
+
+#[test]
+fn linear_non_copy_values() {
+    let mut m: LinearHash<String, Vec<u8>> = (0..SET_SIZE).map(|i| (i.to_string(), vec![i as u8])).collect();
+    assert_eq!(m.len(), SET_SIZE);
+    m.get_mut(&"1".to_string()).unwrap().push(2);
+    assert_eq!(m.get(&"1".to_string()), Some(&vec![1, 2]));
+    m.update("1".to_string(), vec![]);
+    assert_eq!(m.remove(&"1".to_string(), Mode::Shrink), Some(vec![]));
+    assert!(!m.contains_key(&"1".to_string()));
+    assert_eq!(m.into_iter().count(), SET_SIZE - 1);
+}
+
+#[test]
+fn stats() {
+    // 恒等哈希, 0..64每个桶正好4个
+    let mut m = ExtendiableHash::with_hasher(1, 4, BuildHasherDefault::<IdentityHasher>::default());
+    for i in 0..64 {
+        m.insert(i as K, i as V);
+    }
+    let stats = m.stats();
+    assert_eq!(stats.global_depth, 4);
+    assert_eq!(stats.bucket_count, 16);
+    assert_eq!(stats.len, 64);
+    assert_eq!(stats.fill[10], 16);
+    assert_eq!(stats.overflowed, 0);
+    assert_eq!(stats.local_depths, vec![0, 0, 0, 0, 16]);
+
+    // 删掉后缀为0000的桶, 合并后有一个3位的桶
+    for i in (0..64).step_by(16) {
+        m.remove(&(i as K), Mode::Merge);
+    }
+    let stats = m.stats();
+    assert_eq!(stats.bucket_count, 15);
+    assert_eq!(stats.fill[0], 0);
+    assert_eq!(stats.local_depths, vec![0, 0, 0, 1, 14]);
+    assert_eq!(stats.fill.iter().sum::<usize>(), 15);
+    m.check_invariants().unwrap();
+}
+
+#[test]
+#[should_panic]
+fn zero_bucket_cap() {
+    let _: ExtendiableHash<K, V> = ExtendiableHash::new(1, 0);
+}
+
+const THREADS: usize = 8;
+
+fn concurrent_workload() -> Vec<Vec<(K, V)>> {
+    (0..THREADS)
+        .map(|t| (0..SET_SIZE).map(|i| ((t * SET_SIZE + i) as K, i as V)).collect())
+        .collect()
+}
+
+#[test]
+fn concurrent_stress_insert() {
+    let workload = concurrent_workload();
+    let m = ConcurrentExtendibleHash::new(2, 32);
+
+    std::thread::scope(|s| {
+        for part in &workload {
+            let m = &m;
+            s.spawn(move || {
+                for (k, v) in part {
+                    assert!(m.insert(*k, *v));
+                }
+            });
+        }
+    });
+
+    for part in &workload {
+        for (k, v) in part {
+            assert_eq!(m.get(k), Some(*v));
+            assert!(!m.insert(*k, *v));
+        }
+    }
+}
+
+#[test]
+fn concurrent_stress_same_keys() {
+    // 所有线程抢着插入同一批key, 每个key只能有一个线程插入成功
+    let m = ConcurrentExtendibleHash::new(2, 8);
+    let inserted = std::sync::atomic::AtomicUsize::new(0);
+
+    std::thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for i in 0..SET_SIZE {
+                    if m.insert(i as K, i as V) {
+                        inserted.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(inserted.into_inner(), SET_SIZE);
+    for i in 0..SET_SIZE {
+        assert_eq!(m.get(&(i as K)), Some(i as V));
+    }
+}
+
+fn concurrent_insert_remove(mode: Mode) {
+    let workload = concurrent_workload();
+    let m = ConcurrentExtendibleHash::new(2, 16);
+
+    std::thread::scope(|s| {
+        for part in &workload {
+            let m = &m;
+            s.spawn(move || {
+                for (k, v) in part {
+                    m.insert(*k, *v);
+                }
+                for (k, v) in part {
+                    assert_eq!(m.get(k), Some(*v));
+                }
+                for (k, _) in part {
+                    m.remove(k, mode);
+                }
+                for (k, _) in part {
+                    assert!(m.get(k).is_none());
+                }
+            });
+        }
+    });
+
+    for part in &workload {
+        for (k, _) in part {
+            assert!(!m.contains_key(k));
+        }
+    }
+}
+
+#[test]
+fn concurrent_stress_no() {
+    concurrent_insert_remove(Mode::No);
+}
+
+#[test]
+fn concurrent_stress_merge() {
+    concurrent_insert_remove(Mode::Merge);
+}
+
+#[test]
+fn concurrent_stress_shrink() {
+    concurrent_insert_remove(Mode::Shrink);
+}
+
+#[test]
+fn concurrent_readers_during_split() {
+    // 一半的key先插入, 读线程持续读取它们, 同时写线程插入另一半触发大量分裂
+    let m = ConcurrentExtendibleHash::new(1, 4);
+    for i in 0..SET_SIZE {
+        m.insert(i as K, i as V);
+    }
+
+    std::thread::scope(|s| {
+        for t in 0..THREADS / 2 {
+            let m = &m;
+            s.spawn(move || {
+                for i in 0..SET_SIZE {
+                    let k = ((t + 1) * SET_SIZE + i) as K;
+                    m.insert(k, i as V);
+                }
+            });
+        }
+        for _ in 0..THREADS / 2 {
+            s.spawn(|| {
+                for i in 0..SET_SIZE {
+                    assert_eq!(m.get(&(i as K)), Some(i as V));
+                }
+            });
+        }
+    });
+}
+
+/// 哈希值就是key本身, 桶号完全可以算出来
+#[derive(Default)]
+struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    /// 其他类型的key: 字节折叠进去, 每个字节都会影响低位
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = self.0.wrapping_mul(31).wrapping_add(b as u64);
+        }
+    }
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n;
+    }
+}
+
+/// 低4位全是0: 深度不超过4时所有key都落在同一个桶
+#[derive(Default)]
+struct ShiftHasher(u64);
+
+impl Hasher for ShiftHasher {
+    fn finish(&self) -> u64 {
+        self.0 << 4
+    }
+    /// 其他类型的key: 字节折叠进去, 每个字节都会影响低位
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = self.0.wrapping_mul(31).wrapping_add(b as u64);
+        }
+    }
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n;
+    }
+}
+
+#[test]
+fn hasher_any_key_type() {
+    // 字符串key走`write`, 也能正常分裂
+    let mut m = ExtendiableHash::with_hasher(1, 2, BuildHasherDefault::<IdentityHasher>::default());
+    let mut n = ExtendiableHash::with_hasher(1, 2, BuildHasherDefault::<ShiftHasher>::default());
+    for i in 0..64 {
+        assert_eq!(m.insert(i.to_string(), i), None);
+        assert_eq!(n.insert(i.to_string(), i), None);
+    }
+    m.check_invariants().unwrap();
+    n.check_invariants().unwrap();
+    for i in 0..64 {
+        assert_eq!(m.get(&i.to_string()), Some(&i));
+        assert_eq!(n.get(&i.to_string()), Some(&i));
+    }
+}
+
+#[test]
+fn hasher_deterministic_split() {
+    // 0..64均匀分布, 每个桶4个, 正好需要16个桶
+    let mut m = ExtendiableHash::with_hasher(1, 4, BuildHasherDefault::<IdentityHasher>::default());
+    for i in 0..64 {
+        assert_eq!(m.insert(i as K, i as V), None);
+    }
+    assert_eq!(m.global_depth(), 4);
+    for i in 0..64 {
+        assert_eq!(m.get(&(i as K)), Some(&(i as V)));
+    }
+
+    let m = ConcurrentExtendibleHash::with_hasher(1, 4, BuildHasherDefault::<IdentityHasher>::default());
+    for i in 0..64 {
+        assert!(m.insert(i as K, i as V));
+    }
+    assert_eq!(m.global_depth(), 4);
+}
+
+#[test]
+fn hasher_adversarial_collision() {
+    let mut m = ExtendiableHash::with_hasher(1, 4, BuildHasherDefault::<ShiftHasher>::default());
+    for i in 0..SET_SIZE {
+        assert_eq!(m.insert(i as K, i as V), None);
+    }
+    // 前4位没用, 至少再要8位才能把1024个key分到每桶4个
+    assert_eq!(m.global_depth(), 4 + 8);
+    for i in 0..SET_SIZE {
+        assert_eq!(m.get(&(i as K)), Some(&(i as V)));
+    }
+    for i in 0..SET_SIZE {
+        m.remove(&(i as K), Mode::Shrink);
+        m.check_invariants().unwrap();
+    }
+    for i in 0..SET_SIZE {
+        assert!(m.get(&(i as K)).is_none());
+    }
+}
+
+/// 所有key的哈希值都一样
+#[derive(Default)]
+struct ConstHasher;
+
+impl Hasher for ConstHasher {
+    fn finish(&self) -> u64 {
+        0
+    }
+    fn write(&mut self, _: &[u8]) {}
+}
+
+#[test]
+fn overflow_identical_hash() {
+    let mut m = ExtendiableHash::with_hasher(1, 4, BuildHasherDefault::<ConstHasher>::default());
+    // 完整哈希值都相同, 分裂没有用, 目录不能增长
+    for i in 0..8 {
+        assert_eq!(m.try_insert(i as K, i as V), Ok(None));
+    }
+    assert_eq!(m.global_depth(), 1);
+    assert_eq!(m.try_insert(8, 8), Err(InsertError::Overflow));
+    // 已存在的key仍然可以覆盖
+    assert_eq!(m.try_insert(0, 100), Ok(Some(0)));
+    for i in 1..8 {
+        assert_eq!(m.get(&(i as K)), Some(&(i as V)));
+    }
+
+    // 删掉一个后又能插入
+    m.remove(&0, Mode::Merge);
+    assert_eq!(m.try_insert(9, 9), Ok(None));
+}
+
+#[test]
+fn overflow_unbounded_insert() {
+    // insert, update和entry不限制溢出, 不会panic, 目录也不会增长
+    let mut m = ExtendiableHash::with_hasher(1, 1, BuildHasherDefault::<ConstHasher>::default());
+    for i in 0..8 {
+        assert_eq!(m.insert(i as K, i as V), None);
+    }
+    m.update(8, 8);
+    *m.entry(9).or_insert(0) += 9;
+    assert_eq!(m.try_insert(10, 10), Err(InsertError::Overflow));
+    assert_eq!(m.global_depth(), 1);
+    assert_eq!(m.len(), 10);
+    for i in 0..10 {
+        assert_eq!(m.get(&(i as K)), Some(&(i as V)));
+    }
+    m.check_invariants().unwrap();
+}
+
+#[test]
+fn overflow_max_global_depth() {
+    // 低4位冲突, 目录最多到4位时所有key都在同一个桶
+    let mut m = ExtendiableHash::with_hasher(1, 4, BuildHasherDefault::<ShiftHasher>::default());
+    m.set_max_global_depth(4);
+    m.set_max_overflow(60);
+    for i in 0..64 {
+        assert_eq!(m.try_insert(i as K, i as V), Ok(None));
+        m.check_invariants().unwrap();
+    }
+    assert_eq!(m.global_depth(), 4);
+    assert_eq!(m.stats().overflowed, 1);
+    assert_eq!(m.try_insert(64, 64), Err(InsertError::Overflow));
+
+    // 放开限制后可以继续分裂
+    m.set_max_global_depth(10);
+    assert_eq!(m.try_insert(64, 64), Ok(None));
+    assert!(m.global_depth() > 4);
+    for i in 0..=64 {
+        assert_eq!(m.get(&(i as K)), Some(&(i as V)));
+    }
+}
+
+#[test]
+fn concurrent_overflow_identical_hash() {
+    let m = ConcurrentExtendibleHash::with_hasher(1, 4, BuildHasherDefault::<ConstHasher>::default());
+    // 同单线程版本: 分裂没有用, 目录不能增长
+    for i in 0..8 {
+        assert_eq!(m.try_insert(i as K, i as V), Ok(true));
+    }
+    assert_eq!(m.global_depth(), 1);
+    assert_eq!(m.try_insert(8, 8), Err(InsertError::Overflow));
+    assert_eq!(m.try_insert(0, 100), Ok(false));
+
+    // insert不限制溢出
+    for i in 8..64 {
+        assert!(m.insert(i as K, i as V));
+    }
+    assert_eq!(m.global_depth(), 1);
+    for i in 0..64 {
+        assert_eq!(m.get(&(i as K)), Some(i as V));
+    }
+}
+
+#[test]
+fn concurrent_overflow_max_global_depth() {
+    let m = ConcurrentExtendibleHash::with_hasher(1, 4, BuildHasherDefault::<ShiftHasher>::default());
+    m.set_max_global_depth(4);
+    m.set_max_overflow(60);
+    for i in 0..64 {
+        assert_eq!(m.try_insert(i as K, i as V), Ok(true));
+    }
+    assert_eq!(m.global_depth(), 4);
+    assert_eq!(m.try_insert(64, 64), Err(InsertError::Overflow));
+
+    // 放开限制后可以继续分裂, 溢出的桶分裂后也不会再无限分裂
+    m.set_max_global_depth(10);
+    assert_eq!(m.try_insert(64, 64), Ok(true));
+    assert!(m.global_depth() > 4);
+    for i in 0..=64 {
+        assert_eq!(m.get(&(i as K)), Some(i as V));
+    }
+}
+
+/// 每个测试用自己的文件, 测试是并行跑的
+fn disk_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("extendible-hashing-{}-{}.db", std::process::id(), name))
+}
+
+fn disk_insert_remove(name: &str, mode: Mode) {
+    let path = disk_path(name);
+    let workload: Vec<(K, V)> = (0..SET_SIZE).map(|i| (i as K, i as V)).collect();
+
+    let mut m = DiskExtendibleHash::create(&path, 2, 16).unwrap();
+    for (k, v) in &workload {
+        assert!(m.insert(*k, *v).unwrap());
+    }
+    assert!(!m.insert(0, 1).unwrap());
+    for (k, v) in &workload {
+        assert_eq!(m.get(k).unwrap(), Some(*v));
+    }
+    for (k, v) in &workload {
+        m.update(*k, v * 2).unwrap();
+    }
+    for (k, v) in &workload {
+        assert_eq!(m.get(k).unwrap(), Some(v * 2));
+    }
+    for (k, _) in &workload {
+        m.remove(k, mode).unwrap();
+    }
+    for (k, _) in &workload {
+        assert!(!m.contains_key(k).unwrap());
+    }
+    if mode == Mode::Shrink {
+        assert_eq!(m.global_depth(), 1);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn disk_stress_no() {
+    disk_insert_remove("no", Mode::No);
+}
+
+#[test]
+fn disk_stress_merge() {
+    disk_insert_remove("merge", Mode::Merge);
+}
+
+#[test]
+fn disk_stress_shrink() {
+    disk_insert_remove("shrink", Mode::Shrink);
+}
+
+#[test]
+fn disk_reopen() {
+    let path = disk_path("reopen");
+    {
+        let mut m = DiskExtendibleHash::create(&path, 2, 16).unwrap();
+        for i in 0..SET_SIZE {
+            m.insert(i as K, i as V).unwrap();
+        }
+        for i in 0..SET_SIZE / 2 {
+            m.remove(&(i as K), Mode::Shrink).unwrap();
+        }
+        m.sync().unwrap();
+    }
+
+    let mut m = DiskExtendibleHash::<K, V>::open(&path).unwrap();
+    for i in 0..SET_SIZE {
+        let expect = if i < SET_SIZE / 2 { None } else { Some(i as V) };
+        assert_eq!(m.get(&(i as K)).unwrap(), expect);
+    }
+    // 重新打开后还能继续分裂
+    for i in SET_SIZE..SET_SIZE * 2 {
+        assert!(m.insert(i as K, i as V).unwrap());
+    }
+    drop(m);
+
+    // kv大小对不上的文件不能打开
+    assert!(DiskExtendibleHash::<u32, V>::open(&path).is_err());
+
+    // 桶容量放不进一页的文件也不能打开
+    {
+        use std::io::{Seek, SeekFrom, Write};
+        let mut f = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        f.seek(SeekFrom::Start(20)).unwrap();
+        f.write_all(&u32::MAX.to_le_bytes()).unwrap();
+    }
+    assert!(DiskExtendibleHash::<K, V>::open(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn disk_concurrent_get() {
+    let path = disk_path("concurrent-get");
+    let mut m = DiskExtendibleHash::create(&path, 2, 16).unwrap();
+    for i in 0..SET_SIZE {
+        m.insert(i as K, i as V).unwrap();
+    }
+    // 只读操作共享`&m`, 每个线程都要读到自己的页
+    let m = &m;
+    std::thread::scope(|s| {
+        for t in 0..4 {
+            s.spawn(move || {
+                for _ in 0..8 {
+                    for i in (t..SET_SIZE).step_by(4) {
+                        assert_eq!(m.get(&(i as K)).unwrap(), Some(i as V));
+                        assert!(m.contains_key(&(i as K)).unwrap());
+                    }
+                }
+            });
+        }
+    });
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn disk_reuse_free_pages() {
+    let path = disk_path("free");
+    let mut m = DiskExtendibleHash::create(&path, 1, 4).unwrap();
+    for i in 0..SET_SIZE {
+        m.insert(i as K, i as V).unwrap();
+    }
+    let pages = m.page_count();
+
+    // 合并回收的页在下一轮分裂时复用, 文件不会变大
+    for _ in 0..4 {
+        for i in 0..SET_SIZE {
+            m.remove(&(i as K), Mode::Shrink).unwrap();
+        }
+        for i in 0..SET_SIZE {
+            m.insert(i as K, i as V).unwrap();
+        }
+        assert!(m.page_count() <= pages);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn disk_directory_full() {
+    use extendible_hashing::MAX_GLOBAL_DEPTH;
+
+    // 和DiskExtendibleHash一样对key的小端编码做FNV-1a
+    let fnv = |k: K| k.to_le_bytes().iter().fold(0xcbf29ce484222325u64, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3));
+    // 找两个hash低MAX_GLOBAL_DEPTH位相同的key, 每页只放一个kv, 它们怎么分裂都分不开
+    let mask = (1 << MAX_GLOBAL_DEPTH) - 1;
+    let mut seen = HashMap::new();
+    let (a, b) = (0..).find_map(|k: K| seen.insert(fnv(k) & mask, k).map(|a| (a, k))).unwrap();
+
+    let path = disk_path("full");
+    let mut m = DiskExtendibleHash::create(&path, 1, 1).unwrap();
+    assert!(m.insert(a, a).unwrap());
+    assert!(m.insert(b, b).is_err());
+    assert_eq!(m.global_depth(), MAX_GLOBAL_DEPTH);
+    assert_eq!(m.get(&a).unwrap(), Some(a));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn disk_directory_pages() {
+    // 桶很小, 目录超过一页(1024项)
+    let path = disk_path("dir_pages");
+    let n = SET_SIZE as K * 4;
+    {
+        let mut m = DiskExtendibleHash::create(&path, 1, 2).unwrap();
+        for i in 0..n {
+            m.insert(i, i).unwrap();
+        }
+        assert!(m.global_depth() > 10);
+        m.sync().unwrap();
+    }
+
+    let mut m = DiskExtendibleHash::<K, V>::open(&path).unwrap();
+    for i in 0..n {
+        assert_eq!(m.get(&i).unwrap(), Some(i));
+    }
+    let depth = m.global_depth();
+    for i in 0..n {
+        m.remove(&i, Mode::Shrink).unwrap();
+    }
+    assert!(m.global_depth() <= depth);
+    drop(m);
+
+    // 收缩后多出来的目录页放回空闲链表, 重新打开还是对的
+    let mut m = DiskExtendibleHash::<K, V>::open(&path).unwrap();
+    for i in 0..n {
+        assert_eq!(m.get(&i).unwrap(), None);
+        m.insert(i, i).unwrap();
+    }
+    for i in 0..n {
+        assert_eq!(m.get(&i).unwrap(), Some(i));
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "serde")]
+mod snapshot {
+    use super::*;
+
+    /// 恢复后每个目录项的后缀和桶内容都相同
+    fn assert_same_shape<S: std::hash::BuildHasher>(a: &ExtendiableHash<K, V, S>, b: &ExtendiableHash<K, V, S>) {
+        assert_eq!(a.global_depth(), b.global_depth());
+        assert_eq!(a.stats(), b.stats());
+        for i in 0..1 << a.global_depth() {
+            assert_eq!(a.bucket_id_string(i), b.bucket_id_string(i));
+            let mut x: Vec<_> = a.bucket_iter(i).collect();
+            let mut y: Vec<_> = b.bucket_iter(i).collect();
+            x.sort();
+            y.sort();
+            assert_eq!(x, y);
+        }
+        b.check_invariants().unwrap();
+    }
+
+    fn workload() -> ExtendiableHash<K, V> {
+        let mut m = ExtendiableHash::new(1, 4);
+        for i in 0..SET_SIZE {
+            m.insert(i as K, i as V);
+        }
+        // 合并掉一部分, 让free中有桶, local_depth也各不相同
+        for i in 0..SET_SIZE / 2 {
+            m.remove(&(i as K), Mode::Merge);
+        }
+        m
+    }
+
+    #[test]
+    fn snapshot_bincode() {
+        let m = workload();
+        let bytes = bincode::serialize(&m).unwrap();
+        let restored: ExtendiableHash<K, V> = bincode::deserialize(&bytes).unwrap();
+        assert_same_shape(&m, &restored);
+
+        // 恢复后还能正常使用
+        let mut restored = restored;
+        for i in 0..SET_SIZE {
+            restored.insert(i as K, i as V);
+            restored.check_invariants().unwrap();
+        }
+    }
+
+    #[test]
+    fn snapshot_incremental() {
+        // 搬迁做到一半时保存, 恢复出来是做完的样子
+        let mut m = ExtendiableHash::new(1, 4);
+        m.set_incremental(true);
+        for i in 0..SET_SIZE {
+            m.insert(i as K, i as V);
+        }
+        let bytes = bincode::serialize(&m).unwrap();
+        let restored: ExtendiableHash<K, V> = bincode::deserialize(&bytes).unwrap();
+        assert!(restored.incremental());
+        m.finish_resize();
+        assert_same_shape(&m, &restored);
+    }
+
+    #[test]
+    fn snapshot_json() {
+        let m = workload();
+        let json = serde_json::to_string(&m).unwrap();
+        let restored: ExtendiableHash<K, V> = serde_json::from_str(&json).unwrap();
+        assert_same_shape(&m, &restored);
+
+        let empty: ExtendiableHash<K, V> = ExtendiableHash::new(3, 4);
+        let restored: ExtendiableHash<K, V> = serde_json::from_str(&serde_json::to_string(&empty).unwrap()).unwrap();
+        assert_same_shape(&empty, &restored);
+    }
+
+    #[test]
+    fn snapshot_custom_hasher() {
+        let mut m = ExtendiableHash::with_hasher(1, 4, BuildHasherDefault::<IdentityHasher>::default());
+        for i in 0..64 {
+            m.insert(i as K, i as V);
+        }
+        let bytes = bincode::serialize(&m).unwrap();
+        let restored: ExtendiableHash<K, V, BuildHasherDefault<IdentityHasher>> = bincode::deserialize(&bytes).unwrap();
+        assert_same_shape(&m, &restored);
+
+        // 换了哈希函数, key和后缀对不上
+        assert!(bincode::deserialize::<ExtendiableHash<K, V>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn snapshot_corrupted() {
+        let bad = [
+            // 目录大小不对
+            r#"{"global_depth":2,"bucket_cap":4,"max_global_depth":20,"max_overflow":4,"entries":[0,1],"buckets":[[1,[]],[1,[]]]}"#,
+            // 目录项越界
+            r#"{"global_depth":1,"bucket_cap":4,"max_global_depth":20,"max_overflow":4,"entries":[0,2],"buckets":[[1,[]],[1,[]]]}"#,
+            // local_depth比global_depth大
+            r#"{"global_depth":1,"bucket_cap":4,"max_global_depth":20,"max_overflow":4,"entries":[0,1],"buckets":[[2,[]],[1,[]]]}"#,
+            // 后缀不同的目录项共享桶
+            r#"{"global_depth":1,"bucket_cap":4,"max_global_depth":20,"max_overflow":4,"entries":[0,0],"buckets":[[1,[]]]}"#,
+            // 没被引用的桶
+            r#"{"global_depth":1,"bucket_cap":4,"max_global_depth":20,"max_overflow":4,"entries":[0,1],"buckets":[[1,[]],[1,[]],[1,[[1,1]]]]}"#,
+        ];
+        for json in bad {
+            assert!(serde_json::from_str::<ExtendiableHash<K, V>>(json).is_err(), "{}", json);
+        }
+        let ok = r#"{"global_depth":1,"bucket_cap":4,"max_global_depth":20,"max_overflow":4,"entries":[0,1],"buckets":[[1,[]],[1,[]]]}"#;
+        assert!(serde_json::from_str::<ExtendiableHash<K, V>>(ok).is_ok());
+    }
+
+    #[test]
+    fn snapshot_duplicate_key() {
+        // 同一个桶里放两次同一个key, 只换value
+        let mut json: serde_json::Value = serde_json::to_value(workload()).unwrap();
+        let bucket = json["buckets"].as_array_mut().unwrap()
+            .iter_mut()
+            .map(|b| b[1].as_array_mut().unwrap())
+            .find(|kv| !kv.is_empty())
+            .unwrap();
+        let mut dup = bucket[0].clone();
+        dup[1] = serde_json::json!(SET_SIZE * 2);
+        bucket.push(dup);
+        let err = serde_json::from_value::<ExtendiableHash<K, V>>(json).err().unwrap();
+        assert!(err.to_string().contains("duplicate key"), "{}", err);
+    }
+}
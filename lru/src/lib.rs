@@ -0,0 +1,3 @@
+pub mod lru;
+
+pub use crate::lru::*;
@@ -0,0 +1,405 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// 空指针
+const NIL: usize = usize::MAX;
+
+/// 链表节点放在数组里, prev/next是下标
+struct Node<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+/// 双向链表 + HashMap的LRU, 所有操作O(1)
+///  - 链表按最近使用排序, 链首最新, 链尾最久没用
+///  - HashMap: key -> 节点下标
+///  - ⭐节点放在arena(`Vec`)里, 用下标当指针, 不用`Rc<RefCell>`也不用unsafe
+///    删除时把最后一个节点挪到空位(swap_remove), 再修一下它的邻居和map, arena一直是紧凑的
+///  - `K: Clone`: key在map和节点里各存一份, 节点里那份用来在淘汰/挪位置时反查map
+pub struct LruCache<K, V> {
+    map: HashMap<K, usize>,
+    nodes: Vec<Node<K, V>>,
+    head: usize,
+    tail: usize,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "LruCache: capacity must be positive");
+        Self { map: HashMap::with_capacity(capacity), nodes: Vec::with_capacity(capacity), head: NIL, tail: NIL, capacity }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// 查找并移到链首
+    ///  同`HashMap`, 可以用key的借用形式查, e.g. `LruCache<String, _>`用`&str`
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+    {
+        let i = *self.map.get(key)?;
+        self.move_to_head(i);
+        Some(&self.nodes[i].value)
+    }
+
+    /// 同get, 返回可变引用
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+    {
+        let i = *self.map.get(key)?;
+        self.move_to_head(i);
+        Some(&mut self.nodes[i].value)
+    }
+
+    /// 只看不动, 不影响淘汰顺序
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+    {
+        self.map.get(key).map(|&i| &self.nodes[i].value)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+    {
+        self.map.contains_key(key)
+    }
+
+    /// 插入到链首, 返回被淘汰的kv
+    ///  - key已存在: 覆盖value, 不淘汰, 返回None
+    ///  - 满了: 淘汰链尾, 返回它
+    pub fn put(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some(&i) = self.map.get(&key) {
+            self.move_to_head(i);
+            self.nodes[i].value = value;
+            return None;
+        }
+        let evicted = if self.len() == self.capacity { self.pop_lru() } else { None };
+        let i = self.nodes.len();
+        self.map.insert(key.clone(), i);
+        self.nodes.push(Node { key, value, prev: NIL, next: NIL });
+        self.push_front(i);
+        evicted
+    }
+
+    /// 删除key, 返回它的value
+    pub fn pop<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+    {
+        let i = *self.map.get(key)?;
+        Some(self.remove(i).1)
+    }
+
+    /// 删除最久没用过的, 即链尾
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        if self.tail == NIL {
+            return None;
+        }
+        Some(self.remove(self.tail))
+    }
+
+    /// 最久没用过的, 不删除
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        self.nodes.get(self.tail).map(|node| (&node.key, &node.value))
+    }
+
+    /// 改容量, 变小时从链尾淘汰多出来的
+    pub fn resize(&mut self, capacity: usize) {
+        assert!(capacity > 0, "LruCache: capacity must be positive");
+        while self.len() > capacity {
+            self.pop_lru();
+        }
+        self.capacity = capacity;
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        self.head = NIL;
+        self.tail = NIL;
+    }
+
+    /// 从新到旧
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { nodes: &self.nodes, front: self.head, back: self.tail, len: self.len() }
+    }
+
+    /// 摘下节点i, 把最后一个节点挪过来填坑
+    fn remove(&mut self, i: usize) -> (K, V) {
+        self.unlink(i);
+        let last = self.nodes.len() - 1;
+        if i != last {
+            // 最后一个节点要搬到i, 改它邻居和map里的下标
+            let (prev, next) = (self.nodes[last].prev, self.nodes[last].next);
+            match prev {
+                NIL => self.head = i,
+                p => self.nodes[p].next = i,
+            }
+            match next {
+                NIL => self.tail = i,
+                n => self.nodes[n].prev = i,
+            }
+            *self.map.get_mut(&self.nodes[last].key).unwrap() = i;
+        }
+        let node = self.nodes.swap_remove(i);
+        self.map.remove(&node.key);
+        (node.key, node.value)
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = (self.nodes[i].prev, self.nodes[i].next);
+        match prev {
+            NIL => self.head = next,
+            p => self.nodes[p].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            n => self.nodes[n].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, i: usize) {
+        self.nodes[i].prev = NIL;
+        self.nodes[i].next = self.head;
+        match self.head {
+            NIL => self.tail = i,
+            h => self.nodes[h].prev = i,
+        }
+        self.head = i;
+    }
+
+    fn move_to_head(&mut self, i: usize) {
+        if self.head != i {
+            self.unlink(i);
+            self.push_front(i);
+        }
+    }
+}
+
+/// 按最近使用顺序遍历, 反过来就是淘汰顺序
+pub struct Iter<'a, K, V> {
+    nodes: &'a [Node<K, V>],
+    front: usize,
+    back: usize,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let node = &self.nodes[self.front];
+        self.front = node.next;
+        self.len -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let node = &self.nodes[self.back];
+        self.back = node.prev;
+        self.len -= 1;
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+#[test]
+fn test() {
+    // ["LRUCache","put","put","get","put","get",// "put","get","get","get"]
+    // [[2],[1,1],[2,2],[1],[3,3], // [2],[4,4],[1],[3],[4]]
+    let mut obj = LruCache::new(2);
+    obj.put(1, 1);
+    obj.put(2, 2);
+    // 1 -> 2
+    assert_eq!(Some(&1), obj.get(&1));
+    // 3 -> 1
+    assert_eq!(Some((2, 2)), obj.put(3, 3));
+    // should be -1 since lru cap = 2
+    assert_eq!(None, obj.get(&2));
+    // 4 -> 3
+    obj.put(4, 4);
+    assert_eq!(None, obj.get(&1));
+    assert_eq!(Some(&3), obj.get(&3));
+    assert_eq!(Some(&4), obj.get(&4));
+
+    // ["LRUCache","put","put","get",// "put","get","put","get","get","get"]
+    // [[2],[1,0],[2,2],[1]// ,[3,3],[2],[4,4],[1],[3],[4]]
+    let mut obj = LruCache::new(2);
+    // 1:0
+    obj.put(1, 0);
+    // 2:2 -> 1:0
+    obj.put(2, 2);
+    // 1:0 -> 2:2
+    assert_eq!(Some(&0), obj.get(&1));
+    // 3:3 -> 1:0
+    obj.put(3, 3);
+    assert_eq!(None, obj.get(&2));
+    // 4:4 -> 3:3
+    obj.put(4, 4);
+    assert_eq!(None, obj.get(&1));
+    assert_eq!(Some(&3), obj.get(&3));
+    assert_eq!(Some(&4), obj.get(&4));
+
+    // ["LRUCache","put","get","put","get","get"]
+    // [[1],[2,1],[2],[3,2],[2],[3]]
+    let mut obj = LruCache::new(1);
+    obj.put(2, 1);
+    assert_eq!(Some(&1), obj.get(&2));
+    obj.put(3, 2);
+    assert_eq!(None, obj.get(&2));
+    assert_eq!(Some(&2), obj.get(&3));
+
+    // ["LRUCache","put","put","get","get","put","get","get","get"]
+    // [[2],[2,1],[3,2],[3],[2],[4,3],[2],[3],[4]]
+    // 头节点被使用, rehead的情况
+    let mut obj = LruCache::new(2);
+    // 2:1
+    obj.put(2, 1);
+    assert_eq!(Some(&1), obj.get(&2));
+    // 3:2 -> 2:1
+    obj.put(3, 2);
+    assert_eq!(Some(&2), obj.get(&3));
+    // 2:1 -> 3:2
+    assert_eq!(Some(&1), obj.get(&2));
+    // 4:3 -> 2:1
+    obj.put(4, 3);
+    // 2:1 -> 4:3
+    assert_eq!(Some(&1), obj.get(&2));
+    assert_eq!(None, obj.get(&3));
+    // 4:3 -> 2:1
+    assert_eq!(Some(&3), obj.get(&4));
+}
+
+#[test]
+fn pop() {
+    let mut obj = LruCache::new(3);
+    obj.put(1, 1);
+    obj.put(2, 2);
+    obj.put(3, 3);
+    // 3 -> 2 -> 1
+    assert_eq!(Some((&1, &1)), obj.peek_lru());
+    assert_eq!(Some((1, 1)), obj.pop_lru());
+    // 删链首
+    assert_eq!(Some(3), obj.pop(&3));
+    assert_eq!(None, obj.pop(&3));
+    assert_eq!(1, obj.len());
+    obj.put(4, 4);
+    obj.put(5, 5);
+    // 5 -> 4 -> 2
+    assert_eq!(Some(&2), obj.get(&2));
+    // 2 -> 5 -> 4
+    assert_eq!(Some((4, 4)), obj.pop_lru());
+    assert_eq!(Some((5, 5)), obj.pop_lru());
+    assert_eq!(Some((2, 2)), obj.pop_lru());
+    assert_eq!(None, obj.pop_lru());
+    assert!(obj.is_empty());
+    obj.put(6, 6);
+    assert_eq!(Some(&6), obj.get(&6));
+}
+
+#[test]
+fn borrowed_key() {
+    let mut obj: LruCache<String, u64> = LruCache::new(2);
+    obj.put("a".to_string(), 1);
+    obj.put("b".to_string(), 2);
+    // 用&str查
+    assert_eq!(Some(&1), obj.get("a"));
+    assert!(obj.contains("b"));
+    *obj.get_mut("b").unwrap() += 1;
+    assert_eq!(Some(&3), obj.peek("b"));
+    assert_eq!(Some(3), obj.pop("b"));
+    assert_eq!(None, obj.peek("b"));
+}
+
+#[test]
+fn peek_iter_resize() {
+    let mut obj = LruCache::new(4);
+    for i in 0..4 {
+        obj.put(i, i * 10);
+    }
+    // peek不改顺序, get_mut改
+    assert_eq!(Some(&0), obj.peek(&0));
+    *obj.get_mut(&1).unwrap() += 1;
+    // 覆盖不算淘汰
+    assert_eq!(None, obj.put(2, 21));
+    let order: Vec<_> = obj.iter().map(|(k, v)| (*k, *v)).collect();
+    assert_eq!(order, vec![(2, 21), (1, 11), (3, 30), (0, 0)]);
+    assert_eq!(obj.iter().rev().map(|(k, _)| *k).collect::<Vec<_>>(), vec![0, 3, 1, 2]);
+
+    // 缩容从链尾淘汰
+    obj.resize(2);
+    assert_eq!(obj.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![2, 1]);
+    assert_eq!(Some((1, 11)), obj.put(5, 50));
+    obj.resize(3);
+    assert_eq!(None, obj.put(6, 60));
+    assert_eq!(obj.len(), 3);
+    obj.clear();
+    assert!(obj.iter().next().is_none());
+}
+
+/// 和一个O(n)的Vec版本对比
+#[test]
+fn random() {
+    let mut obj = LruCache::new(16);
+    // 链首在最后
+    let mut expect: Vec<(u64, u64)> = Vec::new();
+    for _ in 0..10000 {
+        let k = rand::random::<u64>() % 32;
+        match rand::random::<u64>() % 4 {
+            0 => {
+                let got = obj.get(&k).copied();
+                let pos = expect.iter().position(|e| e.0 == k);
+                assert_eq!(got, pos.map(|p| expect[p].1));
+                if let Some(p) = pos {
+                    let e = expect.remove(p);
+                    expect.push(e);
+                }
+            }
+            1 => {
+                let pos = expect.iter().position(|e| e.0 == k);
+                assert_eq!(obj.pop(&k), pos.map(|p| expect.remove(p).1));
+            }
+            _ => {
+                let v = rand::random::<u64>();
+                let evicted = match expect.iter().position(|e| e.0 == k) {
+                    Some(p) => {
+                        expect.remove(p);
+                        None
+                    }
+                    None if expect.len() == 16 => Some(expect.remove(0)),
+                    None => None,
+                };
+                expect.push((k, v));
+                assert_eq!(obj.put(k, v), evicted);
+            }
+        }
+        assert_eq!(obj.len(), expect.len());
+    }
+    let order: Vec<_> = obj.iter().map(|(k, v)| (*k, *v)).collect();
+    assert_eq!(order, expect.into_iter().rev().collect::<Vec<_>>());
+}
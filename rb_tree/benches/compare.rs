@@ -0,0 +1,101 @@
+//! BST, AVL, RB对比: 写多(随机插入再删掉)和读多(建好树后反复查)
+//!  有序输入下不平衡的bst是链表, 只在随机输入上比
+//!  cargo bench --bench compare
+
+use std::collections::BTreeSet;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use avl_tree::AVLTree;
+use bst::{BinarySearchTree, Treap};
+use rb_tree::RBTree;
+
+type K = u64;
+const SET_SIZE: usize = 1 << 12;
+/// 读多: 每轮查询次数
+const LOOKUPS: usize = 1 << 14;
+
+/// 固定种子打乱, 每次跑都一样
+fn create_workload() -> Vec<K> {
+    let mut rng = StdRng::seed_from_u64(0x2545F4914F6CDD1D);
+    (0..SET_SIZE).map(|_| rng.gen()).collect()
+}
+
+fn insert_bst(workload: &[K]) {
+    let mut t = BinarySearchTree::new();
+    for k in workload {
+        t.insert(*k);
+    }
+    for k in workload {
+        t.remove(k);
+    }
+}
+fn insert_treap(workload: &[K]) {
+    let mut t = BinarySearchTree::<_, Treap>::with_balance();
+    for k in workload {
+        t.insert(*k);
+    }
+    for k in workload {
+        t.remove(k);
+    }
+}
+fn insert_avl(workload: &[K]) {
+    let mut t = AVLTree::new();
+    for k in workload {
+        t.insert(*k);
+    }
+    for k in workload {
+        t.remove(k);
+    }
+}
+fn insert_rb(workload: &[K]) {
+    let mut t = RBTree::new();
+    for k in workload {
+        t.insert(*k);
+    }
+    for k in workload {
+        t.remove(k);
+    }
+}
+fn insert_std(workload: &[K]) {
+    let mut t = BTreeSet::new();
+    for k in workload {
+        t.insert(*k);
+    }
+    for k in workload {
+        t.remove(k);
+    }
+}
+
+/// 查询一半命中一半不命中
+fn lookup(workload: &[K], contains: impl Fn(&K) -> bool) -> usize {
+    workload.iter().cycle().take(LOOKUPS).enumerate().filter(|(i, k)| contains(&(*k + (*i as K & 1)))).count()
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let workload = create_workload();
+    c.bench_function("insert: bst", |b| b.iter(|| insert_bst(black_box(&workload))));
+    c.bench_function("insert: bst treap", |b| b.iter(|| insert_treap(black_box(&workload))));
+    c.bench_function("insert: avl", |b| b.iter(|| insert_avl(black_box(&workload))));
+    c.bench_function("insert: rb", |b| b.iter(|| insert_rb(black_box(&workload))));
+    c.bench_function("insert: std", |b| b.iter(|| insert_std(black_box(&workload))));
+
+    let mut bst = BinarySearchTree::new();
+    let mut treap = BinarySearchTree::<_, Treap>::with_balance();
+    for k in &workload {
+        bst.insert(*k);
+        treap.insert(*k);
+    }
+    let avl: AVLTree<_> = workload.iter().copied().collect();
+    let rb: RBTree<_> = workload.iter().copied().collect();
+    let std: BTreeSet<_> = workload.iter().copied().collect();
+    c.bench_function("lookup: bst", |b| b.iter(|| lookup(black_box(&workload), |k| bst.search(k))));
+    c.bench_function("lookup: bst treap", |b| b.iter(|| lookup(black_box(&workload), |k| treap.search(k))));
+    c.bench_function("lookup: avl", |b| b.iter(|| lookup(black_box(&workload), |k| avl.contains(k))));
+    c.bench_function("lookup: rb", |b| b.iter(|| lookup(black_box(&workload), |k| rb.contains(k))));
+    c.bench_function("lookup: std", |b| b.iter(|| lookup(black_box(&workload), |k| std.contains(k))));
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,3 @@
+pub mod rb_tree;
+
+pub use crate::rb_tree::*;
@@ -0,0 +1,550 @@
+use std::cmp::Ordering;
+use std::mem;
+use std::ops::Not;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Color {
+    Red,
+    Black,
+}
+
+impl Not for Color {
+    type Output = Color;
+
+    fn not(self) -> Self::Output {
+        match self {
+            Color::Red => Color::Black,
+            Color::Black => Color::Red,
+        }
+    }
+}
+
+/// 节点的颜色就是指向它的链接的颜色
+struct RBNode<K: Ord, V> {
+    key: K,
+    value: V,
+    left: Option<Box<RBNode<K, V>>>,
+    right: Option<Box<RBNode<K, V>>>,
+    color: Color,
+}
+
+/// 有序kv, 左倾红黑树(LLRB)
+///  红链接只能是左链接, 一个节点不能连着两条红链接, 相当于2-3树: 红链接把两个节点粘成一个3-节点
+///  所以从根到每个空链接经过的黑链接数相同, 高度不超过2log n
+pub struct RBMap<K: Ord, V> {
+    root: Option<Box<RBNode<K, V>>>,
+    len: usize,
+}
+
+/// 有序集合: value为()的RBMap, 方法名和`AVLTree`一样
+pub struct RBSet<T: Ord> {
+    map: RBMap<T, ()>,
+}
+
+pub type RBTree<T> = RBSet<T>;
+
+fn is_red<K: Ord, V>(node: &Option<Box<RBNode<K, V>>>) -> bool {
+    node.as_ref().is_some_and(|n| n.color == Color::Red)
+}
+
+impl<K: Ord, V> RBNode<K, V> {
+    /// 新节点总是红的: 先粘到下面的节点上, 再往上调整
+    fn new(key: K, value: V) -> Box<Self> {
+        Box::new(Self { key, value, left: None, right: None, color: Color::Red })
+    }
+
+    /// 右倾的红链接转成左倾
+    ///  和AVL一样交换的是Box指针
+    fn rotate_left(self: &mut Box<Self>) {
+        let mut x = self.right.take().unwrap();
+        self.right = x.left.take();
+        x.color = self.color;
+        self.color = Color::Red;
+        mem::swap(self, &mut x);
+        self.left = Some(x);
+    }
+
+    fn rotate_right(self: &mut Box<Self>) {
+        let mut x = self.left.take().unwrap();
+        self.left = x.right.take();
+        x.color = self.color;
+        self.color = Color::Red;
+        mem::swap(self, &mut x);
+        self.right = Some(x);
+    }
+
+    /// 自己和两个孩子都变色
+    ///  插入时: 两个孩子都红, 相当于拆开4-节点, 中间的key送给父节点
+    ///  删除时: 反过来, 和两个孩子合成4-节点
+    fn flip_colors(&mut self) {
+        self.color = !self.color;
+        for child in [&mut self.left, &mut self.right].into_iter().flatten() {
+            child.color = !child.color;
+        }
+    }
+
+    /// ⭐回溯时的调整, 插入和删除共用
+    ///  1. 右红左黑: 左旋
+    ///  2. 左红, 左左也红: 右旋, 变成两个孩子都红
+    ///  3. 两个孩子都红: 变色
+    fn fix_up(self: &mut Box<Self>) {
+        if is_red(&self.right) && !is_red(&self.left) {
+            self.rotate_left();
+        }
+        if is_red(&self.left) && is_red(&self.left.as_ref().unwrap().left) {
+            self.rotate_right();
+        }
+        if is_red(&self.left) && is_red(&self.right) {
+            self.flip_colors();
+        }
+    }
+
+    /// 往左走之前保证左孩子或左左孩子是红的, 删除时不会删掉一个2-节点
+    ///  右孩子是3-节点时借一个过来, 否则和右孩子合并
+    fn move_red_left(self: &mut Box<Self>) {
+        self.flip_colors();
+        let right = self.right.as_mut().unwrap();
+        if is_red(&right.left) {
+            right.rotate_right();
+            self.rotate_left();
+            self.flip_colors();
+        }
+    }
+
+    /// 同move_red_left, 往右走
+    fn move_red_right(self: &mut Box<Self>) {
+        self.flip_colors();
+        if is_red(&self.left.as_ref().unwrap().left) {
+            self.rotate_right();
+            self.flip_colors();
+        }
+    }
+}
+
+impl<K: Ord, V> RBMap<K, V> {
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    /// key已存在时覆盖value, 返回旧value
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let old = Self::tree_insert(&mut self.root, RBNode::new(key, value));
+        if old.is_none() {
+            self.len += 1;
+        }
+        // 根拆分出来的红链接没有父节点可以粘, 直接变黑, 黑高加一
+        self.root.as_mut().unwrap().color = Color::Black;
+        old
+    }
+
+    fn tree_insert(tree: &mut Option<Box<RBNode<K, V>>>, new: Box<RBNode<K, V>>) -> Option<V> {
+        let Some(node) = tree else {
+            *tree = Some(new);
+            return None;
+        };
+        let old = match new.key.cmp(&node.key) {
+            Ordering::Less => Self::tree_insert(&mut node.left, new),
+            Ordering::Equal => return Some(mem::replace(&mut node.value, new.value)),
+            Ordering::Greater => Self::tree_insert(&mut node.right, new),
+        };
+        node.fix_up();
+        old
+    }
+
+    // 二叉搜索, 找相等
+    fn find(&self, key: &K) -> Option<&RBNode<K, V>> {
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            tree = match key.cmp(&node.key) {
+                Ordering::Less => &node.left,
+                Ordering::Greater => &node.right,
+                Ordering::Equal => return Some(node),
+            }
+        }
+        None
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find(key).map(|n| &n.value)
+    }
+
+    /// 只改value, 不改形状
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut tree = &mut self.root;
+        while let Some(node) = tree {
+            tree = match key.cmp(&node.key) {
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+                Ordering::Equal => return Some(&mut node.value),
+            }
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// 返回被删除的value
+    ///  往下走时一路保证当前节点不是2-节点, 删到底时直接摘掉, 回溯时再fix_up
+    ///  这样要求key一定存在, 所以先查一次
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if !self.contains_key(key) {
+            return None;
+        }
+        let root = self.root.as_mut().unwrap();
+        if !is_red(&root.left) && !is_red(&root.right) {
+            root.color = Color::Red;
+        }
+        let value = Self::tree_remove(&mut self.root, key);
+        if let Some(root) = &mut self.root {
+            root.color = Color::Black;
+        }
+        self.len -= 1;
+        Some(value)
+    }
+
+    fn tree_remove(tree: &mut Option<Box<RBNode<K, V>>>, key: &K) -> V {
+        let node = tree.as_mut().unwrap();
+        if key < &node.key {
+            if !is_red(&node.left) && !is_red(&node.left.as_ref().unwrap().left) {
+                node.move_red_left();
+            }
+            let value = Self::tree_remove(&mut node.left, key);
+            node.fix_up();
+            return value;
+        }
+
+        // 左倾的红链接转到右边, 才能往右删
+        if is_red(&node.left) {
+            node.rotate_right();
+        }
+        // 到底了: 没有右孩子就一定没有左孩子
+        if key == &node.key && node.right.is_none() {
+            return tree.take().unwrap().value;
+        }
+        if !is_red(&node.right) && !is_red(&node.right.as_ref().unwrap().left) {
+            node.move_red_right();
+        }
+        let value = if key == &node.key {
+            // 和AVL一样用后继顶替自己
+            let min = Self::take_min(&mut node.right);
+            node.key = min.key;
+            mem::replace(&mut node.value, min.value)
+        } else {
+            Self::tree_remove(&mut node.right, key)
+        };
+        node.fix_up();
+        value
+    }
+
+    /// 摘掉最小节点, 同样往下走时保证不是2-节点
+    fn take_min(tree: &mut Option<Box<RBNode<K, V>>>) -> Box<RBNode<K, V>> {
+        let node = tree.as_mut().unwrap();
+        if node.left.is_none() {
+            return tree.take().unwrap();
+        }
+        if !is_red(&node.left) && !is_red(&node.left.as_ref().unwrap().left) {
+            node.move_red_left();
+        }
+        let min = Self::take_min(&mut node.left);
+        node.fix_up();
+        min
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_ref()?;
+        while let Some(left) = &node.left {
+            node = left;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    pub fn last(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_ref()?;
+        while let Some(right) = &node.right {
+            node = right;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut iter = Iter { stack: Vec::new(), len: self.len };
+        iter.push_left(&self.root);
+        iter
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { iter: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { iter: self.iter() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 黑高, 空树为0
+    pub fn black_height(&self) -> usize {
+        let mut height = 0;
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            if node.color == Color::Black {
+                height += 1;
+            }
+            tree = &node.left;
+        }
+        height
+    }
+
+    /// 检查结构, 出错时返回描述
+    ///  1. 根是黑的
+    ///  2. 没有右倾的红链接, 没有连续的红链接
+    ///  3. 每条路径的黑链接数相同
+    ///  4. 中序有序, 节点数等于len
+    pub fn check_invariants(&self) -> Result<(), String> {
+        if is_red(&self.root) {
+            return Err("red root".to_string());
+        }
+        let black = self.black_height();
+        let len = Self::check_node(&self.root, black)?;
+        if len != self.len {
+            return Err(format!("len {} != {} nodes", self.len, len));
+        }
+        if self.iter().zip(self.iter().skip(1)).any(|((a, _), (b, _))| a >= b) {
+            return Err("keys out of order".to_string());
+        }
+        Ok(())
+    }
+
+    /// 返回子树节点数
+    fn check_node(tree: &Option<Box<RBNode<K, V>>>, black: usize) -> Result<usize, String> {
+        let Some(node) = tree else {
+            return if black == 0 { Ok(0) } else { Err("black height mismatch".to_string()) };
+        };
+        if is_red(&node.right) {
+            return Err("right-leaning red link".to_string());
+        }
+        if node.color == Color::Red && is_red(&node.left) {
+            return Err("two red links in a row".to_string());
+        }
+        let black = match node.color {
+            Color::Black => black.checked_sub(1).ok_or("black height mismatch")?,
+            Color::Red => black,
+        };
+        Ok(1 + Self::check_node(&node.left, black)? + Self::check_node(&node.right, black)?)
+    }
+}
+
+impl<T: Ord> RBSet<T> {
+    pub fn new() -> Self {
+        Self { map: RBMap::new() }
+    }
+
+    /// 已存在时返回false, 不替换原来的元素
+    pub fn insert(&mut self, value: T) -> bool {
+        if self.map.contains_key(&value) {
+            return false;
+        }
+        self.map.insert(value, ());
+        true
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.map.contains_key(value)
+    }
+
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.map.remove(value).is_some()
+    }
+
+    pub fn iter(&self) -> Keys<'_, T, ()> {
+        self.map.keys()
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.map.first().map(|(k, _)| k)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.map.last().map(|(k, _)| k)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn black_height(&self) -> usize {
+        self.map.black_height()
+    }
+
+    pub fn check_invariants(&self) -> Result<(), String> {
+        self.map.check_invariants()
+    }
+}
+
+impl<K: Ord, V> Default for RBMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> Default for RBSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for RBMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = RBMap::new();
+        for (key, value) in iter {
+            tree.insert(key, value);
+        }
+        tree
+    }
+}
+
+impl<T: Ord> FromIterator<T> for RBSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = RBSet::new();
+        for value in iter {
+            tree.insert(value);
+        }
+        tree
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a RBMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T: Ord> IntoIterator for &'a RBSet<T> {
+    type Item = &'a T;
+    type IntoIter = Keys<'a, T, ()>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// 中序遍历, 栈里是还没访问的左链
+pub struct Iter<'a, K: Ord, V> {
+    stack: Vec<&'a RBNode<K, V>>,
+    len: usize,
+}
+
+impl<'a, K: Ord, V> Iter<'a, K, V> {
+    fn push_left(&mut self, mut tree: &'a Option<Box<RBNode<K, V>>>) {
+        while let Some(node) = tree {
+            self.stack.push(node);
+            tree = &node.left;
+        }
+    }
+}
+
+impl<'a, K: Ord, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.len -= 1;
+        self.push_left(&node.right);
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+pub struct Keys<'a, K: Ord, V> {
+    iter: Iter<'a, K, V>,
+}
+
+impl<'a, K: Ord, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.iter.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+pub struct Values<'a, K: Ord, V> {
+    iter: Iter<'a, K, V>,
+}
+
+impl<'a, K: Ord, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.iter.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for Values<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::{RBMap, RBTree};
+
+    #[test]
+    fn map() {
+        let mut map: RBMap<_, _> = (0..8).map(|i| (i, i.to_string())).collect();
+        assert_eq!(map.insert(3, "three".to_string()), Some("3".to_string()));
+        assert_eq!(map.get(&3).map(String::as_str), Some("three"));
+        map.get_mut(&4).unwrap().push('!');
+        assert_eq!(map.remove(&4).as_deref(), Some("4!"));
+        assert_eq!(map.remove(&4), None);
+        assert_eq!(map.len(), 7);
+        assert_eq!(map.first(), Some((&0, &"0".to_string())));
+        assert_eq!(map.last(), Some((&7, &"7".to_string())));
+        assert!(map.keys().copied().eq([0, 1, 2, 3, 5, 6, 7]));
+        map.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn black_height() {
+        // 有序插入也不会退化
+        let mut tree = RBTree::new();
+        for i in 0..(1 << 12) {
+            tree.insert(i);
+            tree.check_invariants().unwrap();
+        }
+        // 黑高 <= log n, 高度 <= 2 * 黑高
+        assert!(tree.black_height() <= 12);
+        for i in 0..(1 << 12) {
+            assert!(tree.remove(&i));
+            tree.check_invariants().unwrap();
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.black_height(), 0);
+    }
+}
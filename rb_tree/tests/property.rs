@@ -0,0 +1,167 @@
+//! 同一组性质, 跑在BST, AVL和RB上
+//!  每棵树实现一下`OrderedSet`, 再用`property_tests!`展开成测试
+
+use std::collections::BTreeSet;
+
+use avl_tree::AVLTree;
+use bst::{BinarySearchTree, Treap};
+use rb_tree::RBTree;
+
+const SET_SIZE: u64 = 1 << 12;
+/// 随机增删时每隔几步检查一次结构
+const CHECK_EVERY: u64 = 64;
+
+trait OrderedSet: Default {
+    /// 已存在时返回false
+    fn insert(&mut self, value: u64) -> bool;
+    fn remove(&mut self, value: &u64) -> bool;
+    fn contains(&self, value: &u64) -> bool;
+    fn len(&self) -> usize;
+    fn to_vec(&self) -> Vec<u64>;
+    /// 检查自己的结构
+    fn check(&self);
+}
+
+impl OrderedSet for RBTree<u64> {
+    fn insert(&mut self, value: u64) -> bool {
+        RBTree::insert(self, value)
+    }
+    fn remove(&mut self, value: &u64) -> bool {
+        RBTree::remove(self, value)
+    }
+    fn contains(&self, value: &u64) -> bool {
+        RBTree::contains(self, value)
+    }
+    fn len(&self) -> usize {
+        RBTree::len(self)
+    }
+    fn to_vec(&self) -> Vec<u64> {
+        self.iter().copied().collect()
+    }
+    fn check(&self) {
+        self.check_invariants().unwrap();
+    }
+}
+
+impl OrderedSet for AVLTree<u64> {
+    fn insert(&mut self, value: u64) -> bool {
+        AVLTree::insert(self, value)
+    }
+    fn remove(&mut self, value: &u64) -> bool {
+        AVLTree::remove(self, value)
+    }
+    fn contains(&self, value: &u64) -> bool {
+        AVLTree::contains(self, value)
+    }
+    fn len(&self) -> usize {
+        AVLTree::len(self)
+    }
+    fn to_vec(&self) -> Vec<u64> {
+        self.iter().copied().collect()
+    }
+    fn check(&self) {
+        self.check_invariants().unwrap();
+    }
+}
+
+/// bst可以重复插入, 这里先查一次当集合用
+macro_rules! impl_bst {
+    ($ty:ty) => {
+        impl OrderedSet for $ty {
+            fn insert(&mut self, value: u64) -> bool {
+                if self.search(&value) {
+                    return false;
+                }
+                BinarySearchTree::insert(self, value);
+                true
+            }
+            fn remove(&mut self, value: &u64) -> bool {
+                BinarySearchTree::remove(self, value).is_some()
+            }
+            fn contains(&self, value: &u64) -> bool {
+                self.search(value)
+            }
+            fn len(&self) -> usize {
+                BinarySearchTree::len(self)
+            }
+            fn to_vec(&self) -> Vec<u64> {
+                self.iter().copied().collect()
+            }
+            fn check(&self) {
+                self.check_invariants().unwrap();
+            }
+        }
+    };
+}
+
+impl_bst!(BinarySearchTree<u64>);
+impl_bst!(BinarySearchTree<u64, Treap>);
+
+/// 随机增删, 每一步的返回值都和BTreeSet一样, 每CHECK_EVERY步和最后检查一次结构
+fn random_ops<S: OrderedSet>() {
+    let mut set = S::default();
+    let mut expect = BTreeSet::new();
+    for i in 0..SET_SIZE * 4 {
+        let v = rand::random::<u64>() % SET_SIZE;
+        if rand::random::<u64>().is_multiple_of(3) {
+            assert_eq!(set.remove(&v), expect.remove(&v));
+        } else {
+            assert_eq!(set.insert(v), expect.insert(v));
+        }
+        assert_eq!(set.len(), expect.len());
+        // 检查是O(n)的, 每步都查太慢了
+        if i % CHECK_EVERY == 0 {
+            set.check();
+        }
+    }
+    set.check();
+    assert_eq!(set.to_vec(), expect.iter().copied().collect::<Vec<_>>());
+    for v in 0..SET_SIZE {
+        assert_eq!(set.contains(&v), expect.contains(&v));
+    }
+}
+
+/// 顺序和逆序插入, 再按另一个顺序删空
+fn sorted_ops<S: OrderedSet>() {
+    // 不平衡的bst在有序输入下是链表, 递归会很深, 规模小一点
+    let n = SET_SIZE / 4;
+    for order in [(0..n).collect::<Vec<_>>(), (0..n).rev().collect()] {
+        let mut set = S::default();
+        for &v in &order {
+            assert!(set.insert(v));
+            assert!(!set.insert(v));
+            set.check();
+        }
+        assert_eq!(set.to_vec(), (0..n).collect::<Vec<_>>());
+        for v in (0..n).filter(|v| v % 2 == 0).chain((0..n).filter(|v| v % 2 == 1)) {
+            assert!(set.remove(&v));
+            assert!(!set.contains(&v));
+            set.check();
+        }
+        assert_eq!(set.len(), 0);
+        assert!(set.to_vec().is_empty());
+    }
+}
+
+macro_rules! property_tests {
+    ($name:ident, $ty:ty) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn random() {
+                random_ops::<$ty>();
+            }
+
+            #[test]
+            fn sorted() {
+                sorted_ops::<$ty>();
+            }
+        }
+    };
+}
+
+property_tests!(rb, RBTree<u64>);
+property_tests!(avl, AVLTree<u64>);
+property_tests!(bst_unbalanced, BinarySearchTree<u64>);
+property_tests!(bst_treap, BinarySearchTree<u64, Treap>);